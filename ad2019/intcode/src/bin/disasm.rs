@@ -0,0 +1,13 @@
+//! Print an annotated listing of the reachable code in an Intcode program.
+//!
+//! Usage: disasm PROGRAM_FILE
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Some(filename) = std::env::args().nth(1) else {
+        eprintln!("usage: disasm PROGRAM_FILE");
+        std::process::exit(1);
+    };
+    let program = intcode::parse(&std::fs::read_to_string(filename)?)?;
+    print!("{}", intcode::analyze(&program));
+    Ok(())
+}
@@ -0,0 +1,1157 @@
+//! A disassembler that only disassembles code the program can actually reach.
+//!
+//! Starting at address 0, we follow control flow using an abstract machine
+//! state and iterate to a fixed point. The state tracks what is known about
+//! the stack, relative to the current relative base, which is enough to
+//! follow the usual calling convention:
+//!
+//! ```text
+//!     add 702, 0 -> rel[0]      ; push return address
+//!     jump-if-true 1, 786       ; call
+//!     ...
+//! 786 arb 7                     ; allocate frame
+//!     ...
+//!     arb -7                    ; pop frame
+//!     jump-if-true 1, rel[0]    ; return
+//! ```
+//!
+//! The analysis keeps one state per (address, return address of the current
+//! function), and when a function returns, stack cells it didn't touch are
+//! restored from the caller's state at the call.
+//!
+//! Program image cells that some reachable instruction writes are tracked
+//! too, which covers the usual way to do an indirect load or store: patching
+//! the operand of the next instruction. Cells written through a pointer that
+//! walks over a range of addresses are just treated as unknown. Writes we
+//! can't model, like overwriting an opcode, are reported as [`Issue`]s, and
+//! the listing should not be trusted past them.
+//!
+//! Two assumptions: when the absolute relative base is unknown (for example,
+//! in a recursive function), relative-mode writes don't land in the program
+//! image; and position-mode writes land on the stack only if we can tell
+//! exactly where.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use crate::Word;
+
+/// How many distinct values we track for a cell before giving up on it.
+const MAX_VALUES: usize = 64;
+
+/// How many times a state may grow before we stop tracking the parts that
+/// keep changing. Without this, a loop counter would take `MAX_VALUES` trips
+/// around the loop to settle.
+const WIDEN_AFTER: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Opcode {
+    Add,
+    Mul,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    Lt,
+    Eq,
+    Arb,
+    Halt,
+}
+
+impl Opcode {
+    fn from_word(op: Word) -> Option<Opcode> {
+        Some(match op {
+            1 => Opcode::Add,
+            2 => Opcode::Mul,
+            3 => Opcode::Input,
+            4 => Opcode::Output,
+            5 => Opcode::JumpIfTrue,
+            6 => Opcode::JumpIfFalse,
+            7 => Opcode::Lt,
+            8 => Opcode::Eq,
+            9 => Opcode::Arb,
+            99 => Opcode::Halt,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::Add => "add",
+            Opcode::Mul => "mul",
+            Opcode::Input => "input",
+            Opcode::Output => "output",
+            Opcode::JumpIfTrue => "jump-if-true",
+            Opcode::JumpIfFalse => "jump-if-false",
+            Opcode::Lt => "lt",
+            Opcode::Eq => "eq",
+            Opcode::Arb => "arb",
+            Opcode::Halt => "halt",
+        }
+    }
+
+    /// Number of (input, output) operands.
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => (2, 1),
+            Opcode::Input => (0, 1),
+            Opcode::Output | Opcode::Arb => (1, 0),
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => (2, 0),
+            Opcode::Halt => (0, 0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operand {
+    Position(Word),
+    Immediate(Word),
+    Relative(Word),
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Position(n) => write!(f, "[{n}]"),
+            Operand::Immediate(n) => write!(f, "{n}"),
+            Operand::Relative(n) => write!(f, "rel[{n}]"),
+        }
+    }
+}
+
+/// A decoded instruction. The last operand of add/mul/lt/eq/input is the destination.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Instruction {
+    pub addr: usize,
+    pub opcode: Opcode,
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    /// Number of memory cells the instruction occupies.
+    pub fn size(&self) -> usize {
+        1 + self.operands.len()
+    }
+
+    fn has_dest(&self) -> bool {
+        self.opcode.arity().1 == 1
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:6}  {}", self.addr, self.opcode.name())?;
+        for (i, op) in self.operands.iter().enumerate() {
+            if self.has_dest() && i + 1 == self.operands.len() {
+                write!(f, " -> {op}")?;
+            } else if i == 0 {
+                write!(f, " {op}")?;
+            } else {
+                write!(f, ", {op}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode the instruction at `addr`. Returns `None` if it isn't a valid instruction.
+pub fn decode(program: &[Word], addr: usize) -> Option<Instruction> {
+    let insn = *program.get(addr)?;
+    if insn < 0 {
+        return None;
+    }
+    let opcode = Opcode::from_word(insn % 100)?;
+    let (n_in, n_out) = opcode.arity();
+    let mut modes = insn / 100;
+    let mut operands = vec![];
+    for i in 0..n_in + n_out {
+        let raw = program.get(addr + 1 + i).copied().unwrap_or(0);
+        operands.push(match (modes % 10, i < n_in) {
+            (0, _) => Operand::Position(raw),
+            (1, true) => Operand::Immediate(raw),
+            (2, _) => Operand::Relative(raw),
+            _ => return None,
+        });
+        modes /= 10;
+    }
+    if modes != 0 {
+        return None;
+    }
+    Some(Instruction {
+        addr,
+        opcode,
+        operands,
+    })
+}
+
+/// Something the analysis couldn't model soundly.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum Issue {
+    /// Control reaches an address that doesn't hold a valid instruction.
+    BadInstruction { addr: usize },
+    /// The instruction at `writer` may overwrite the opcode of the
+    /// instruction at `target`.
+    SelfModifyingCode { writer: usize, target: usize },
+    /// The instruction at `addr` writes to an address we can't determine.
+    UnknownWrite { addr: usize },
+    /// The instruction at `addr` jumps to a target we can't determine.
+    UnresolvedJump { addr: usize },
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::BadInstruction { addr } => write!(f, "invalid instruction reached at {addr}"),
+            Issue::SelfModifyingCode { writer, target } => {
+                write!(f, "{writer} may overwrite the opcode at {target}")
+            }
+            Issue::UnknownWrite { addr } => write!(f, "{addr} writes to an unknown address"),
+            Issue::UnresolvedJump { addr } => write!(f, "{addr} jumps to an unknown address"),
+        }
+    }
+}
+
+/// Abstract value: a small set of possible values, a range, or anything at all.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Value {
+    OneOf(BTreeSet<Word>),
+    /// Any value in `lo..=hi`.
+    Range(Word, Word),
+    Unknown,
+}
+
+impl Value {
+    fn known(v: Word) -> Value {
+        Value::OneOf(BTreeSet::from([v]))
+    }
+
+    fn bounds(&self) -> Option<(Word, Word)> {
+        match self {
+            Value::OneOf(set) => Some((*set.first()?, *set.last()?)),
+            Value::Range(lo, hi) => Some((*lo, *hi)),
+            Value::Unknown => None,
+        }
+    }
+
+    fn join(&self, other: &Value) -> Value {
+        match (self, other) {
+            (Value::OneOf(a), Value::OneOf(b)) => Value::capped(a.union(b).copied().collect()),
+            _ => match (self.bounds(), other.bounds()) {
+                (Some((a, b)), Some((c, d))) => Value::Range(a.min(c), b.max(d)),
+                _ => Value::Unknown,
+            },
+        }
+    }
+
+    /// `self` joined with `next`, except that a bound that moved goes all
+    /// the way. A pointer stepping through an array settles right away.
+    fn widen(&self, next: &Value) -> Value {
+        let joined = self.join(next);
+        if joined == *self {
+            return joined;
+        }
+        match (self.bounds(), joined.bounds()) {
+            (Some((a, b)), Some((c, d))) => Value::Range(
+                if c < a { Word::MIN } else { c },
+                if d > b { Word::MAX } else { d },
+            ),
+            _ => Value::Unknown,
+        }
+    }
+
+    fn capped(set: BTreeSet<Word>) -> Value {
+        if set.len() > MAX_VALUES {
+            Value::Range(*set.first().unwrap(), *set.last().unwrap())
+        } else {
+            Value::OneOf(set)
+        }
+    }
+
+    fn combine(&self, other: &Value, f: impl Fn(Word, Word) -> Word) -> Value {
+        match (self, other) {
+            (Value::OneOf(a), Value::OneOf(b)) if a.len() * b.len() <= MAX_VALUES => Value::OneOf(
+                a.iter()
+                    .flat_map(|&x| b.iter().map(move |&y| (x, y)))
+                    .map(|(x, y)| f(x, y))
+                    .collect(),
+            ),
+            _ => Value::Unknown,
+        }
+    }
+
+    fn add(&self, other: &Value) -> Value {
+        match (self, other) {
+            (Value::OneOf(_), Value::OneOf(_)) => self.combine(other, Word::wrapping_add),
+            _ => match (self.bounds(), other.bounds()) {
+                // Treat the ends of the range as infinite.
+                (Some((a, b)), Some((c, d))) => {
+                    Value::Range(a.saturating_add(c), b.saturating_add(d))
+                }
+                _ => Value::Unknown,
+            },
+        }
+    }
+
+    /// Result of a comparison: 0 or 1, if we can't do better.
+    fn compare(&self, other: &Value, f: impl Fn(Word, Word) -> bool) -> Value {
+        match self.combine(other, |a, b| Word::from(f(a, b))) {
+            Value::OneOf(set) => Value::OneOf(set),
+            _ => Value::OneOf(BTreeSet::from([0, 1])),
+        }
+    }
+
+    /// Join `f(v)` over every possible value `v`.
+    fn flat_map(&self, f: impl Fn(Word) -> Value) -> Value {
+        match self {
+            Value::OneOf(set) => set
+                .iter()
+                .map(|&v| f(v))
+                .reduce(|a, b| a.join(&b))
+                .unwrap_or(Value::Unknown),
+            _ => Value::Unknown,
+        }
+    }
+
+    fn single(&self) -> Option<Word> {
+        match self {
+            Value::OneOf(set) if set.len() == 1 => set.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// (could be zero, could be nonzero)
+    fn truthiness(&self) -> (bool, bool) {
+        match self {
+            Value::OneOf(set) => (set.contains(&0), set.iter().any(|&v| v != 0)),
+            Value::Range(lo, hi) => (*lo <= 0 && 0 <= *hi, lo != hi || *lo != 0),
+            Value::Unknown => (true, true),
+        }
+    }
+}
+
+/// A set of stack offsets: exact near the relative base, coarse farther out.
+/// This keeps the analysis finite even when recursion keeps pushing frames.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+struct Touched {
+    /// Offsets in `-WINDOW..=WINDOW`.
+    near: BTreeSet<Word>,
+    /// If true, every offset below the window.
+    below: bool,
+    /// If true, every offset above the window.
+    above: bool,
+}
+
+const WINDOW: Word = 64;
+
+impl Touched {
+    fn everything() -> Touched {
+        Touched {
+            near: (-WINDOW..=WINDOW).collect(),
+            below: true,
+            above: true,
+        }
+    }
+
+    fn contains(&self, off: Word) -> bool {
+        if off < -WINDOW {
+            self.below
+        } else if off > WINDOW {
+            self.above
+        } else {
+            self.near.contains(&off)
+        }
+    }
+
+    fn insert(&mut self, off: Word) {
+        if off < -WINDOW {
+            self.below = true;
+        } else if off > WINDOW {
+            self.above = true;
+        } else {
+            self.near.insert(off);
+        }
+    }
+
+    fn union(&self, other: &Touched) -> Touched {
+        Touched {
+            near: self.near.union(&other.near).copied().collect(),
+            below: self.below || other.below,
+            above: self.above || other.above,
+        }
+    }
+
+    /// The same cells, after the relative base moves by `k`.
+    fn shift(&self, k: Word) -> Touched {
+        let mut out = Touched {
+            near: BTreeSet::new(),
+            below: self.below,
+            above: self.above,
+        };
+        for &off in &self.near {
+            out.insert(off - k);
+        }
+        // Cells from beyond the window may have moved into it.
+        if self.above && k > 0 {
+            out.near.extend((WINDOW + 1 - k).max(-WINDOW)..=WINDOW);
+        }
+        if self.below && k < 0 {
+            out.near.extend(-WINDOW..=(-WINDOW - 1 - k).min(WINDOW));
+        }
+        out
+    }
+}
+
+/// The return address of the function being analyzed, if known.
+type Context = Option<usize>;
+
+/// Abstract machine state at the start of an instruction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct State {
+    /// Known stack cells, keyed by offset from the current relative base.
+    /// Missing cells are unknown.
+    stack: BTreeMap<Word, Value>,
+    /// The absolute relative base. Even when it isn't known exactly, a lower
+    /// bound can show that stack stores miss the program image.
+    relative_base: Value,
+    /// How far the relative base has moved since entering the current
+    /// function, if known.
+    frame: Option<Word>,
+    /// Stack cells (keyed like `stack`) this function may have written
+    /// since it was entered.
+    written: Touched,
+    /// Known values of program image cells that get written somewhere.
+    /// Missing cells are unknown.
+    image: BTreeMap<usize, Value>,
+}
+
+/// Combine two maps of known cells with `f`. Cells missing from either are unknown.
+fn merge_cells<K: Ord + Copy>(
+    a: &BTreeMap<K, Value>,
+    b: &BTreeMap<K, Value>,
+    f: impl Fn(&Value, &Value) -> Value,
+) -> BTreeMap<K, Value> {
+    a.iter()
+        .filter_map(|(k, x)| match f(x, b.get(k)?) {
+            Value::Unknown => None,
+            v => Some((*k, v)),
+        })
+        .collect()
+}
+
+impl State {
+    fn join(&self, other: &State) -> State {
+        State {
+            stack: merge_cells(&self.stack, &other.stack, Value::join),
+            image: merge_cells(&self.image, &other.image, Value::join),
+            relative_base: self.relative_base.join(&other.relative_base),
+            frame: if self.frame == other.frame {
+                self.frame
+            } else {
+                None
+            },
+            written: self.written.union(&other.written),
+        }
+    }
+
+    /// `next` is `self` joined with something; widen whatever changed.
+    fn widen(&self, next: &State) -> State {
+        State {
+            stack: merge_cells(&self.stack, &next.stack, Value::widen),
+            image: merge_cells(&self.image, &next.image, Value::widen),
+            relative_base: self.relative_base.widen(&next.relative_base),
+            ..next.clone()
+        }
+    }
+
+    /// The state on entry to a function called from this state.
+    fn enter(&self) -> State {
+        State {
+            frame: Some(0),
+            written: Touched::default(),
+            ..self.clone()
+        }
+    }
+
+    /// The state after returning from a call: `self` is the caller's state
+    /// at the call, `callee` the state at the return jump. Cells the callee
+    /// didn't touch are as the caller left them.
+    fn after_return(&self, callee: &State) -> State {
+        if callee.frame != Some(0) {
+            return State {
+                frame: None,
+                written: Touched::everything(),
+                ..callee.clone()
+            };
+        }
+        let touched = &callee.written;
+        let mut stack: BTreeMap<Word, Value> = self
+            .stack
+            .iter()
+            .filter(|(k, _)| !touched.contains(**k))
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        for (k, v) in &callee.stack {
+            if touched.contains(*k) {
+                stack.insert(*k, v.clone());
+            }
+        }
+        State {
+            stack,
+            relative_base: if callee.relative_base.single().is_some() {
+                callee.relative_base.clone()
+            } else {
+                self.relative_base.clone()
+            },
+            frame: self.frame,
+            written: self.written.union(touched),
+            image: callee.image.clone(),
+        }
+    }
+
+    /// Store to a stack cell. A weak store might not happen, so the cell
+    /// might also keep its old value.
+    fn store(&mut self, off: Word, value: &Value, strong: bool) {
+        set_cell(&mut self.stack, off, value, strong);
+        self.written.insert(off);
+    }
+
+    /// Note a write that may have hit any stack cell.
+    fn clobber(&mut self) {
+        self.stack.clear();
+        self.written = Touched::everything();
+    }
+}
+
+fn set_cell<K: Ord>(cells: &mut BTreeMap<K, Value>, key: K, value: &Value, strong: bool) {
+    let v = if strong {
+        value.clone()
+    } else {
+        cells
+            .get(&key)
+            .map_or(Value::Unknown, |old| old.join(value))
+    };
+    match v {
+        Value::Unknown => cells.remove(&key),
+        v => cells.insert(key, v),
+    };
+}
+
+/// Result of analyzing a program.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub program: Vec<Word>,
+    /// Every reachable instruction, by address.
+    pub instructions: BTreeMap<usize, Instruction>,
+    /// For each jump instruction, the addresses it may jump to.
+    pub jump_targets: BTreeMap<usize, BTreeSet<usize>>,
+    /// For each memory cell in the program image that may be written, the
+    /// instructions that may write it.
+    pub writes: BTreeMap<usize, BTreeSet<usize>>,
+    pub issues: BTreeSet<Issue>,
+    /// Cells that may be hit by a store through a range of addresses.
+    volatile: BTreeSet<usize>,
+}
+
+/// Which program image cells may be written. The rest always hold their
+/// initial values.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+struct Memory {
+    written: BTreeSet<usize>,
+    /// Cells hit by stores through a range of addresses, like an array being
+    /// filled in. We don't track their values at all; there can be a lot of
+    /// them.
+    volatile: BTreeSet<usize>,
+}
+
+impl Memory {
+    /// Should the abstract state track this cell's value?
+    fn tracked(&self, a: usize) -> bool {
+        self.written.contains(&a) && !self.volatile.contains(&a)
+    }
+
+    /// The value of a cell whose initial value is `init`.
+    fn load(&self, state: &State, a: usize, init: Word) -> Value {
+        if self.volatile.contains(&a) {
+            Value::Unknown
+        } else if self.written.contains(&a) {
+            state.image.get(&a).cloned().unwrap_or(Value::Unknown)
+        } else {
+            Value::known(init)
+        }
+    }
+}
+
+/// After this many rounds, stop letting the set of writable cells shrink.
+const MAX_ROUNDS: usize = 5;
+
+/// Analyze `program`, starting at address 0.
+pub fn analyze(program: &[Word]) -> Analysis {
+    let mut memory = Memory::default();
+    let mut round = 0;
+    loop {
+        let analysis = analyze_with(program, &memory);
+        // Which cells get written affects what gets read, so repeat until
+        // that's stable too.
+        let mut next = Memory {
+            written: analysis.writes.keys().copied().collect(),
+            volatile: analysis.volatile.clone(),
+        };
+        round += 1;
+        if round > MAX_ROUNDS {
+            next.written.extend(&memory.written);
+            next.volatile.extend(&memory.volatile);
+        }
+        if next == memory {
+            return analysis;
+        }
+        memory = next;
+    }
+}
+
+fn analyze_with(program: &[Word], memory: &Memory) -> Analysis {
+    let mut analysis = Analysis {
+        program: program.to_vec(),
+        instructions: BTreeMap::new(),
+        jump_targets: BTreeMap::new(),
+        writes: BTreeMap::new(),
+        issues: BTreeSet::new(),
+        volatile: BTreeSet::new(),
+    };
+    // Analysis is context-sensitive to one level of calls: each state is
+    // keyed by an address and the return address of the function we're in.
+    let mut states = BTreeMap::<(usize, Context), State>::new();
+    // For each return address and caller context, the caller's state at the call.
+    let mut calls = BTreeMap::<usize, BTreeMap<Context, State>>::new();
+    let start = State {
+        stack: BTreeMap::new(),
+        relative_base: Value::known(0),
+        frame: Some(0),
+        written: Touched::default(),
+        image: memory
+            .written
+            .iter()
+            .filter(|&&a| memory.tracked(a))
+            .map(|&a| (a, Value::known(program[a])))
+            .collect(),
+    };
+    states.insert((0, None), start);
+    let mut todo = BTreeSet::from([(0usize, None)]);
+    let mut updates = BTreeMap::<(usize, Context), usize>::new();
+    // Instructions that may run after their opcode was overwritten.
+    let mut modified = BTreeSet::new();
+
+    while let Some((addr, ctx)) = todo.pop_first() {
+        let state = states[&(addr, ctx)].clone();
+        if let Some(&op) = program.get(addr) {
+            if memory.load(&state, addr, op) != Value::known(op) {
+                modified.insert(addr);
+            }
+        }
+        let Some(insn) = decode(program, addr) else {
+            analysis.issues.insert(Issue::BadInstruction { addr });
+            continue;
+        };
+        let next = addr + insn.size();
+        let is_jump = matches!(insn.opcode, Opcode::JumpIfTrue | Opcode::JumpIfFalse);
+        // A call pushes the address of the next instruction, then jumps.
+        let is_call = is_jump && state.stack.get(&0).and_then(Value::single) == Some(next as Word);
+        // A return jumps to an address it reads from memory.
+        let is_return = is_jump
+            && !is_call
+            && (memory.written.contains(&(addr + 2))
+                || !matches!(insn.operands[1], Operand::Immediate(_)));
+        for (target, next_state) in analysis.transfer(&insn, state, memory) {
+            let mut succ = vec![];
+            if is_call && target != next {
+                let sites = calls.entry(next).or_default();
+                let merged = match sites.get(&ctx) {
+                    None => next_state.clone(),
+                    Some(old) => old.join(&next_state),
+                };
+                if sites.get(&ctx) != Some(&merged) {
+                    sites.insert(ctx, merged);
+                    // Returns already analyzed now have somewhere new to go.
+                    todo.extend(states.keys().filter(|k| k.1 == Some(next)));
+                }
+                succ.push(((target, Some(next)), next_state.enter()));
+            } else if let Some(sites) = calls.get(&target).filter(|_| is_return && target != next) {
+                for (&c, caller) in sites {
+                    succ.push(((target, c), caller.after_return(&next_state)));
+                }
+            } else {
+                succ.push(((target, ctx), next_state));
+            }
+            for (key, next_state) in succ {
+                let merged = match states.get(&key) {
+                    None => next_state,
+                    Some(old) => {
+                        let merged = old.join(&next_state);
+                        if merged == *old {
+                            continue;
+                        }
+                        let count = updates.entry(key).or_insert(0);
+                        *count += 1;
+                        if *count > WIDEN_AFTER {
+                            old.widen(&merged)
+                        } else {
+                            merged
+                        }
+                    }
+                };
+                if states.get(&key) != Some(&merged) {
+                    states.insert(key, merged);
+                    todo.insert(key);
+                }
+            }
+        }
+        analysis.instructions.insert(addr, insn);
+    }
+
+    for target in modified {
+        for &writer in analysis.writes.get(&target).into_iter().flatten() {
+            analysis
+                .issues
+                .insert(Issue::SelfModifyingCode { writer, target });
+        }
+    }
+    analysis
+}
+
+impl Analysis {
+    /// Abstractly execute one instruction. Returns successor addresses with their states.
+    fn transfer(
+        &mut self,
+        insn: &Instruction,
+        mut state: State,
+        memory: &Memory,
+    ) -> Vec<(usize, State)> {
+        let program = &self.program;
+        let addr = insn.addr;
+        // Read the cell at absolute address `abs` (-1 if unknown), which is
+        // `rel` relative to the relative base.
+        let load = |state: &State, abs: Word, rel: Option<Word>| -> Value {
+            if let Some(v) = rel.and_then(|rel| state.stack.get(&rel)) {
+                return v.clone();
+            }
+            match usize::try_from(abs) {
+                Ok(a) if a < program.len() => memory.load(state, a, program[a]),
+                _ => Value::Unknown,
+            }
+        };
+        let read = |state: &State, i: usize| -> Value {
+            let raw = operand_value(state, memory, insn, i);
+            match insn.operands[i] {
+                Operand::Immediate(_) => raw,
+                Operand::Position(_) => {
+                    raw.flat_map(|n| load(state, n, state.relative_base.single().map(|rb| n - rb)))
+                }
+                Operand::Relative(_) => raw.flat_map(|n| {
+                    load(
+                        state,
+                        state.relative_base.single().map_or(-1, |rb| rb + n),
+                        Some(n),
+                    )
+                }),
+            }
+        };
+
+        let next = addr + insn.size();
+        match insn.opcode {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq | Opcode::Input => {
+                let value = match insn.opcode {
+                    Opcode::Add => read(&state, 0).add(&read(&state, 1)),
+                    Opcode::Mul => read(&state, 0).combine(&read(&state, 1), Word::wrapping_mul),
+                    Opcode::Lt => read(&state, 0).compare(&read(&state, 1), |a, b| a < b),
+                    Opcode::Eq => read(&state, 0).compare(&read(&state, 1), |a, b| a == b),
+                    _ => Value::Unknown,
+                };
+                let dest = insn.operands.len() - 1;
+                self.write(&mut state, insn, dest, &value, memory);
+                vec![(next, state)]
+            }
+            Opcode::Output => vec![(next, state)],
+            Opcode::Arb => {
+                let k = read(&state, 0);
+                state.relative_base = state.relative_base.add(&k);
+                match k.single() {
+                    Some(k) => {
+                        state.stack = state
+                            .stack
+                            .into_iter()
+                            .map(|(off, v)| (off - k, v))
+                            .collect();
+                        state.frame = state.frame.map(|f| f + k);
+                        state.written = state.written.shift(k);
+                    }
+                    None => {
+                        state.stack.clear();
+                        state.frame = None;
+                        state.written = Touched::everything();
+                    }
+                }
+                vec![(next, state)]
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let (maybe_zero, maybe_nonzero) = read(&state, 0).truthiness();
+                let (may_jump, may_fall) = if insn.opcode == Opcode::JumpIfTrue {
+                    (maybe_nonzero, maybe_zero)
+                } else {
+                    (maybe_zero, maybe_nonzero)
+                };
+                let mut out = vec![];
+                if may_fall {
+                    out.push((next, state.clone()));
+                }
+                if may_jump {
+                    match read(&state, 1) {
+                        Value::OneOf(targets) => {
+                            for t in targets {
+                                match usize::try_from(t) {
+                                    Ok(t) => {
+                                        self.jump_targets.entry(addr).or_default().insert(t);
+                                        out.push((t, state.clone()));
+                                    }
+                                    Err(_) => {
+                                        self.issues.insert(Issue::BadInstruction { addr });
+                                    }
+                                }
+                            }
+                        }
+                        _ => {
+                            self.issues.insert(Issue::UnresolvedJump { addr });
+                        }
+                    }
+                }
+                out
+            }
+            Opcode::Halt => vec![],
+        }
+    }
+
+    /// Abstractly store `value` to operand `i` of `insn`.
+    fn write(
+        &mut self,
+        state: &mut State,
+        insn: &Instruction,
+        i: usize,
+        value: &Value,
+        memory: &Memory,
+    ) {
+        let addr = insn.addr;
+        let len = self.program.len() as Word;
+        let relative = matches!(insn.operands[i], Operand::Relative(_));
+        let offset = operand_value(state, memory, insn, i);
+        let base = if relative {
+            state.relative_base.single()
+        } else {
+            Some(0)
+        };
+        if relative && base.is_none() {
+            // We don't know exactly where the stack is. That's fine as long
+            // as the store can't reach back into the program image.
+            let lowest = state.relative_base.add(&offset).bounds();
+            if lowest.is_none_or(|(lo, _)| lo < len) {
+                self.issues.insert(Issue::UnknownWrite { addr });
+                state.image.clear();
+            }
+        }
+        let targets = match offset {
+            Value::OneOf(targets) => targets,
+            Value::Range(lo, hi) => {
+                // A store somewhere in a range, like a pointer walking through an array.
+                if let Some(base) = base {
+                    for t in lo.saturating_add(base).max(0)..=hi.saturating_add(base).min(len - 1) {
+                        self.record(addr, t as usize);
+                        self.volatile.insert(t as usize);
+                    }
+                }
+                if relative {
+                    state.clobber();
+                }
+                return;
+            }
+            Value::Unknown => {
+                // An indirect store to an address we know nothing about. It
+                // could go anywhere, but having reported it, carry on as if
+                // it only hit data.
+                self.issues.insert(Issue::UnknownWrite { addr });
+                state.image.clear();
+                return;
+            }
+        };
+        // With more than one possible target, each one may keep its old value.
+        let strong = targets.len() == 1;
+        for target in targets {
+            if let Some(abs) = base.map(|b| b + target) {
+                if (0..len).contains(&abs) {
+                    self.record(addr, abs as usize);
+                    if memory.tracked(abs as usize) {
+                        set_cell(&mut state.image, abs as usize, value, strong);
+                    }
+                }
+            }
+            if relative {
+                state.store(target, value, strong);
+            } else if let (true, Some(rb)) = (target >= len, state.relative_base.single()) {
+                // The stack lives past the end of the program image.
+                state.store(target - rb, value, strong);
+            }
+        }
+    }
+
+    /// Note that the instruction at `addr` may store to `target`.
+    fn record(&mut self, addr: usize, target: usize) {
+        self.writes.entry(target).or_default().insert(addr);
+    }
+
+    /// Is this cell part of some reachable instruction?
+    fn code_cells(&self) -> BTreeSet<usize> {
+        self.instructions
+            .values()
+            .flat_map(|insn| insn.addr..insn.addr + insn.size())
+            .collect()
+    }
+}
+
+/// The raw value of operand `i` of `insn`, which may have been patched.
+fn operand_value(state: &State, memory: &Memory, insn: &Instruction, i: usize) -> Value {
+    match insn.operands[i] {
+        Operand::Position(n) | Operand::Immediate(n) | Operand::Relative(n) => {
+            memory.load(state, insn.addr + 1 + i, n)
+        }
+    }
+}
+
+/// An annotated listing: issues first, then the program, with reachable
+/// instructions disassembled and everything else shown as data.
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "; WARNING: {issue}")?;
+        }
+        let labels: BTreeSet<usize> = self.jump_targets.values().flatten().copied().collect();
+        let code = self.code_cells();
+
+        // Instructions can overlap, so list every one that starts inside the
+        // last, and only show data once past the end of all of them.
+        let mut addr = 0;
+        let mut end = 0;
+        while addr < self.program.len() {
+            if let Some(insn) = self.instructions.get(&addr) {
+                if labels.contains(&addr) {
+                    writeln!(f, "L{addr}:")?;
+                }
+                let mut notes = vec![];
+                if addr < end {
+                    notes.push("overlaps the instruction before it".to_string());
+                }
+                if let Some(targets) = self.jump_targets.get(&addr) {
+                    let indirect = matches!(
+                        insn.operands[1],
+                        Operand::Relative(_) | Operand::Position(_)
+                    );
+                    if indirect {
+                        let list: Vec<String> = targets.iter().map(|t| t.to_string()).collect();
+                        notes.push(format!("to {}", list.join(", ")));
+                    }
+                }
+                for i in 0..insn.operands.len() {
+                    if let Some(writers) = self.writes.get(&(addr + 1 + i)) {
+                        let list: Vec<String> = writers.iter().map(|w| w.to_string()).collect();
+                        notes.push(format!("operand {} patched by {}", i + 1, list.join(", ")));
+                    }
+                }
+                if notes.is_empty() {
+                    writeln!(f, "{insn}")?;
+                } else {
+                    writeln!(f, "{insn}  ; {}", notes.join("; "))?;
+                }
+                end = end.max(addr + insn.size());
+                addr = (addr + 1..end)
+                    .find(|a| self.instructions.contains_key(a))
+                    .unwrap_or(end);
+            } else {
+                let start = addr;
+                addr += 1;
+                while addr < self.program.len() && !code.contains(&addr) && addr - start < 8 {
+                    addr += 1;
+                }
+                let words: Vec<String> = self.program[start..addr]
+                    .iter()
+                    .map(|w| w.to_string())
+                    .collect();
+                writeln!(f, "{start:6}  data {}", words.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Event, Vm};
+
+    fn lines(analysis: &Analysis) -> Vec<String> {
+        analysis.to_string().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_d7p2_1() {
+        let p1 = parse(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        )
+        .unwrap();
+        let analysis = analyze(&p1);
+        assert!(analysis.issues.is_empty());
+        assert_eq!(
+            lines(&analysis),
+            [
+                "     0  input -> [26]",
+                "     2  add [26], -4 -> [26]",
+                "L6:",
+                "     6  input -> [27]",
+                "     8  mul [27], 2 -> [27]",
+                "    12  add [27], [26] -> [27]",
+                "    16  output [27]",
+                "    18  add [28], -1 -> [28]",
+                "    22  jump-if-true [28], 6",
+                "    25  halt",
+                // Unlike the Python disassembler, we know this part is data.
+                "    26  data 0, 0, 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        // main: push return address 11, call 15, output rel[1], halt.
+        // func at 15: arb 2, store 42 at rel[-1], arb -2, return via rel[0].
+        let program = [
+            109, 100, // 0: arb 100
+            21101, 11, 0, 0, // 2: add 11, 0 -> rel[0]
+            1105, 1, 15, // 6: jump-if-true 1, 15
+            0, 0, // 9: data
+            204, 1,  // 11: output rel[1]
+            99, // 13: halt
+            0,  // 14: data
+            109, 2, // 15: arb 2
+            21101, 42, 0, -1, // 17: add 42, 0 -> rel[-1]
+            109, -2, // 21: arb -2
+            2105, 1, 0, // 23: jump-if-true 1, rel[0]
+        ];
+        let analysis = analyze(&program);
+        assert!(analysis.issues.is_empty(), "{:?}", analysis.issues);
+        assert_eq!(analysis.jump_targets[&23], BTreeSet::from([11]));
+        assert!(analysis.instructions.contains_key(&11));
+        assert!(!analysis.instructions.contains_key(&9));
+        let text = analysis.to_string();
+        assert!(text.contains("    23  jump-if-true 1, rel[0]  ; to 11\n"));
+        assert!(text.contains("     9  data 0, 0\n"));
+    }
+
+    #[test]
+    fn test_indirect_load() {
+        // Copy [input] into the operand of the output instruction, then output.
+        let program = [3, 10, 1001, 10, 0, 7, 4, 0, 99, 0, 0];
+        let analysis = analyze(&program);
+        assert!(analysis.issues.is_empty());
+        assert!(analysis
+            .to_string()
+            .contains("     6  output [0]  ; operand 1 patched by 2\n"));
+    }
+
+    #[test]
+    fn test_unknown_relative_base() {
+        // Move the relative base by [input], then store through it.
+        let program = [3, 20, 9, 20, 21101, 1, 2, 0, 99];
+        let analysis = analyze(&program);
+        assert!(analysis.issues.contains(&Issue::UnknownWrite { addr: 4 }));
+
+        // Same, but the base is 100 or 101, well past the end of the program.
+        let program = [109, 100, 3, 30, 1007, 30, 5, 31, 9, 31, 21101, 1, 2, 0, 99];
+        let analysis = analyze(&program);
+        assert!(analysis.issues.is_empty(), "{:?}", analysis.issues);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // Overwrite the halt at 5 with whatever was input.
+        let program = [3, 5, 1105, 1, 5, 99];
+        let analysis = analyze(&program);
+        assert!(analysis.issues.contains(&Issue::SelfModifyingCode {
+            writer: 0,
+            target: 5
+        }));
+        assert!(analysis
+            .to_string()
+            .starts_with("; WARNING: 0 may overwrite the opcode at 5\n"));
+
+        // Jumping to an address read from input.
+        let program = [3, 7, 5, 7, 7, 99, 99, 0];
+        let analysis = analyze(&program);
+        assert!(analysis.issues.contains(&Issue::UnresolvedJump { addr: 2 }));
+    }
+
+    #[test]
+    fn test_overlapping() {
+        // The jump at 4 goes to 2, in the middle of the first jump.
+        let program = [1105, 1, 4, 0, 1105, 1, 2];
+        let analysis = analyze(&program);
+        assert_eq!(
+            lines(&analysis),
+            [
+                "     0  jump-if-true 1, 4",
+                "L2:",
+                "     2  output [0]  ; overlaps the instruction before it",
+                "L4:",
+                "     4  jump-if-true 1, 2",
+            ]
+        );
+    }
+
+    /// Every instruction the program actually executes must have been found.
+    fn check_sound(program: &[Word], input: &[Word]) {
+        let analysis = analyze(program);
+        assert!(
+            analysis.issues.is_empty(),
+            "unexpected issues: {:?}",
+            analysis.issues
+        );
+        let mut vm = Vm::new(program);
+        vm.extend_input(input.iter().copied());
+        loop {
+            assert!(
+                analysis.instructions.contains_key(&vm.ip()),
+                "executed {} but analysis missed it",
+                vm.ip()
+            );
+            if let Some(Event::Halted | Event::NeedInput) = vm.step().unwrap() {
+                break;
+            }
+        }
+    }
+
+    fn puzzle(day: &str) -> Vec<Word> {
+        let path = format!(
+            "{}/../dec{day}/puzzle-input.txt",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        parse(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_puzzle_inputs() {
+        // Day 5's program patches its own opcodes right at the start.
+        let analysis = analyze(&puzzle("05"));
+        assert!(analysis.issues.contains(&Issue::SelfModifyingCode {
+            writer: 2,
+            target: 6
+        }));
+
+        check_sound(&puzzle("09"), &[1]);
+        check_sound(&puzzle("09"), &[2]);
+        check_sound(&puzzle("11"), &[0; 100]);
+        check_sound(&puzzle("13"), &[]);
+    }
+}
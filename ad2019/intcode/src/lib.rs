@@ -0,0 +1,7 @@
+//! Tools for the Intcode computer from Advent of Code 2019.
+
+mod disasm;
+mod vm;
+
+pub use disasm::*;
+pub use vm::*;
@@ -0,0 +1,688 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseIntError;
+use std::sync::mpsc::{Receiver, Sender};
+
+pub type Word = i64;
+
+/// Parse a program: integers separated by commas.
+pub fn parse(text: &str) -> Result<Vec<Word>, ParseIntError> {
+    text.trim()
+        .split(',')
+        .map(|word| word.trim().parse())
+        .collect()
+}
+
+/// Run a program to completion on the given inputs and return its outputs.
+pub fn run(program: &[Word], input: impl IntoIterator<Item = Word>) -> Result<Vec<Word>, Error> {
+    let mut vm = Vm::new(program);
+    vm.extend_input(input);
+    vm.run_to_halt()
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    BadOpcode {
+        ip: usize,
+        insn: Word,
+    },
+    BadMode {
+        ip: usize,
+        operand: usize,
+        mode: Word,
+    },
+    NegativeAddress {
+        ip: usize,
+        addr: Word,
+    },
+    /// The program wanted input and none was available.
+    OutOfInput {
+        ip: usize,
+    },
+    /// The program wanted input and the channel it reads from was closed.
+    InputClosed {
+        ip: usize,
+    },
+    /// The program produced output and the channel it writes to was closed.
+    OutputClosed {
+        ip: usize,
+    },
+    /// An addition or multiplication, or an address computed from the
+    /// relative base, doesn't fit in a `Word`.
+    Overflow {
+        ip: usize,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadOpcode { ip, insn } => {
+                write!(f, "unrecognized instruction {insn} at ip={ip}")
+            }
+            Error::BadMode { ip, operand, mode } => {
+                write!(
+                    f,
+                    "invalid mode {mode} for operand {operand} of instruction at ip={ip}"
+                )
+            }
+            Error::NegativeAddress { ip, addr } => {
+                write!(f, "access to negative address {addr} at ip={ip}")
+            }
+            Error::OutOfInput { ip } => {
+                write!(f, "input instruction at ip={ip}, but no input left")
+            }
+            Error::InputClosed { ip } => write!(
+                f,
+                "input instruction at ip={ip}, but input channel is closed"
+            ),
+            Error::OutputClosed { ip } => write!(
+                f,
+                "output instruction at ip={ip}, but output channel is closed"
+            ),
+            Error::Overflow { ip } => write!(f, "arithmetic overflow at ip={ip}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Why the VM stopped running.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    Output(Word),
+    /// Stopped at an input instruction because the input queue is empty.
+    /// Push some input and run again to continue.
+    NeedInput,
+    Halted,
+    /// Stopped because the step limit was reached.
+    StepLimit,
+}
+
+/// An Intcode computer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Vm {
+    memory: Vec<Word>,
+    ip: usize,
+    relative_base: Word,
+    input: VecDeque<Word>,
+    halted: bool,
+    steps: u64,
+}
+
+/// A saved copy of a VM's complete state, including pending input.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Snapshot(Vm);
+
+impl Vm {
+    pub fn new(program: &[Word]) -> Self {
+        Vm {
+            memory: program.to_vec(),
+            ip: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            halted: false,
+            steps: 0,
+        }
+    }
+
+    pub fn memory(&self) -> &[Word] {
+        &self.memory
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> Word {
+        self.relative_base
+    }
+
+    /// Total number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Read memory. Addresses past the end of the program read as 0.
+    pub fn peek(&self, addr: usize) -> Word {
+        self.memory.get(addr).copied().unwrap_or(0)
+    }
+
+    /// Write memory, growing it if necessary.
+    pub fn poke(&mut self, addr: usize, value: Word) {
+        if addr >= self.memory.len() {
+            self.memory.resize(addr + 1, 0);
+        }
+        self.memory[addr] = value;
+    }
+
+    pub fn push_input(&mut self, value: Word) {
+        self.input.push_back(value);
+    }
+
+    pub fn extend_input(&mut self, values: impl IntoIterator<Item = Word>) {
+        self.input.extend(values);
+    }
+
+    /// Push a line of ASCII text, followed by a newline.
+    pub fn push_line(&mut self, line: &str) {
+        self.input.extend(line.bytes().map(Word::from));
+        self.input.push_back(10);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(self.clone())
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        *self = snapshot.0.clone();
+    }
+
+    fn load(&self, addr: Word) -> Result<Word, Error> {
+        if addr < 0 {
+            return Err(Error::NegativeAddress { ip: self.ip, addr });
+        }
+        Ok(self.peek(addr as usize))
+    }
+
+    fn store(&mut self, addr: Word, value: Word) -> Result<(), Error> {
+        if addr < 0 {
+            return Err(Error::NegativeAddress { ip: self.ip, addr });
+        }
+        self.poke(addr as usize, value);
+        Ok(())
+    }
+
+    fn relative(&self, raw: Word) -> Result<Word, Error> {
+        raw.checked_add(self.relative_base)
+            .ok_or(Error::Overflow { ip: self.ip })
+    }
+
+    fn mode(&self, operand: usize) -> Word {
+        self.peek(self.ip) / [100, 1000, 10000][operand - 1] % 10
+    }
+
+    /// Get the value of an input operand for the current instruction.
+    fn get(&self, operand: usize) -> Result<Word, Error> {
+        let raw = self.peek(self.ip + operand);
+        match self.mode(operand) {
+            0 => self.load(raw),
+            1 => Ok(raw),
+            2 => self.load(self.relative(raw)?),
+            mode => Err(Error::BadMode {
+                ip: self.ip,
+                operand,
+                mode,
+            }),
+        }
+    }
+
+    /// Get the target address for an output operand.
+    fn get_addr(&self, operand: usize) -> Result<Word, Error> {
+        let raw = self.peek(self.ip + operand);
+        match self.mode(operand) {
+            0 => Ok(raw),
+            2 => self.relative(raw),
+            mode => Err(Error::BadMode {
+                ip: self.ip,
+                operand,
+                mode,
+            }),
+        }
+    }
+
+    /// Execute one instruction. Returns `Some(event)` if the instruction
+    /// was an output or halt, or if it was an input and no input was queued
+    /// (in which case the instruction is not executed).
+    pub fn step(&mut self) -> Result<Option<Event>, Error> {
+        if self.halted {
+            return Ok(Some(Event::Halted));
+        }
+        let insn = self.peek(self.ip);
+        let event = match insn % 100 {
+            op @ (1 | 2 | 7 | 8) => {
+                let (a, b, out) = (self.get(1)?, self.get(2)?, self.get_addr(3)?);
+                let value = match op {
+                    1 => a.checked_add(b).ok_or(Error::Overflow { ip: self.ip })?,
+                    2 => a.checked_mul(b).ok_or(Error::Overflow { ip: self.ip })?,
+                    7 => Word::from(a < b),
+                    _ => Word::from(a == b),
+                };
+                self.store(out, value)?;
+                self.ip += 4;
+                None
+            }
+            3 => {
+                let addr = self.get_addr(1)?;
+                match self.input.pop_front() {
+                    None => return Ok(Some(Event::NeedInput)),
+                    Some(value) => self.store(addr, value)?,
+                }
+                self.ip += 2;
+                None
+            }
+            4 => {
+                let value = self.get(1)?;
+                self.ip += 2;
+                Some(Event::Output(value))
+            }
+            op @ (5 | 6) => {
+                let (cond, target) = (self.get(1)?, self.get(2)?);
+                if (cond != 0) == (op == 5) {
+                    if target < 0 {
+                        return Err(Error::NegativeAddress {
+                            ip: self.ip,
+                            addr: target,
+                        });
+                    }
+                    self.ip = target as usize;
+                } else {
+                    self.ip += 3;
+                }
+                None
+            }
+            9 => {
+                self.relative_base = self.relative(self.get(1)?)?;
+                self.ip += 2;
+                None
+            }
+            99 if insn == 99 => {
+                self.halted = true;
+                Some(Event::Halted)
+            }
+            _ => return Err(Error::BadOpcode { ip: self.ip, insn }),
+        };
+        self.steps += 1;
+        Ok(event)
+    }
+
+    /// Run until the next output, halt, or input instruction with no input available.
+    pub fn run(&mut self) -> Result<Event, Error> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Like `run`, but give up after executing `max_steps` instructions.
+    pub fn run_limited(&mut self, max_steps: u64) -> Result<Event, Error> {
+        for _ in 0..max_steps {
+            if let Some(event) = self.step()? {
+                return Ok(event);
+            }
+        }
+        Ok(Event::StepLimit)
+    }
+
+    /// Run until the program halts, collecting its output. It's an error if
+    /// the program needs more input than has been queued.
+    pub fn run_to_halt(&mut self) -> Result<Vec<Word>, Error> {
+        let mut output = vec![];
+        loop {
+            match self.run()? {
+                Event::Output(value) => output.push(value),
+                Event::NeedInput => return Err(Error::OutOfInput { ip: self.ip }),
+                Event::Halted | Event::StepLimit => return Ok(output),
+            }
+        }
+    }
+
+    /// Iterate over the program's outputs, running it as needed.
+    pub fn outputs(&mut self) -> Outputs<'_> {
+        Outputs { vm: self }
+    }
+
+    /// Run until the program halts, reading input from `input` whenever the
+    /// input queue is empty and sending all output to `output`. Meant to be
+    /// run on its own thread, with channels connecting it to other VMs.
+    pub fn run_on_channels(
+        &mut self,
+        input: &Receiver<Word>,
+        output: &Sender<Word>,
+    ) -> Result<(), Error> {
+        loop {
+            match self.run()? {
+                Event::Output(value) => output
+                    .send(value)
+                    .map_err(|_| Error::OutputClosed { ip: self.ip })?,
+                Event::NeedInput => {
+                    let value = input
+                        .recv()
+                        .map_err(|_| Error::InputClosed { ip: self.ip })?;
+                    self.push_input(value);
+                }
+                Event::Halted | Event::StepLimit => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`Vm::outputs`]. Ends when the program halts or
+/// runs out of input.
+pub struct Outputs<'a> {
+    vm: &'a mut Vm,
+}
+
+impl Iterator for Outputs<'_> {
+    type Item = Result<Word, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.vm.run() {
+            Ok(Event::Output(value)) => Some(Ok(value)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    fn compute(program: &[Word]) -> Vec<Word> {
+        let mut vm = Vm::new(program);
+        vm.run_to_halt().unwrap();
+        vm.memory().to_vec()
+    }
+
+    #[test]
+    fn test_day_2() {
+        assert_eq!(
+            compute(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
+            [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]
+        );
+        assert_eq!(compute(&[1, 0, 0, 0, 99]), [2, 0, 0, 0, 99]);
+        assert_eq!(compute(&[2, 3, 0, 3, 99]), [2, 3, 0, 6, 99]);
+        assert_eq!(compute(&[2, 4, 4, 5, 99, 0]), [2, 4, 4, 5, 99, 9801]);
+        assert_eq!(
+            compute(&[1, 1, 1, 4, 99, 5, 6, 0, 99]),
+            [30, 1, 1, 4, 2, 5, 6, 0, 99]
+        );
+        assert_eq!(compute(&[1002, 4, 3, 4, 33]), [1002, 4, 3, 4, 99]);
+        assert_eq!(compute(&[1101, 100, -1, 4, 0]), [1101, 100, -1, 4, 99]);
+    }
+
+    fn check(program: &[Word], inputs: &[Word], expected: impl Fn(Word) -> Word) {
+        for &x in inputs {
+            assert_eq!(run(program, [x]).unwrap(), [expected(x)]);
+        }
+    }
+
+    #[test]
+    fn test_day_5() {
+        assert_eq!(run(&[3, 0, 4, 0, 99], [12345, 54321]).unwrap(), [12345]);
+        check(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[7, 8, 9], |x| {
+            Word::from(x == 8)
+        });
+        check(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[7, 8, 9], |x| {
+            Word::from(x < 8)
+        });
+        check(&[3, 3, 1108, -1, 8, 3, 4, 3, 99], &[7, 8, 9], |x| {
+            Word::from(x == 8)
+        });
+        check(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[7, 8, 9], |x| {
+            Word::from(x < 8)
+        });
+        check(
+            &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            &[-1, 0, 1],
+            |x| Word::from(x != 0),
+        );
+        check(
+            &[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+            &[-1, 0, 1],
+            |x| Word::from(x != 0),
+        );
+        let large = parse(
+            "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\
+             1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\
+             999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+        )
+        .unwrap();
+        check(&large, &[7, 8, 9], |x| match x.cmp(&8) {
+            std::cmp::Ordering::Less => 999,
+            std::cmp::Ordering::Equal => 1000,
+            std::cmp::Ordering::Greater => 1001,
+        });
+    }
+
+    #[test]
+    fn test_day_9() {
+        let quine = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(run(&quine, []).unwrap(), quine);
+        let [big] = run(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], []).unwrap()[..] else {
+            panic!("expected one output");
+        };
+        assert_eq!(big.to_string().len(), 16);
+        assert_eq!(
+            run(&[104, 1125899906842624, 99], []).unwrap(),
+            [1125899906842624]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(run(&[3, 0, 99], []), Err(Error::OutOfInput { ip: 0 }));
+        assert_eq!(run(&[42], []), Err(Error::BadOpcode { ip: 0, insn: 42 }));
+        assert_eq!(
+            run(&[1, -1, 0, 0, 99], []),
+            Err(Error::NegativeAddress { ip: 0, addr: -1 })
+        );
+        assert_eq!(
+            run(&[11101, 1, 1, 0, 99], []),
+            Err(Error::BadMode {
+                ip: 0,
+                operand: 3,
+                mode: 1
+            })
+        );
+
+        let big = Word::MAX / 2 + 1;
+        assert_eq!(
+            run(&[1101, big, big, 0, 99], []),
+            Err(Error::Overflow { ip: 0 })
+        );
+        assert_eq!(
+            run(&[1102, big, -3, 0, 99], []),
+            Err(Error::Overflow { ip: 0 })
+        );
+        assert_eq!(run(&[1102, big, -2, 0, 4, 0, 99], []), Ok(vec![Word::MIN]));
+        assert_eq!(
+            run(&[109, Word::MAX, 209, 1, 99], []),
+            Err(Error::Overflow { ip: 2 })
+        );
+    }
+
+    #[test]
+    fn test_pause_and_snapshot() {
+        // Read a number, output it doubled, forever.
+        let program = [3, 9, 1002, 9, 2, 9, 4, 9, 1105, 1, 0];
+        let mut vm = Vm::new(&program);
+        assert_eq!(vm.run(), Ok(Event::NeedInput));
+        vm.push_input(5);
+        assert_eq!(vm.run(), Ok(Event::Output(10)));
+        let saved = vm.snapshot();
+        vm.push_input(7);
+        assert_eq!(vm.run(), Ok(Event::Output(14)));
+        vm.restore(&saved);
+        assert_eq!(vm.run(), Ok(Event::NeedInput));
+        vm.extend_input([1, 2, 3]);
+        let outputs: Vec<Word> = vm.outputs().map(Result::unwrap).collect();
+        assert_eq!(outputs, [2, 4, 6]);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let spin = [1105, 1, 0];
+        let mut vm = Vm::new(&spin);
+        assert_eq!(vm.run_limited(1000), Ok(Event::StepLimit));
+        assert_eq!(vm.steps(), 1000);
+        assert_eq!(Vm::new(&[99]).run_limited(1), Ok(Event::Halted));
+    }
+
+    /// Run five amplifiers in a feedback loop, each on its own thread.
+    fn thruster_signal(program: &[Word], phases: &[Word]) -> Word {
+        let n = phases.len();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..n).map(|_| channel()).unzip();
+        for (i, &phase) in phases.iter().enumerate() {
+            senders[i].send(phase).unwrap();
+        }
+        senders[0].send(0).unwrap();
+
+        // Amp i reads from channel i and writes to channel i+1; the last
+        // amp's output goes back to the first, but we also need to see it.
+        let (last_tx, last_rx) = channel();
+        let mut handles = vec![];
+        for (i, rx) in receivers.into_iter().enumerate() {
+            let tx = if i + 1 == n {
+                last_tx.clone()
+            } else {
+                senders[i + 1].clone()
+            };
+            let program = program.to_vec();
+            handles.push(thread::spawn(move || {
+                Vm::new(&program).run_on_channels(&rx, &tx)
+            }));
+        }
+        drop(last_tx);
+
+        let mut signal = None;
+        let first = senders[0].clone();
+        drop(senders);
+        for value in last_rx {
+            signal = Some(value);
+            let _ = first.send(value);
+        }
+        drop(first);
+        for h in handles {
+            h.join().unwrap().unwrap();
+        }
+        signal.unwrap()
+    }
+
+    #[test]
+    fn test_day_7_feedback() {
+        let program = parse(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,\
+             27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        )
+        .unwrap();
+        assert_eq!(thruster_signal(&program, &[9, 8, 7, 6, 5]), 139629729);
+    }
+
+    fn puzzle(day: &str) -> Vec<Word> {
+        let path = format!(
+            "{}/../dec{day}/puzzle-input.txt",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        parse(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_puzzle_inputs() {
+        let mut day2 = Vm::new(&puzzle("02"));
+        day2.poke(1, 12);
+        day2.poke(2, 2);
+        day2.run_to_halt().unwrap();
+        assert_eq!(day2.peek(0), 3224742);
+
+        let day5 = puzzle("05");
+        assert_eq!(run(&day5, [1]).unwrap().last(), Some(&15259545));
+        assert_eq!(run(&day5, [5]).unwrap(), [7616021]);
+
+        let day9 = puzzle("09");
+        assert_eq!(run(&day9, [1]).unwrap(), [2870072642]);
+        assert_eq!(run(&day9, [2]).unwrap(), [58534]);
+    }
+
+    #[test]
+    fn test_day_7_puzzle() {
+        let program = puzzle("07");
+        let mut phases = [5, 6, 7, 8, 9];
+        let mut best = 0;
+        // Heap's algorithm, to try every ordering of the phases.
+        let mut c = [0; 5];
+        best = best.max(thruster_signal(&program, &phases));
+        let mut i = 0;
+        while i < 5 {
+            if c[i] < i {
+                phases.swap(if i % 2 == 0 { 0 } else { c[i] }, i);
+                best = best.max(thruster_signal(&program, &phases));
+                c[i] += 1;
+                i = 0;
+            } else {
+                c[i] = 0;
+                i += 1;
+            }
+        }
+        assert_eq!(best, 5371621);
+    }
+
+    /// Run the day 23 network deterministically, round-robin, until the NAT
+    /// sends the same y value twice in a row. Returns the first y sent to
+    /// the NAT and the repeated y.
+    fn run_network(program: &[Word]) -> (Word, Word) {
+        let mut computers: Vec<Vm> = (0..50)
+            .map(|addr| {
+                let mut vm = Vm::new(program);
+                vm.push_input(addr);
+                vm
+            })
+            .collect();
+        let mut pending: Vec<Vec<Word>> = vec![vec![]; 50];
+        let mut first_y = None;
+        let mut nat: Option<(Word, Word)> = None;
+        let mut last_nat_y = None;
+        let mut idle_rounds = 0;
+        loop {
+            let mut idle = true;
+            for i in 0..50 {
+                match computers[i].run_limited(10_000).unwrap() {
+                    Event::Output(v) => {
+                        idle = false;
+                        pending[i].push(v);
+                        if let [addr, x, y] = pending[i][..] {
+                            pending[i].clear();
+                            if addr == 255 {
+                                first_y.get_or_insert(y);
+                                nat = Some((x, y));
+                            } else {
+                                computers[addr as usize].extend_input([x, y]);
+                            }
+                        }
+                    }
+                    Event::NeedInput => computers[i].push_input(-1),
+                    Event::StepLimit => idle = false,
+                    Event::Halted => panic!("computer {i} halted"),
+                }
+            }
+            idle_rounds = if idle { idle_rounds + 1 } else { 0 };
+            // One quiet round isn't enough; a computer may have just read a
+            // packet and not responded yet.
+            if let (2, Some((x, y))) = (idle_rounds, nat) {
+                idle_rounds = 0;
+                if last_nat_y == Some(y) {
+                    return (first_y.unwrap(), y);
+                }
+                last_nat_y = Some(y);
+                computers[0].extend_input([x, y]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_day_23_network() {
+        let (first_y, repeated_y) = run_network(&puzzle("23"));
+        assert_eq!(first_y, 21897);
+        assert_eq!(repeated_y, 16424);
+    }
+}
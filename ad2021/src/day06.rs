@@ -1,3 +1,4 @@
+use adlib::{Scalar, Transition};
 use aoc_runner_derive::*;
 
 const TIMER_LIMIT: usize = 9;
@@ -14,13 +15,17 @@ fn parse_input(text: &str) -> anyhow::Result<Vec<u64>> {
     Ok(results)
 }
 
-fn solve(ndays: usize, fish: &[u64]) -> u64 {
-    let mut fish = fish.to_vec();
-    for i in 0..ndays {
-        let k = fish[i % TIMER_LIMIT];
-        fish[(i + 6 + 1) % TIMER_LIMIT] += k;
-    }
-    fish.into_iter().sum()
+fn lanternfish<T: Scalar>() -> Transition<usize, T> {
+    Transition::new(0..TIMER_LIMIT, |&timer| match timer {
+        0 => vec![(6, T::one()), (8, T::one())],
+        t => vec![(t - 1, T::one())],
+    })
+}
+
+fn solve(ndays: u64, fish: &[u64]) -> u64 {
+    let t = lanternfish::<u64>();
+    let fish = t.vector(fish.iter().copied().enumerate());
+    t.advance(&fish, ndays).into_iter().sum()
 }
 
 #[aoc(day6, part1, jorendorff)]
@@ -50,4 +55,22 @@ mod tests {
     fn test_part_2() {
         assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 26984457539);
     }
+
+    #[test]
+    fn test_many_days() {
+        use adlib::Mod;
+
+        const P: u64 = 1_000_000_007;
+        let t = lanternfish::<Mod<P>>();
+        let fish = parse_input(EXAMPLE).unwrap();
+        let v = t.vector(fish.iter().map(|&n| Mod::new(n)).enumerate());
+        let total = |days| {
+            t.advance(&v, days)
+                .into_iter()
+                .fold(Mod::zero(), Scalar::plus)
+                .value()
+        };
+        assert_eq!(total(256), 26984457539 % P);
+        assert_eq!(total(1_000_000_000_000), 995077479);
+    }
 }
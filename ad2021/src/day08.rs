@@ -1,4 +1,3 @@
-use itertools::Itertools;
 use std::str::FromStr;
 
 use aoc_runner_derive::*;
@@ -26,34 +25,192 @@ const GOOD_PATTERN_STRS: [&str; 10] = [
     "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
 ];
 
-impl Pattern {
-    fn read(self) -> u64 {
-        match self.0 {
-            0b1110111 => 0,
-            0b0100100 => 1,
-            0b1011101 => 2,
-            0b1101101 => 3,
-            0b0101110 => 4,
-
-            0b1101011 => 5,
-            0b1111011 => 6,
-            0b0100101 => 7,
-            0b1111111 => 8,
-            0b1101111 => 9,
-            _ => panic!("can't read digit: Pattern({:b})", self.0),
+/// A display made of up to 26 segments, `a` through `z`, and the symbols it can show, each
+/// one a set of lit segments (one bit per segment).
+struct SegmentDisplay {
+    segments: usize,
+    symbols: Vec<u64>,
+}
+
+/// Which segment each wire really lights up: `wiring.0[w]` is the segment
+/// for wire `w`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Wiring(Vec<usize>);
+
+impl Wiring {
+    fn apply(&self, wires: u64) -> u64 {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|&(w, _)| wires & (1 << w) != 0)
+            .map(|(_, &s)| 1 << s)
+            .sum()
+    }
+}
+
+fn bits(set: u64) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |i| set & (1 << i) != 0)
+}
+
+impl SegmentDisplay {
+    /// Symbols written as strings of segment letters: `a` is segment 0, `b`
+    /// is segment 1, and so on. The display has as many segments as the
+    /// highest letter used.
+    fn from_strs(strs: &[&str]) -> anyhow::Result<Self> {
+        let symbols = strs
+            .iter()
+            .map(|s| {
+                let mut bits = 0u64;
+                for c in s.chars() {
+                    let bit = match c {
+                        'a'..='z' => 1u64 << (c as u32 - 'a' as u32),
+                        _ => anyhow::bail!("unrecognized segment {:?} in symbol {:?}", c, s),
+                    };
+                    anyhow::ensure!(
+                        bits & bit == 0,
+                        "segment {:?} repeated in symbol {:?}",
+                        c,
+                        s
+                    );
+                    bits |= bit;
+                }
+                Ok(bits)
+            })
+            .collect::<anyhow::Result<Vec<u64>>>()?;
+        let segments = 64 - symbols.iter().fold(0, |acc, s| acc | s).leading_zeros() as usize;
+        Ok(SegmentDisplay { segments, symbols })
+    }
+
+    fn all(&self) -> u64 {
+        (1 << self.segments) - 1
+    }
+
+    /// Index of the symbol shown by these segments.
+    fn read(&self, lit: u64) -> Option<usize> {
+        self.symbols.iter().position(|&s| s == lit)
+    }
+
+    /// Narrow down `cand`, the segments each wire could be connected to,
+    /// until nothing changes. Returns false if some wire has nowhere left
+    /// to go.
+    fn propagate(&self, observed: &[u64], cand: &mut [u64]) -> bool {
+        let all = self.all();
+        loop {
+            let before = cand.to_vec();
+
+            // Each observation must be one of the symbols with the same
+            // number of segments. Wires that are on must go to segments
+            // lit in one of those; wires that are off, to segments unlit in
+            // one of those.
+            for &p in observed {
+                let fits = |sym: &&u64| {
+                    sym.count_ones() == p.count_ones()
+                        && (0..self.segments).all(|w| {
+                            let allowed = if p & (1 << w) != 0 {
+                                **sym
+                            } else {
+                                all & !**sym
+                            };
+                            cand[w] & allowed != 0
+                        })
+                };
+                let (mut on, mut off) = (0, 0);
+                for &sym in self.symbols.iter().filter(fits) {
+                    on |= sym;
+                    off |= all & !sym;
+                }
+                for (w, c) in cand.iter_mut().enumerate() {
+                    *c &= if p & (1 << w) != 0 { on } else { off };
+                }
+            }
+
+            // No two wires go to the same segment.
+            for w in 0..self.segments {
+                if cand[w].count_ones() == 1 {
+                    let taken = cand[w];
+                    for (v, c) in cand.iter_mut().enumerate() {
+                        if v != w {
+                            *c &= !taken;
+                        }
+                    }
+                }
+            }
+            // Every segment needs a wire.
+            for s in 0..self.segments {
+                let mut wires = (0..self.segments).filter(|&w| cand[w] & (1 << s) != 0);
+                match (wires.next(), wires.next()) {
+                    (None, _) => return false,
+                    (Some(w), None) => cand[w] = 1 << s,
+                    _ => {}
+                }
+            }
+
+            if cand.contains(&0) {
+                return false;
+            }
+            if cand == before {
+                return true;
+            }
         }
     }
 
-    fn bit(self) -> u128 {
-        1 << self.0
+    fn search(&self, observed: &[u64], mut cand: Vec<u64>, limit: usize, out: &mut Vec<Wiring>) {
+        if out.len() >= limit || !self.propagate(observed, &mut cand) {
+            return;
+        }
+        let undecided = (0..self.segments)
+            .filter(|&w| cand[w].count_ones() > 1)
+            .min_by_key(|&w| cand[w].count_ones());
+        match undecided {
+            Some(w) => {
+                for s in bits(cand[w]) {
+                    let mut guess = cand.clone();
+                    guess[w] = 1 << s;
+                    self.search(observed, guess, limit, out);
+                }
+            }
+            None => {
+                let wiring = Wiring(cand.iter().map(|c| c.trailing_zeros() as usize).collect());
+                let mut shown = observed
+                    .iter()
+                    .map(|&p| wiring.apply(p))
+                    .collect::<Vec<_>>();
+                let mut symbols = self.symbols.clone();
+                shown.sort_unstable();
+                symbols.sort_unstable();
+                if shown == symbols {
+                    out.push(wiring);
+                }
+            }
+        }
+    }
+
+    /// Up to `limit` wirings under which `observed`, a scrambled view of
+    /// every symbol, shows exactly the display's symbols. More than one means
+    /// the observations are ambiguous. Wires the display doesn't have can't
+    /// be wired to anything, so observing one rules out every wiring.
+    fn solve(&self, observed: &[u64], limit: usize) -> Vec<Wiring> {
+        let mut out = vec![];
+        let fits = observed.iter().all(|&p| p & !self.all() == 0);
+        if observed.len() == self.symbols.len() && fits {
+            self.search(observed, vec![self.all(); self.segments], limit, &mut out);
+        }
+        out
     }
 }
 
 #[test]
 fn test_patterns() {
+    let display = SegmentDisplay::from_strs(&GOOD_PATTERN_STRS).unwrap();
+    assert_eq!(display.segments, 7);
     for (i, s) in GOOD_PATTERN_STRS.iter().enumerate() {
-        assert_eq!(Pattern::from_str(s).unwrap().read(), i as u64);
+        let p = Pattern::from_str(s).unwrap();
+        assert_eq!(display.read(p.0 as u64), Some(i));
     }
+
+    let err = |strs: &[&str]| SegmentDisplay::from_strs(strs).err().unwrap().to_string();
+    assert_eq!(err(&["ab", "aa"]), "segment 'a' repeated in symbol \"aa\"");
+    assert_eq!(err(&["aB"]), "unrecognized segment 'B' in symbol \"aB\"");
 }
 
 struct Entry {
@@ -106,65 +263,25 @@ fn part_1(entries: &[Entry]) -> usize {
         .count()
 }
 
-/// There are exactly 2^7 == 128 different Patterns, so a bitset of Patterns
-/// fits in a u128.
-struct PatternSet(u128);
-
-impl PatternSet {
-    fn new(patterns: &[Pattern]) -> Self {
-        Self(patterns.iter().map(|p| p.bit()).sum())
-    }
-
-    fn contains(&self, p: Pattern) -> bool {
-        p.bit() & self.0 != 0
-    }
-}
-
-#[allow(clippy::many_single_char_names)]
-fn decode(entry: &Entry, good_patterns: &PatternSet) -> anyhow::Result<u64> {
-    // Brute force. 7! is 5040.
-    for v in (0..7).permutations(7) {
-        let [a, b, c, d, e, f, g]: [u32; 7] = v.try_into().expect("failed to unpack permutation");
-
-        let permute = move |p: Pattern| -> Pattern {
-            Pattern(
-                (0..7)
-                    .zip([a, b, c, d, e, f, g])
-                    .map(|(i, j)| if p.0 & (1 << i) != 0 { 1 << j } else { 0 })
-                    .sum(),
-            )
-        };
-        if entry
-            .patterns
-            .iter()
-            .all(|p| good_patterns.contains(permute(*p)))
-        {
-            return Ok(entry
-                .output_value
-                .iter()
-                .map(|p| permute(*p).read())
-                .fold(0, |acc, digit| acc * 10 + digit));
-        }
-    }
-    anyhow::bail!("no solution found");
+fn decode(entry: &Entry, display: &SegmentDisplay) -> anyhow::Result<u64> {
+    let observed = entry.patterns.map(|p| p.0 as u64);
+    let wiring = match &display.solve(&observed, 2)[..] {
+        [] => anyhow::bail!("no solution found"),
+        [wiring] => wiring.clone(),
+        _ => anyhow::bail!("wiring is ambiguous"),
+    };
+    entry.output_value.iter().try_fold(0, |acc, p| {
+        let digit = display
+            .read(wiring.apply(p.0 as u64))
+            .ok_or_else(|| anyhow::anyhow!("can't read digit: Pattern({:b})", p.0))?;
+        Ok(acc * 10 + digit as u64)
+    })
 }
 
 #[aoc(day8, part2, jorendorff)]
 fn part_2(entries: &[Entry]) -> anyhow::Result<u64> {
-    // A bitset with 10 bits set, one for each good pattern.
-    let good_patterns = PatternSet::new(
-        &GOOD_PATTERN_STRS
-            .iter()
-            .map(|s| Pattern::from_str(*s))
-            .collect::<anyhow::Result<Vec<Pattern>>>()?,
-    );
-
-    Ok(entries
-        .iter()
-        .map(|e| decode(e, &good_patterns))
-        .collect::<anyhow::Result<Vec<u64>>>()?
-        .into_iter()
-        .sum())
+    let display = SegmentDisplay::from_strs(&GOOD_PATTERN_STRS)?;
+    entries.iter().map(|e| decode(e, &display)).sum()
 }
 
 #[cfg(test)]
@@ -193,4 +310,57 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
     fn test_part_2() {
         assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()).unwrap(), 61229);
     }
+
+    #[test]
+    fn test_ambiguous() {
+        // A display showing just 1 and 7 can't tell which of the
+        // right-hand wires is which.
+        let display = SegmentDisplay::from_strs(&["bc", "abc"]).unwrap();
+        let found = display.solve(&[0b011, 0b111], 10);
+        assert_eq!(found, [Wiring(vec![1, 2, 0]), Wiring(vec![2, 1, 0])]);
+
+        // Same, but with a fourth wire the display doesn't have.
+        assert!(display.solve(&[0b1011, 0b111], 10).is_empty());
+
+        // Observations that don't match the symbols at all.
+        let display = SegmentDisplay::from_strs(&GOOD_PATTERN_STRS).unwrap();
+        let observed = [1, 3, 7, 15, 31, 63, 127, 2, 4, 8];
+        assert!(display.solve(&observed, 10).is_empty());
+    }
+
+    #[test]
+    fn test_sixteen_segments() {
+        let mut seed = 2021u64;
+        let mut rand = move |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let symbols = (0..36)
+            .map(|_| ('a'..='p').filter(|_| rand(2) == 1).collect::<String>())
+            .collect::<Vec<_>>();
+        let symbols = symbols.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let display = SegmentDisplay::from_strs(&symbols).unwrap();
+        assert_eq!(display.segments, 16);
+
+        // Scramble the wires and shuffle the order the symbols are seen in.
+        let mut scramble = (0..16).collect::<Vec<usize>>();
+        for i in (1..16).rev() {
+            scramble.swap(i, rand(i as u64 + 1) as usize);
+        }
+        let mut unscramble = vec![0; 16];
+        for (s, &w) in scramble.iter().enumerate() {
+            unscramble[w] = s;
+        }
+        let scramble = Wiring(scramble);
+        let mut observed = display
+            .symbols
+            .iter()
+            .map(|&s| scramble.apply(s))
+            .collect::<Vec<_>>();
+        observed.reverse();
+
+        assert_eq!(display.solve(&observed, 10), [Wiring(unscramble)]);
+    }
 }
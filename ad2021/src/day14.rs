@@ -1,5 +1,4 @@
-use std::collections::HashMap;
-
+use adlib::Transition;
 use aoc_runner_derive::*;
 
 type Rules = [[u8; 26]; 26];
@@ -42,57 +41,30 @@ fn parse_input(text: &str) -> anyhow::Result<(Vec<u8>, Rules)> {
 
 type Counts = [u64; 26];
 
-type Cache = HashMap<(u8, u8, usize), Counts>;
-
-fn add(a: &mut Counts, b: &Counts) {
-    for i in 0..26 {
-        a[i] += b[i];
-    }
+// The polymer after some steps, as a count of each adjacent pair. Each pair
+// `ab` with rule `ab -> m` becomes one `am` and one `mb`.
+fn pairs(template: &[u8], rules: &Rules, steps: u64) -> Vec<((u8, u8), u64)> {
+    let t = Transition::new(template.windows(2).map(|w| (w[0], w[1])), |&(a, b)| {
+        let m = rules[a as usize][b as usize];
+        vec![((a, m), 1), ((m, b), 1)]
+    });
+    let start = t.vector(template.windows(2).map(|w| ((w[0], w[1]), 1)));
+    let end = t.advance(&start, steps);
+    t.states().iter().copied().zip(end).collect()
 }
 
-// Count how many of each element exists after expanding the 2-element chain
-// `ab` for the given number of `steps`, **not counting** the `b` at the end.
-//
-// We don't count the last element so that adjacent slices add cleanly.
-fn count(rules: &Rules, cache: &mut Cache, a: u8, b: u8, steps: usize, acc: &mut Counts) {
-    if steps == 0 {
-        acc[a as usize] += 1;
-    } else {
-        let key = (a, b, steps);
-        match cache.get(&key) {
-            Some(result) => add(acc, result),
-            None => {
-                let m = rules[a as usize][b as usize];
-                let mut counts = [0; 26];
-                count(rules, cache, a, m, steps - 1, &mut counts);
-                count(rules, cache, m, b, steps - 1, &mut counts);
-                cache.insert(key, counts);
-                add(acc, &counts);
-            }
-        }
-    }
-}
-
-fn count_all(template: &[u8], rules: &Rules, steps: usize) -> Counts {
+fn count_all(template: &[u8], rules: &Rules, steps: u64) -> Counts {
+    // Every element is the first element of exactly one pair, except the last
+    // one, which never changes.
     let mut counts = [0; 26];
-    let mut cache = HashMap::new();
-    for i in 0..template.len() - 1 {
-        count(
-            rules,
-            &mut cache,
-            template[i],
-            template[i + 1],
-            steps,
-            &mut counts,
-        );
+    for ((a, _), n) in pairs(template, rules, steps) {
+        counts[a as usize] += n;
     }
-
-    // Don't forget to count the last character! See comment on `count`.
     counts[template[template.len() - 1] as usize] += 1;
     counts
 }
 
-fn solve(template: &[u8], rules: &Rules, steps: usize) -> u64 {
+fn solve(template: &[u8], rules: &Rules, steps: u64) -> u64 {
     let counts = count_all(template, rules, steps);
     let max = counts.iter().copied().max().unwrap();
     let min = counts.iter().copied().filter(|n| *n > 0).min().unwrap();
@@ -143,7 +115,7 @@ CN -> C
         c
     }
 
-    fn assert_expansion(template: &[u8], rules: &Rules, steps: usize, expected: &str) {
+    fn assert_expansion(template: &[u8], rules: &Rules, steps: u64, expected: &str) {
         let actual = count_all(template, rules, steps);
         let expected = counts(expected);
         assert_eq!(actual, expected);
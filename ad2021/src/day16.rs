@@ -1,98 +1,226 @@
+use std::fmt;
+
 use aoc_runner_derive::*;
 
 #[aoc_generator(day16, part1, jorendorff)]
 #[aoc_generator(day16, part2, jorendorff)]
-fn parse_input(text: &str) -> Vec<bool> {
-    text.trim()
-        .chars()
-        .flat_map(|c| {
-            let digit = c.to_digit(16).unwrap();
-            (0..4).rev().map(move |bit| (1 << bit) & digit != 0)
-        })
-        .collect()
+fn parse_input(text: &str) -> anyhow::Result<Vec<bool>> {
+    let start = text.len() - text.trim_start().len();
+    let mut bits = vec![];
+    for (i, c) in text.trim().char_indices() {
+        let Some(digit) = c.to_digit(16) else {
+            anyhow::bail!("invalid hex digit {c:?} at position {}", start + i);
+        };
+        bits.extend((0..4).rev().map(|bit| (1 << bit) & digit != 0));
+    }
+    Ok(bits)
 }
 
 #[derive(PartialEq, Debug)]
-struct Packet {
-    version: u32,
-    type_id: u32,
-    payload: Payload,
+pub struct Packet {
+    pub version: u32,
+    pub type_id: u32,
+    pub payload: Payload,
 }
 
 #[derive(PartialEq, Debug)]
-enum Payload {
+pub enum Payload {
     Literal(u64),
     SubPackets(Vec<Packet>),
 }
 
-struct Parser {
-    bits: Vec<bool>,
+#[derive(Clone, PartialEq, Debug)]
+enum DecodeError {
+    /// Ran out of bits at this position.
+    Truncated { pos: usize },
+    /// The literal starting here doesn't fit in 64 bits.
+    LiteralTooBig { pos: usize },
+    /// The sub-packets ended at `pos`, but their parent's header said `end`.
+    LengthMismatch { pos: usize, end: usize },
+    /// Nonzero padding after the outermost packet.
+    TrailingBits { pos: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated { pos } => write!(f, "bit {pos}: unexpected end of input"),
+            DecodeError::LiteralTooBig { pos } => write!(f, "bit {pos}: literal is over 64 bits"),
+            DecodeError::LengthMismatch { pos, end } => {
+                write!(
+                    f,
+                    "bit {pos}: sub-packets were supposed to end at bit {end}"
+                )
+            }
+            DecodeError::TrailingBits { pos } => write!(f, "bit {pos}: nonzero bits after packet"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Why a packet can't be written in the BITS format.
+#[derive(Clone, PartialEq, Debug)]
+pub enum EncodeError {
+    /// A version or type id that doesn't fit in 3 bits.
+    FieldTooBig { field: &'static str, value: u32 },
+    /// Type id and payload disagree about whether this is a literal.
+    Malformed { type_id: u32 },
+    /// An operator with no sub-packets.
+    NoOperands { type_id: u32 },
+    /// Too many sub-packets for the 11-bit count, and too long for the
+    /// 15-bit length.
+    TooManySubPackets(usize),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::FieldTooBig { field, value } => {
+                write!(f, "{field} {value} doesn't fit in 3 bits")
+            }
+            EncodeError::Malformed { type_id } => {
+                write!(f, "type id {type_id} with the wrong payload")
+            }
+            EncodeError::NoOperands { type_id } => {
+                write!(f, "{} packet with no sub-packets", op_name(*type_id))
+            }
+            EncodeError::TooManySubPackets(n) => write!(f, "can't encode {n} sub-packets"),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+#[derive(Clone, PartialEq, Debug)]
+enum EvalError {
+    /// The result of this operator doesn't fit in a u64.
+    Overflow(&'static str),
+    /// This operator got the wrong number of operands.
+    Arity(&'static str, usize),
+    /// Type id and payload disagree about whether this is a literal.
+    Malformed { type_id: u32 },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Overflow(op) => write!(f, "overflow in {op}"),
+            EvalError::Arity(op, n) => write!(f, "{op} can't take {n} operands"),
+            EvalError::Malformed { type_id } => {
+                write!(f, "type id {type_id} with the wrong payload")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+const LITERAL: u32 = 4;
+
+fn op_name(type_id: u32) -> &'static str {
+    match type_id {
+        0 => "sum",
+        1 => "product",
+        2 => "min",
+        3 => "max",
+        4 => "literal",
+        5 => "gt",
+        6 => "lt",
+        7 => "eq",
+        _ => "???",
+    }
+}
+
+struct Parser<'a> {
+    bits: &'a [bool],
     point: usize,
 }
 
-impl Parser {
-    fn parse_packet(bits: &[bool]) -> anyhow::Result<Packet> {
-        let mut parser = Parser {
-            bits: bits.to_vec(),
-            point: 0,
-        };
-        let packet = parser.read_packet();
-        assert!(parser.bits[parser.point..].iter().all(|&bit| !bit));
+impl Parser<'_> {
+    fn parse_packet(bits: &[bool]) -> Result<Packet, DecodeError> {
+        let mut parser = Parser { bits, point: 0 };
+        let packet = parser.read_packet()?;
+        if let Some(i) = bits[parser.point..].iter().position(|&bit| bit) {
+            return Err(DecodeError::TrailingBits {
+                pos: parser.point + i,
+            });
+        }
         Ok(packet)
     }
 
-    fn read(&mut self, nbits: usize) -> u32 {
+    fn read(&mut self, nbits: usize) -> Result<u32, DecodeError> {
         let mut total = 0;
         for _i in 0..nbits {
+            let bit = *self
+                .bits
+                .get(self.point)
+                .ok_or(DecodeError::Truncated { pos: self.point })?;
             total <<= 1;
-            total |= self.bits[self.point] as u32;
+            total |= bit as u32;
             self.point += 1;
         }
-        total
+        Ok(total)
     }
 
-    fn read_packet(&mut self) -> Packet {
-        let version = self.read(3);
-        let type_id = self.read(3);
+    fn read_packet(&mut self) -> Result<Packet, DecodeError> {
+        let version = self.read(3)?;
+        let type_id = self.read(3)?;
         let payload = match type_id {
-            4 => {
+            LITERAL => {
+                let start = self.point;
                 let mut n = 0u64;
                 loop {
-                    let more = self.bits[self.point];
-                    self.point += 1;
+                    let more = self.read(1)? == 1;
+                    if n >> 60 != 0 {
+                        return Err(DecodeError::LiteralTooBig { pos: start });
+                    }
                     n <<= 4;
-                    n |= self.read(4) as u64;
+                    n |= self.read(4)? as u64;
                     if !more {
                         break;
                     }
                 }
                 Payload::Literal(n)
             }
-            _ => match self.read(1) {
+            _ => match self.read(1)? {
                 0 => {
-                    let subpacket_len_bits = self.read(15) as usize;
+                    let subpacket_len_bits = self.read(15)? as usize;
                     let end = self.point + subpacket_len_bits;
                     let mut subpackets = vec![];
                     while self.point < end {
-                        subpackets.push(self.read_packet());
+                        subpackets.push(self.read_packet()?);
+                    }
+                    if self.point != end {
+                        return Err(DecodeError::LengthMismatch {
+                            pos: self.point,
+                            end,
+                        });
                     }
                     Payload::SubPackets(subpackets)
                 }
-                1 => {
-                    let subpacket_count = self.read(11);
-                    Payload::SubPackets((0..subpacket_count).map(|_| self.read_packet()).collect())
+                _ => {
+                    let subpacket_count = self.read(11)?;
+                    Payload::SubPackets(
+                        (0..subpacket_count)
+                            .map(|_| self.read_packet())
+                            .collect::<Result<_, _>>()?,
+                    )
                 }
-                _ => unreachable!(),
             },
         };
-        Packet {
+        Ok(Packet {
             version,
             type_id,
             payload,
-        }
+        })
     }
 }
 
+fn write(out: &mut Vec<bool>, nbits: usize, value: u64) {
+    out.extend((0..nbits).rev().map(|i| (value >> i) & 1 == 1));
+}
+
 impl Packet {
     fn version_sum(&self) -> u32 {
         self.version
@@ -102,66 +230,136 @@ impl Packet {
             }
     }
 
-    fn args(&self) -> impl Iterator<Item = u64> + '_ {
-        match &self.payload {
-            Payload::SubPackets(v) => v.iter().map(Self::eval),
-            _ => unreachable!(),
+    /// Append this packet's bits to `out`. Literals use as few groups as
+    /// possible. Sub-packets are given by total length in bits when that
+    /// fits in 15 bits, by count otherwise.
+    fn write_bits(&self, out: &mut Vec<bool>) -> Result<(), EncodeError> {
+        for (field, value) in [("version", self.version), ("type id", self.type_id)] {
+            if value >= 8 {
+                return Err(EncodeError::FieldTooBig { field, value });
+            }
         }
-    }
-
-    fn eval(&self) -> u64 {
-        match self.type_id {
-            0 => self.args().sum(),
-            1 => self.args().product(),
-            2 => self.args().min().unwrap(),
-            3 => self.args().max().unwrap(),
-            4 => match &self.payload {
-                Payload::Literal(x) => *x,
-                _ => unreachable!(),
-            },
-            5 => {
-                let mut args = self.args();
-                let a = args.next().unwrap();
-                let b = args.next().unwrap();
-                if a > b {
-                    1
-                } else {
-                    0
+        write(out, 3, self.version.into());
+        write(out, 3, self.type_id.into());
+        match (&self.payload, self.type_id) {
+            (Payload::Literal(n), LITERAL) => {
+                let groups = (64 - n.leading_zeros() as usize).div_ceil(4).max(1);
+                for i in (0..groups).rev() {
+                    write(out, 1, (i > 0) as u64);
+                    write(out, 4, n >> (4 * i));
                 }
             }
-            6 => {
-                let mut args = self.args();
-                let a = args.next().unwrap();
-                let b = args.next().unwrap();
-                if a < b {
-                    1
+            (Payload::SubPackets(kids), type_id) if type_id != LITERAL => {
+                if kids.is_empty() {
+                    return Err(EncodeError::NoOperands { type_id });
+                }
+                let mut body = vec![];
+                for kid in kids {
+                    kid.write_bits(&mut body)?;
+                }
+                if body.len() < 1 << 15 {
+                    write(out, 1, 0);
+                    write(out, 15, body.len() as u64);
+                } else if kids.len() < 1 << 11 {
+                    write(out, 1, 1);
+                    write(out, 11, kids.len() as u64);
                 } else {
-                    0
+                    return Err(EncodeError::TooManySubPackets(kids.len()));
                 }
+                out.extend(body);
             }
-            7 => {
-                let mut args = self.args();
-                let a = args.next().unwrap();
-                let b = args.next().unwrap();
-                if a == b {
-                    1
-                } else {
-                    0
+            (_, type_id) => return Err(EncodeError::Malformed { type_id }),
+        }
+        Ok(())
+    }
+
+    /// Encode as hex, padded with zero bits to a whole number of bytes.
+    pub fn to_hex(&self) -> Result<String, EncodeError> {
+        let mut bits = vec![];
+        self.write_bits(&mut bits)?;
+        bits.resize(bits.len().div_ceil(8) * 8, false);
+        Ok(bits
+            .chunks(4)
+            .map(|nibble| {
+                let digit = nibble.iter().fold(0, |acc, &b| (acc << 1) | b as u32);
+                char::from_digit(digit, 16).unwrap().to_ascii_uppercase()
+            })
+            .collect())
+    }
+
+    fn fmt_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = depth * 2;
+        match &self.payload {
+            Payload::Literal(n) => writeln!(f, "{:indent$}{n}", ""),
+            Payload::SubPackets(kids) => {
+                writeln!(f, "{:indent$}{}", "", op_name(self.type_id))?;
+                for kid in kids {
+                    kid.fmt_tree(f, depth + 1)?;
                 }
+                Ok(())
             }
-            _ => unreachable!("bad opcode"),
+        }
+    }
+
+    fn eval(&self) -> Result<u64, EvalError> {
+        let op = op_name(self.type_id);
+        let args = match (&self.payload, self.type_id) {
+            (Payload::Literal(x), LITERAL) => return Ok(*x),
+            (Payload::SubPackets(kids), t) if t != LITERAL => kids
+                .iter()
+                .map(Packet::eval)
+                .collect::<Result<Vec<u64>, _>>()?,
+            _ => {
+                return Err(EvalError::Malformed {
+                    type_id: self.type_id,
+                })
+            }
+        };
+        let overflow = EvalError::Overflow(op);
+        let arity = EvalError::Arity(op, args.len());
+        match self.type_id {
+            0 => args
+                .into_iter()
+                .try_fold(0u64, u64::checked_add)
+                .ok_or(overflow),
+            1 => args
+                .into_iter()
+                .try_fold(1u64, u64::checked_mul)
+                .ok_or(overflow),
+            2 => args.into_iter().min().ok_or(arity),
+            3 => args.into_iter().max().ok_or(arity),
+            _ => match args[..] {
+                [a, b] => Ok(match self.type_id {
+                    5 => a > b,
+                    6 => a < b,
+                    _ => a == b,
+                } as u64),
+                _ => Err(arity),
+            },
         }
     }
 }
 
+/// The expression tree, one operator or literal per line.
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_tree(f, 0)
+    }
+}
+
+/// Decode one packet from hex.
+pub fn decode(hex: &str) -> anyhow::Result<Packet> {
+    Ok(Parser::parse_packet(&parse_input(hex)?)?)
+}
+
 #[aoc(day16, part1, jorendorff)]
-fn part_1(bits: &[bool]) -> u32 {
-    Parser::parse_packet(bits).unwrap().version_sum()
+fn part_1(bits: &[bool]) -> anyhow::Result<u32> {
+    Ok(Parser::parse_packet(bits)?.version_sum())
 }
 
 #[aoc(day16, part2, jorendorff)]
-fn part_2(bits: &[bool]) -> u64 {
-    Parser::parse_packet(bits).unwrap().eval()
+fn part_2(bits: &[bool]) -> anyhow::Result<u64> {
+    Ok(Parser::parse_packet(bits)?.eval()?)
 }
 
 #[cfg(test)]
@@ -171,7 +369,7 @@ mod tests {
     #[test]
     fn test_part_1() {
         assert_eq!(
-            Parser::parse_packet(&parse_input("D2FE28")).unwrap(),
+            Parser::parse_packet(&parse_input("D2FE28").unwrap()).unwrap(),
             Packet {
                 version: 6,
                 type_id: 4,
@@ -179,7 +377,7 @@ mod tests {
             }
         );
         assert_eq!(
-            Parser::parse_packet(&parse_input("EE00D40C823060")).unwrap(),
+            Parser::parse_packet(&parse_input("EE00D40C823060").unwrap()).unwrap(),
             Packet {
                 version: 7,
                 type_id: 3,
@@ -202,21 +400,195 @@ mod tests {
                 ])
             }
         );
-        assert_eq!(part_1(&parse_input("8A004A801A8002F478")), 16);
-        assert_eq!(part_1(&parse_input("620080001611562C8802118E34")), 12);
-        assert_eq!(part_1(&parse_input("C0015000016115A2E0802F182340")), 23);
-        assert_eq!(part_1(&parse_input("A0016C880162017C3686B18A3D4780")), 31);
+        assert_eq!(
+            part_1(&parse_input("8A004A801A8002F478").unwrap()).unwrap(),
+            16
+        );
+        assert_eq!(
+            part_1(&parse_input("620080001611562C8802118E34").unwrap()).unwrap(),
+            12
+        );
+        assert_eq!(
+            part_1(&parse_input("C0015000016115A2E0802F182340").unwrap()).unwrap(),
+            23
+        );
+        assert_eq!(
+            part_1(&parse_input("A0016C880162017C3686B18A3D4780").unwrap()).unwrap(),
+            31
+        );
     }
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input("C200B40A82")), 3);
-        assert_eq!(part_2(&parse_input("04005AC33890")), 54);
-        assert_eq!(part_2(&parse_input("880086C3E88112")), 7);
-        assert_eq!(part_2(&parse_input("CE00C43D881120")), 9);
-        assert_eq!(part_2(&parse_input("D8005AC2A8F0")), 1);
-        assert_eq!(part_2(&parse_input("F600BC2D8F")), 0);
-        assert_eq!(part_2(&parse_input("9C005AC2F8F0")), 0);
-        assert_eq!(part_2(&parse_input("9C0141080250320F1802104A08")), 1);
+        assert_eq!(part_2(&parse_input("C200B40A82").unwrap()).unwrap(), 3);
+        assert_eq!(part_2(&parse_input("04005AC33890").unwrap()).unwrap(), 54);
+        assert_eq!(part_2(&parse_input("880086C3E88112").unwrap()).unwrap(), 7);
+        assert_eq!(part_2(&parse_input("CE00C43D881120").unwrap()).unwrap(), 9);
+        assert_eq!(part_2(&parse_input("D8005AC2A8F0").unwrap()).unwrap(), 1);
+        assert_eq!(part_2(&parse_input("F600BC2D8F").unwrap()).unwrap(), 0);
+        assert_eq!(part_2(&parse_input("9C005AC2F8F0").unwrap()).unwrap(), 0);
+        assert_eq!(
+            part_2(&parse_input("9C0141080250320F1802104A08").unwrap()).unwrap(),
+            1
+        );
+    }
+
+    const EXAMPLES: [&str; 15] = [
+        "D2FE28",
+        "38006F45291200",
+        "EE00D40C823060",
+        "8A004A801A8002F478",
+        "620080001611562C8802118E34",
+        "C0015000016115A2E0802F182340",
+        "A0016C880162017C3686B18A3D4780",
+        "C200B40A82",
+        "04005AC33890",
+        "880086C3E88112",
+        "CE00C43D881120",
+        "D8005AC2A8F0",
+        "F600BC2D8F",
+        "9C005AC2F8F0",
+        "9C0141080250320F1802104A08",
+    ];
+
+    #[test]
+    fn test_round_trip() {
+        for hex in EXAMPLES {
+            let packet = decode(hex).unwrap();
+            assert_eq!(decode(&packet.to_hex().unwrap()).unwrap(), packet);
+        }
+        // These examples happen to be encoded the same way we do it.
+        for hex in ["D2FE28", "38006F45291200", "04005AC33890", "9C005AC2F8F0"] {
+            assert_eq!(decode(hex).unwrap().to_hex().unwrap(), hex);
+        }
+
+        let big = Packet {
+            version: 5,
+            type_id: LITERAL,
+            payload: Payload::Literal(u64::MAX),
+        };
+        assert_eq!(decode(&big.to_hex().unwrap()).unwrap(), big);
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let lit = |n| Packet {
+            version: 0,
+            type_id: LITERAL,
+            payload: Payload::Literal(n),
+        };
+        let op = |type_id, kids| Packet {
+            version: 0,
+            type_id,
+            payload: Payload::SubPackets(kids),
+        };
+        assert_eq!(
+            Packet {
+                version: 8,
+                ..lit(1)
+            }
+            .to_hex(),
+            Err(EncodeError::FieldTooBig {
+                field: "version",
+                value: 8
+            })
+        );
+        assert_eq!(
+            op(9, vec![lit(1)]).to_hex(),
+            Err(EncodeError::FieldTooBig {
+                field: "type id",
+                value: 9
+            })
+        );
+        assert_eq!(
+            op(LITERAL, vec![lit(1)]).to_hex(),
+            Err(EncodeError::Malformed { type_id: 4 })
+        );
+        assert_eq!(
+            Packet {
+                type_id: 0,
+                ..lit(1)
+            }
+            .to_hex(),
+            Err(EncodeError::Malformed { type_id: 0 })
+        );
+        assert_eq!(
+            op(0, vec![op(1, vec![])]).to_hex(),
+            Err(EncodeError::NoOperands { type_id: 1 })
+        );
+        // 2048 sub-packets are too long for a length and too many for a
+        // count.
+        let many = op(0, (0..1 << 11).map(lit).collect());
+        assert_eq!(many.to_hex(), Err(EncodeError::TooManySubPackets(2048)));
+        let fewer = op(0, (0..(1 << 11) - 1).map(lit).collect());
+        assert_eq!(decode(&fewer.to_hex().unwrap()).unwrap(), fewer);
+    }
+
+    #[test]
+    fn test_bad_hex() {
+        assert_eq!(
+            parse_input("  D2FG28\n").unwrap_err().to_string(),
+            "invalid hex digit 'G' at position 5"
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let parse = |hex| Parser::parse_packet(&parse_input(hex).unwrap());
+        assert_eq!(parse("D2FE"), Err(DecodeError::Truncated { pos: 16 }));
+        assert_eq!(parse("D2FE29"), Err(DecodeError::TrailingBits { pos: 23 }));
+        // A literal with 17 groups of 1111.
+        let hex = format!(
+            "{:X}",
+            (0..17).fold(0b110100u128, |acc, _| (acc << 5) | 0b11111) << 9
+        );
+        assert_eq!(parse(&hex), Err(DecodeError::LiteralTooBig { pos: 6 }));
+        // 38006F45291200 says its sub-packets are 27 bits long. Say 26 instead.
+        assert_eq!(
+            parse("38006B45291200"),
+            Err(DecodeError::LengthMismatch { pos: 49, end: 48 })
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let packet =
+            Parser::parse_packet(&parse_input("9C0141080250320F1802104A08").unwrap()).unwrap();
+        assert_eq!(
+            packet.to_string(),
+            "eq\n  sum\n    1\n    3\n  product\n    2\n    2\n"
+        );
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let lit = |n| Packet {
+            version: 0,
+            type_id: LITERAL,
+            payload: Payload::Literal(n),
+        };
+        let op = |type_id, kids| Packet {
+            version: 0,
+            type_id,
+            payload: Payload::SubPackets(kids),
+        };
+        assert_eq!(
+            op(1, vec![lit(1 << 40), lit(1 << 30)]).eval(),
+            Err(EvalError::Overflow("product"))
+        );
+        assert_eq!(op(0, vec![lit(u64::MAX), lit(0)]).eval(), Ok(u64::MAX));
+        assert_eq!(op(2, vec![]).eval(), Err(EvalError::Arity("min", 0)));
+        assert_eq!(
+            op(7, vec![lit(1), lit(1), lit(1)]).eval(),
+            Err(EvalError::Arity("eq", 3))
+        );
+        assert_eq!(
+            op(4, vec![]).eval(),
+            Err(EvalError::Malformed { type_id: 4 })
+        );
+
+        let packet = op(0, vec![lit(u64::MAX), lit(1)]);
+        let err = part_2(&parse_input(&packet.to_hex().unwrap()).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "overflow in sum");
     }
 }
@@ -23,6 +23,13 @@ impl Number {
         }
     }
 
+    fn depth(&self) -> usize {
+        match self {
+            Number::Regular(_) => 0,
+            Number::Pair(left, right) => 1 + left.depth().max(right.depth()),
+        }
+    }
+
     fn reduce(&mut self) {
         self.explode(0);
         while self.split_once() {
@@ -93,6 +100,194 @@ impl Number {
     }
 }
 
+/// A snailfish number stored as its leaves, left to right, each with the
+/// number of pairs around it. `[[1,2],3]` is `[(1, 2), (2, 2), (3, 1)]`.
+/// Reducing only ever touches neighboring leaves, so it needs no tree.
+#[derive(Clone, Default, PartialEq)]
+struct Flat {
+    leaves: Vec<Leaf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Leaf {
+    value: i64,
+    depth: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Step {
+    Explode,
+    Split,
+}
+
+impl Flat {
+    fn from_tree(num: &Number) -> Self {
+        fn walk(num: &Number, depth: u8, out: &mut Vec<Leaf>) {
+            match num {
+                Number::Regular(value) => out.push(Leaf {
+                    value: *value,
+                    depth,
+                }),
+                Number::Pair(left, right) => {
+                    walk(left, depth + 1, out);
+                    walk(right, depth + 1, out);
+                }
+            }
+        }
+        let mut leaves = vec![];
+        walk(num, 0, &mut leaves);
+        Flat { leaves }
+    }
+
+    fn magnitude(&self) -> i64 {
+        let mut stack: Vec<Leaf> = Vec::with_capacity(8);
+        for &leaf in &self.leaves {
+            stack.push(leaf);
+            while let [.., a, b] = stack[..] {
+                if a.depth != b.depth {
+                    break;
+                }
+                stack.truncate(stack.len() - 2);
+                stack.push(Leaf {
+                    value: 3 * a.value + 2 * b.value,
+                    depth: a.depth - 1,
+                });
+            }
+        }
+        stack[0].value
+    }
+
+    /// Explode the pair whose left half is at index `i`.
+    fn explode_at(&mut self, i: usize) {
+        let Leaf { value: left, depth } = self.leaves[i];
+        let right = self.leaves[i + 1].value;
+        if i > 0 {
+            self.leaves[i - 1].value += left;
+        }
+        if let Some(next) = self.leaves.get_mut(i + 2) {
+            next.value += right;
+        }
+        self.leaves[i] = Leaf {
+            value: 0,
+            depth: depth - 1,
+        };
+        self.leaves.remove(i + 1);
+    }
+
+    /// Reduce, calling `on_step` after each action.
+    ///
+    /// The puzzle says to start over from the left after every action, but
+    /// there's no need. Explosions never make new deeply nested pairs, so one
+    /// pass explodes them all. After that, a split can only make a deep pair
+    /// where it splits, and exploding that only changes its neighbors.
+    fn reduce_with(&mut self, mut on_step: impl FnMut(Step, &Flat)) {
+        let mut i = 0;
+        while i < self.leaves.len() {
+            if self.leaves[i].depth > 4 {
+                self.explode_at(i);
+                on_step(Step::Explode, self);
+            }
+            i += 1;
+        }
+
+        let mut i = 0;
+        while i < self.leaves.len() {
+            let Leaf { value, depth } = self.leaves[i];
+            if value < 10 {
+                i += 1;
+                continue;
+            }
+            let half = Leaf {
+                value: value / 2,
+                depth: depth + 1,
+            };
+            self.leaves[i] = half;
+            self.leaves.insert(
+                i + 1,
+                Leaf {
+                    value: value - value / 2,
+                    ..half
+                },
+            );
+            on_step(Step::Split, self);
+            if half.depth > 4 {
+                self.explode_at(i);
+                on_step(Step::Explode, self);
+                i = i.saturating_sub(1);
+            }
+        }
+    }
+
+    fn reduce(&mut self) {
+        self.reduce_with(|_, _| {});
+    }
+
+    /// Replace `self` with the unreduced sum `self + other`.
+    fn push_pair(&mut self, other: &Flat) {
+        self.leaves.extend_from_slice(&other.leaves);
+        for leaf in &mut self.leaves {
+            leaf.depth += 1;
+        }
+    }
+
+    fn add(&mut self, other: &Flat) {
+        self.push_pair(other);
+        self.reduce();
+    }
+
+    /// Add, returning the puzzle's step-by-step account of the reduction.
+    fn add_traced(&mut self, other: &Flat) -> Vec<String> {
+        self.push_pair(other);
+        let mut trace = vec![format!("after addition: {self:?}")];
+        self.reduce_with(|step, num| {
+            trace.push(match step {
+                Step::Explode => format!("after explode:  {num:?}"),
+                Step::Split => format!("after split:    {num:?}"),
+            });
+        });
+        trace
+    }
+}
+
+impl std::fmt::Debug for Flat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write(
+            f: &mut std::fmt::Formatter<'_>,
+            leaves: &[Leaf],
+            i: &mut usize,
+            depth: u8,
+        ) -> std::fmt::Result {
+            let leaf = leaves[*i];
+            if leaf.depth == depth {
+                *i += 1;
+                write!(f, "{}", leaf.value)
+            } else {
+                write!(f, "[")?;
+                write(f, leaves, i, depth + 1)?;
+                write!(f, ",")?;
+                write(f, leaves, i, depth + 1)?;
+                write!(f, "]")
+            }
+        }
+        write(f, &self.leaves, &mut 0, 0)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct ParseError {
+    /// Byte offset into the line.
+    pos: usize,
+    expected: &'static str,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at column {}: expected {}", self.pos + 1, self.expected)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 struct Parser<'a> {
     text: &'a str,
     point: usize,
@@ -107,58 +302,122 @@ impl<'a> Parser<'a> {
         self.point == self.text.len()
     }
 
-    fn parse_number(&mut self) -> anyhow::Result<Number> {
-        anyhow::ensure!(!self.at_end());
+    fn expect(&mut self, s: &'static str) -> Result<(), ParseError> {
+        if !self.looking_at(s) {
+            return Err(ParseError {
+                pos: self.point,
+                expected: s,
+            });
+        }
+        self.point += s.len();
+        Ok(())
+    }
+
+    fn parse_number(&mut self) -> Result<Number, ParseError> {
         if self.looking_at("[") {
             self.point += 1;
             let lhs = Box::new(self.parse_number()?);
-            anyhow::ensure!(self.looking_at(","));
-            self.point += 1;
+            self.expect(",")?;
             let rhs = Box::new(self.parse_number()?);
-            anyhow::ensure!(self.looking_at("]"));
-            self.point += 1;
+            self.expect("]")?;
             Ok(Number::Pair(lhs, rhs))
         } else {
-            let mut j = self.point;
-            while let Some(next_ch) = self.text[j..].chars().next() {
-                if !next_ch.is_ascii_digit() {
-                    break;
-                }
-                j += 1;
-            }
-            let n = self.text[self.point..j].parse::<i64>()?;
-            self.point = j;
+            let digits = self.text[self.point..]
+                .bytes()
+                .take_while(u8::is_ascii_digit)
+                .count();
+            let error = ParseError {
+                pos: self.point,
+                expected: "`[` or a number",
+            };
+            let n = self.text[self.point..self.point + digits]
+                .parse::<i64>()
+                .map_err(|_| error)?;
+            self.point += digits;
             Ok(Number::Regular(n))
         }
     }
 }
 
-fn parse_number(s: &str) -> anyhow::Result<Number> {
+fn parse_number(s: &str) -> Result<Number, ParseError> {
     let mut parser = Parser { text: s, point: 0 };
     let num = parser.parse_number()?;
-    anyhow::ensure!(parser.at_end());
+    if !parser.at_end() {
+        return Err(ParseError {
+            pos: parser.point,
+            expected: "end of line",
+        });
+    }
     Ok(num)
 }
 
 #[aoc_generator(day18, part1, jorendorff)]
 #[aoc_generator(day18, part2, jorendorff)]
-fn parse_input(text: &str) -> anyhow::Result<Vec<Number>> {
-    text.lines().map(parse_number).collect()
+fn parse_input(text: &str) -> anyhow::Result<Vec<Flat>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            parse_reduced(line).map_err(|err| anyhow::anyhow!("line {}: {err}", i + 1))
+        })
+        .collect()
 }
 
-fn sum(nums: impl IntoIterator<Item = Number>) -> Number {
-    let mut nums = nums.into_iter();
-    let first = nums.next().unwrap();
-    nums.fold(first, |acc, next| acc.add(next))
+/// Parse a number that's ready to be added.
+fn parse_reduced(s: &str) -> anyhow::Result<Flat> {
+    let num = parse_number(s)?;
+    // Reduction only handles pairs one level too deep, as made by adding two
+    // reduced numbers.
+    anyhow::ensure!(
+        num.depth() <= 4,
+        "nested more than 4 deep; only reduced numbers can be added"
+    );
+    Ok(Flat::from_tree(&num))
+}
+
+/// Add two snailfish numbers, returning each step of the reduction the way
+/// the puzzle shows it.
+pub fn reduction_trace(a: &str, b: &str) -> anyhow::Result<Vec<String>> {
+    Ok(parse_reduced(a)?.add_traced(&parse_reduced(b)?))
+}
+
+#[aoc_generator(day18, part2, tree)]
+fn parse_tree_input(text: &str) -> anyhow::Result<Vec<Number>> {
+    Ok(text.lines().map(parse_number).collect::<Result<_, _>>()?)
+}
+
+fn sum(nums: &[Flat]) -> Flat {
+    let mut total = nums[0].clone();
+    for num in &nums[1..] {
+        total.add(num);
+    }
+    total
 }
 
 #[aoc(day18, part1, jorendorff)]
-fn part_1(input: &Vec<Number>) -> i64 {
-    sum(input.clone()).magnitude()
+fn part_1(input: &[Flat]) -> i64 {
+    sum(input).magnitude()
 }
 
 #[aoc(day18, part2, jorendorff)]
-fn part_2(input: &Vec<Number>) -> i64 {
+fn part_2(input: &[Flat]) -> i64 {
+    // One buffer for all the sums, so no allocation after the first few.
+    let mut total = Flat::default();
+    let mut best = 0;
+    for (i, a) in input.iter().enumerate() {
+        for (j, b) in input.iter().enumerate() {
+            if i != j {
+                total.leaves.clone_from(&a.leaves);
+                total.add(b);
+                best = best.max(total.magnitude());
+            }
+        }
+    }
+    best
+}
+
+/// The original tree-based version, kept to compare with `cargo aoc bench`.
+#[aoc(day18, part2, tree)]
+fn part_2_tree(input: &[Number]) -> i64 {
     (0..input.len())
         .flat_map(|i| {
             (0..input.len()).filter_map(move |j| {
@@ -181,6 +440,7 @@ mod tests {
     fn check_parse_round_trip(s: &str) {
         let num = parse_number(s).unwrap();
         assert_eq!(format!("{:?}", num), s);
+        assert_eq!(format!("{:?}", Flat::from_tree(&num)), s);
     }
 
     #[test]
@@ -198,6 +458,9 @@ mod tests {
     fn check_add(s1: &str, s2: &str, expected: &str) {
         let num1 = parse_number(s1).unwrap();
         let num2 = parse_number(s2).unwrap();
+        let mut flat = Flat::from_tree(&num1);
+        flat.add(&Flat::from_tree(&num2));
+        assert_eq!(format!("{:?}", flat), expected);
         let actual = num1.add(num2);
         assert_eq!(format!("{:?}", actual), expected);
     }
@@ -215,7 +478,7 @@ mod tests {
     #[track_caller]
     fn check_sum(lines: &str, expected: &str) {
         let nums = parse_input(lines).unwrap();
-        let total = sum(nums);
+        let total = sum(&nums);
         assert_eq!(format!("{:?}", total), expected);
     }
 
@@ -271,6 +534,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_errors() {
+        let err = |pos, expected| Some(ParseError { pos, expected });
+        assert_eq!(parse_number("").err(), err(0, "`[` or a number"));
+        assert_eq!(parse_number("[1,2").err(), err(4, "]"));
+        assert_eq!(parse_number("[[1 2],3]").err(), err(3, ","));
+        assert_eq!(parse_number("[1,x]").err(), err(3, "`[` or a number"));
+        assert_eq!(parse_number("[1,2]]").err(), err(5, "end of line"));
+        let msg = parse_input("[1,2]\n[3,4\n").unwrap_err().to_string();
+        assert_eq!(msg, "line 2: at column 5: expected ]");
+        let msg = parse_input("[1,2]\n[[[[[1,2],3],4],5],6]\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            msg,
+            "line 2: nested more than 4 deep; only reduced numbers can be added"
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let trace = reduction_trace("[[[[4,3],4],4],[7,[[8,4],9]]]", "[1,1]").unwrap();
+        assert_eq!(
+            trace,
+            [
+                "after addition: [[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]",
+                "after explode:  [[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+                "after explode:  [[[[0,7],4],[15,[0,13]]],[1,1]]",
+                "after split:    [[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+                "after split:    [[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+                "after explode:  [[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+            ]
+        );
+    }
+
     #[test]
     fn test_magnitude() {
         assert_eq!(parse_number("[9,1]").unwrap().magnitude(), 29);
@@ -331,5 +629,6 @@ mod tests {
     #[test]
     fn test_part_2() {
         assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 3993);
+        assert_eq!(part_2_tree(&parse_tree_input(EXAMPLE).unwrap()), 3993);
     }
 }
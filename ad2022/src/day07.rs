@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
 
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
@@ -12,8 +13,6 @@ use Listing::*;
 
 #[derive(Debug)]
 enum Command {
-    CdRoot,
-    CdUp,
     Cd(String),
     Ls(Vec<Listing>),
 }
@@ -25,9 +24,7 @@ type InputLine = Command;
 #[aoc_generator(day7, part2, jorendorff)]
 fn parse_input(text: &str) -> anyhow::Result<Vec<InputLine>> {
     let p = parser!({
-        line("$ cd /") => CdRoot,
-        line("$ cd ..") => CdUp,
-        d: line("$ cd " string(any_char+)) => Cd(d),
+        path: line("$ cd " string(any_char+)) => Cd(path),
         line("$ ls") output:lines({
             size:u64 " " name:string(any_char+) => LsFile(name, size),
             "dir " name:string(any_char+) => LsDir(name),
@@ -36,115 +33,247 @@ fn parse_input(text: &str) -> anyhow::Result<Vec<InputLine>> {
     Ok(p.parse(text)?)
 }
 
-#[derive(Debug, Default)]
+fn path_str(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+/// Where `cd path` goes from `cwd`. Like a real shell, `..` at the root stays
+/// at the root.
+fn resolve(cwd: &[String], path: &str) -> Vec<String> {
+    let mut out = if path.starts_with('/') {
+        vec![]
+    } else {
+        cwd.to_vec()
+    };
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                out.pop();
+            }
+            name => out.push(name.to_string()),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 struct Dir {
-    dirs: HashMap<String, Dir>,
-    files: HashMap<String, u64>,
-    total_file_size: u64,
-    deep_total_size: u64,
+    dirs: BTreeMap<String, Dir>,
+    files: BTreeMap<String, u64>,
 }
 
 impl Dir {
-    fn new() -> Dir {
-        Dir::default()
+    /// The directory at `path`, creating it and any missing parents.
+    fn dir_mut(&mut self, path: &[String]) -> anyhow::Result<&mut Dir> {
+        let mut dir = self;
+        for (i, name) in path.iter().enumerate() {
+            anyhow::ensure!(
+                !dir.files.contains_key(name),
+                "{} is a file, not a directory",
+                path_str(&path[..=i])
+            );
+            dir = dir.dirs.entry(name.clone()).or_default();
+        }
+        Ok(dir)
     }
 
-    fn get_mut(&mut self, path: &[String]) -> &mut Dir {
+    fn get(&self, path: &[String]) -> Option<&Dir> {
         let mut dir = self;
-        for p in path {
-            dir = dir
-                .dirs
-                .get_mut(p)
-                .unwrap_or_else(|| panic!("failed to get path {path:?}"));
+        for name in path {
+            dir = dir.dirs.get(name)?;
         }
-        dir
+        Some(dir)
     }
 
-    fn compute_sizes(&mut self) -> u64 {
-        let mut t = self.total_file_size;
-        for d in self.dirs.values_mut() {
-            t += d.compute_sizes();
+    /// Record one line of `ls` output for the directory at `path`.
+    fn add(&mut self, path: &[String], listing: &Listing) -> anyhow::Result<()> {
+        let dir = self.dir_mut(path)?;
+        let (name, is_dir) = match listing {
+            LsDir(name) => (name, true),
+            LsFile(name, _) => (name, false),
+        };
+        let full = || path_str(&[path, std::slice::from_ref(name)].concat());
+        let clash = if is_dir {
+            dir.files.contains_key(name)
+        } else {
+            dir.dirs.contains_key(name)
+        };
+        anyhow::ensure!(
+            !clash,
+            "{} is listed as both a file and a directory",
+            full()
+        );
+        match listing {
+            LsDir(name) => {
+                dir.dirs.entry(name.clone()).or_default();
+            }
+            LsFile(name, size) => {
+                if let Some(&old) = dir.files.get(name) {
+                    anyhow::ensure!(
+                        old == *size,
+                        "{} is listed with sizes {old} and {size}",
+                        full()
+                    );
+                }
+                dir.files.insert(name.clone(), *size);
+            }
         }
-        self.deep_total_size += t;
-        t
+        Ok(())
     }
 
-    fn part1_sum(&self) -> u64 {
-        let mut t = 0;
-        if self.deep_total_size <= 100000 {
-            t += self.deep_total_size;
+    fn size(&self) -> u64 {
+        self.files.values().sum::<u64>() + self.dirs.values().map(Dir::size).sum::<u64>()
+    }
+
+    /// Every directory's path and total size, children before parents.
+    fn du(&self) -> Vec<(String, u64)> {
+        fn walk(dir: &Dir, path: &mut Vec<String>, out: &mut Vec<(String, u64)>) -> u64 {
+            let mut total = dir.files.values().sum::<u64>();
+            for (name, sub) in &dir.dirs {
+                path.push(name.clone());
+                total += walk(sub, path, out);
+                path.pop();
+            }
+            out.push((path_str(path), total));
+            total
         }
-        for d in self.dirs.values() {
-            t += d.part1_sum();
+
+        let mut out = vec![];
+        walk(self, &mut vec![], &mut out);
+        out
+    }
+
+    /// Names in this directory, sorted, each with `Some(size)` for files.
+    fn entries(&self) -> Vec<(&String, Option<u64>)> {
+        let mut entries: Vec<(&String, Option<u64>)> = self
+            .dirs
+            .keys()
+            .map(|name| (name, None))
+            .chain(self.files.iter().map(|(name, &size)| (name, Some(size))))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// The tree, drawn the way the puzzle does it.
+    fn tree(&self) -> String {
+        fn walk(dir: &Dir, depth: usize, out: &mut String) {
+            for (name, size) in dir.entries() {
+                let indent = "  ".repeat(depth);
+                match size {
+                    None => {
+                        writeln!(out, "{indent}- {name} (dir)").unwrap();
+                        walk(&dir.dirs[name], depth + 1, out);
+                    }
+                    Some(size) => writeln!(out, "{indent}- {name} (file, size={size})").unwrap(),
+                }
+            }
         }
-        t
+
+        let mut out = "- / (dir)\n".to_string();
+        walk(self, 1, &mut out);
+        out
     }
 
-    fn all_dir_sizes(&self, out: &mut Vec<u64>) {
-        for d in self.dirs.values() {
-            d.all_dir_sizes(out);
+    /// A shell session that lists every directory, depth first.
+    #[cfg(test)]
+    fn transcript(&self) -> String {
+        fn walk(dir: &Dir, out: &mut String) {
+            out.push_str("$ ls\n");
+            for (name, size) in dir.entries() {
+                match size {
+                    None => writeln!(out, "dir {name}").unwrap(),
+                    Some(size) => writeln!(out, "{size} {name}").unwrap(),
+                }
+            }
+            for (name, sub) in &dir.dirs {
+                writeln!(out, "$ cd {name}").unwrap();
+                walk(sub, out);
+                out.push_str("$ cd ..\n");
+            }
         }
-        out.push(self.deep_total_size);
+
+        let mut out = "$ cd /\n".to_string();
+        walk(self, &mut out);
+        out
     }
 }
 
-fn build(input: &[InputLine]) -> Dir {
-    let mut root = Dir::new();
+fn build(input: &[InputLine]) -> anyhow::Result<Dir> {
+    let mut root = Dir::default();
     let mut cwd = vec![];
+    // Directories we've seen `ls` output for. Once a directory is listed, we
+    // know all its subdirectories, so `cd` can't go anywhere else.
+    let mut listed = BTreeSet::new();
     for cmd in input {
         match cmd {
-            CdRoot => {
-                cwd.clear();
-            }
-            CdUp => {
-                cwd.pop();
-            }
-            Cd(name) => {
-                cwd.push(name.clone());
+            Cd(path) => {
+                cwd = resolve(&cwd, path);
+                for i in 0..cwd.len() {
+                    let (parent, name) = (&cwd[..i], &cwd[i]);
+                    let missing = listed.contains(parent)
+                        && root.get(parent).is_some_and(|dir| {
+                            !dir.dirs.contains_key(name) && !dir.files.contains_key(name)
+                        });
+                    anyhow::ensure!(
+                        !missing,
+                        "{} isn't in the listing of {}",
+                        path_str(&cwd[..=i]),
+                        path_str(parent)
+                    );
+                }
+                root.dir_mut(&cwd)?;
             }
             Ls(output) => {
-                let d = root.get_mut(&cwd);
                 for listing in output {
-                    match listing {
-                        LsDir(s) => {
-                            d.dirs.insert(s.to_string(), Dir::new());
-                        }
-                        LsFile(name, size) => {
-                            d.total_file_size -= d.files.get(name).copied().unwrap_or(0);
-                            d.files.insert(name.to_string(), *size);
-                            d.total_file_size += *size;
-                        }
-                    }
+                    root.add(&cwd, listing)?;
                 }
+                listed.insert(cwd.clone());
             }
         }
     }
-    root.compute_sizes();
-    root
+    Ok(root)
+}
+
+/// Draw the tree built from a shell transcript the way the puzzle does.
+pub fn tree(text: &str) -> anyhow::Result<String> {
+    Ok(build(&parse_input(text)?)?.tree())
 }
 
 #[aoc(day7, part1, jorendorff)]
-fn part_1(input: &[InputLine]) -> u64 {
-    build(input).part1_sum()
+fn part_1(input: &[InputLine]) -> anyhow::Result<u64> {
+    Ok(build(input)?
+        .du()
+        .into_iter()
+        .map(|(_, size)| size)
+        .filter(|&size| size <= 100000)
+        .sum())
 }
 
 #[aoc(day7, part2, jorendorff)]
-fn part_2(input: &[InputLine]) -> u64 {
+fn part_2(input: &[InputLine]) -> anyhow::Result<u64> {
     let disk_size = 70000000;
     let required_size = 30000000;
 
-    let d = build(input);
-    assert!(d.deep_total_size < disk_size);
-    assert!(disk_size - d.deep_total_size < required_size);
-    let enough = required_size + d.deep_total_size - disk_size;
+    let root = build(input)?;
+    let used = root.size();
+    anyhow::ensure!(
+        used <= disk_size,
+        "{used} bytes used on a {disk_size}-byte disk"
+    );
+    if used + required_size <= disk_size {
+        return Ok(0);
+    }
+    let enough = used + required_size - disk_size;
 
-    let mut sizes = vec![];
-    d.all_dir_sizes(&mut sizes);
-    sizes
+    Ok(root
+        .du()
         .into_iter()
-        .filter(|size| *size >= enough)
+        .map(|(_, size)| size)
+        .filter(|&size| size >= enough)
         .min()
-        .unwrap_or(0)
+        .unwrap_or(0))
 }
 
 #[cfg(test)]
@@ -179,11 +308,135 @@ $ ls
 
     #[test]
     fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 95437);
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), 95437);
     }
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 24933642);
+        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()).unwrap(), 24933642);
+    }
+
+    #[test]
+    fn test_tree() {
+        assert_eq!(
+            tree(EXAMPLE).unwrap(),
+            "\
+- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+        );
+        let root = build(&parse_input(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(
+            root.du(),
+            vec![
+                ("/a/e".to_string(), 584),
+                ("/a".to_string(), 94853),
+                ("/d".to_string(), 24933642),
+                ("/".to_string(), 48381165),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paths() {
+        let cwd = resolve(&[], "a/e");
+        assert_eq!(cwd, ["a", "e"]);
+        assert_eq!(resolve(&cwd, "../../d"), ["d"]);
+        assert_eq!(resolve(&cwd, "/d/./x/.."), ["d"]);
+        assert_eq!(resolve(&cwd, "/"), Vec::<String>::new());
+        assert_eq!(resolve(&[], ".."), Vec::<String>::new());
+
+        let text = "\
+$ cd /a/e
+$ ls
+584 i
+$ cd ../../d
+$ ls
+4060174 j
+$ cd /
+$ ls
+dir a
+dir d
+";
+        let root = build(&parse_input(text).unwrap()).unwrap();
+        assert_eq!(root.get(&resolve(&[], "a/e")).unwrap().files["i"], 584);
+        assert_eq!(root.size(), 584 + 4060174);
+        assert!(root.get(&resolve(&[], "a/e/i")).is_none());
+    }
+
+    #[test]
+    fn test_inconsistent() {
+        let err = |text: &str| build(&parse_input(text).unwrap()).unwrap_err().to_string();
+        assert_eq!(
+            err("$ cd /\n$ ls\n1 x\n$ cd /\n$ ls\n2 x\n"),
+            "/x is listed with sizes 1 and 2"
+        );
+        assert_eq!(
+            err("$ cd /a\n$ ls\n1 x\n$ cd x\n"),
+            "/a/x is a file, not a directory"
+        );
+        assert_eq!(
+            err("$ cd /\n$ ls\ndir x\n5 x\n"),
+            "/x is listed as both a file and a directory"
+        );
+        assert_eq!(
+            err("$ cd /\n$ ls\ndir a\n$ cd b\n"),
+            "/b isn't in the listing of /"
+        );
+        assert_eq!(
+            err("$ cd /a\n$ ls\n1 f\n$ cd /\n$ ls\ndir a\n$ cd /a/b/c\n"),
+            "/a/b isn't in the listing of /a"
+        );
+        assert!(build(&parse_input("$ cd /\n$ ls\n1 x\n$ ls\n1 x\n").unwrap()).is_ok());
+        // Directories nobody has listed yet can be entered freely.
+        assert!(
+            build(&parse_input("$ cd /a/b\n$ ls\n1 f\n$ cd /\n$ ls\ndir a\n").unwrap()).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let root = build(&parse_input(EXAMPLE).unwrap()).unwrap();
+        let again = build(&parse_input(&root.transcript()).unwrap()).unwrap();
+        assert_eq!(again, root);
+
+        let mut seed = 11u64;
+        let mut rand = move |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        for _ in 0..20 {
+            let mut root = Dir::default();
+            let mut dirs = vec![vec![]];
+            for i in 0..rand(40) {
+                let parent = dirs[rand(dirs.len() as u64) as usize].clone();
+                let name = format!("{}{i}", ["a", "b.txt", "q"][rand(3) as usize]);
+                if rand(3) == 0 {
+                    root.add(&parent, &LsDir(name.clone())).unwrap();
+                    dirs.push([parent, vec![name]].concat());
+                } else {
+                    root.add(&parent, &LsFile(name, rand(1000))).unwrap();
+                }
+            }
+            let text = root.transcript();
+            let again = build(&parse_input(&text).unwrap()).unwrap();
+            assert_eq!(again, root);
+            assert_eq!(again.transcript(), text);
+            assert_eq!(again.du().last().unwrap().1, root.size());
+        }
     }
 }
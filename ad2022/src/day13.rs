@@ -1,24 +1,21 @@
 use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
-use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
-enum Token {
-    OpenBkt,
-    CloseBkt,
-    Int(u64),
-    Comma,
-}
-use Token::*;
-
+/// A packet: an integer or a list of packets. Packets are ordered the way the
+/// puzzle says, comparing an integer to a list as if it were a one-element
+/// list.
 #[derive(Clone)]
-enum Value {
+pub enum Value {
     Int(u64),
     List(Vec<Value>),
 }
 
-impl std::fmt::Debug for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Int(i) => i.fmt(f),
             Value::List(v) => v.fmt(f),
@@ -26,8 +23,23 @@ impl std::fmt::Debug for Value {
     }
 }
 
-fn cmp_lists(a: &[Value], b: &[Value]) -> Ordering {
-    a.cmp(b)
+/// Same syntax as the puzzle input, so `to_string` and `parse` round-trip.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{i}"),
+            Value::List(v) => {
+                write!(f, "[")?;
+                for (i, item) in v.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
 }
 
 impl std::cmp::PartialOrd for Value {
@@ -48,60 +60,164 @@ impl std::cmp::Ord for Value {
     fn cmp(&self, b: &Value) -> Ordering {
         match (self, b) {
             (Value::Int(a), Value::Int(b)) => a.cmp(b),
-            (Value::Int(a), Value::List(b)) => cmp_lists(&[Value::Int(*a)], b),
-            (Value::List(a), Value::Int(b)) => cmp_lists(a, &[Value::Int(*b)]),
-            (Value::List(a), Value::List(b)) => cmp_lists(a, b),
+            (Value::Int(a), Value::List(b)) => [Value::Int(*a)][..].cmp(b),
+            (Value::List(a), Value::Int(b)) => a[..].cmp(&[Value::Int(*b)]),
+            (Value::List(a), Value::List(b)) => a.cmp(b),
         }
     }
 }
 
-fn parse_list(tokens: &[Token]) -> Vec<Value> {
-    let mut lists = vec![vec![]];
-    for t in tokens {
-        match t {
-            OpenBkt => lists.push(vec![]),
-            CloseBkt => {
-                let top = lists.pop().unwrap();
-                lists.last_mut().unwrap().push(Value::List(top));
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte range of the offending text. Empty at end of input.
+    pub span: Range<usize>,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.span.start + 1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    point: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, len: usize, message: &'static str) -> ParseError {
+        let end = (self.point + len).min(self.text.len());
+        ParseError {
+            span: self.point..end,
+            message,
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.text.get(self.point) {
+            Some(b'[') => {
+                self.point += 1;
+                let mut items = vec![];
+                if self.text.get(self.point) == Some(&b']') {
+                    self.point += 1;
+                    return Ok(Value::List(items));
+                }
+                loop {
+                    items.push(self.parse_value()?);
+                    match self.text.get(self.point) {
+                        Some(b',') => self.point += 1,
+                        Some(b']') => {
+                            self.point += 1;
+                            return Ok(Value::List(items));
+                        }
+                        _ => return Err(self.error(1, "expected `,` or `]`")),
+                    }
+                }
+            }
+            Some(b'0'..=b'9') => {
+                let len = self.text[self.point..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_digit())
+                    .count();
+                let digits = std::str::from_utf8(&self.text[self.point..self.point + len]).unwrap();
+                let n = digits
+                    .parse()
+                    .map_err(|_| self.error(len, "number too big"))?;
+                self.point += len;
+                Ok(Value::Int(n))
             }
-            Int(n) => lists.last_mut().unwrap().push(Value::Int(*n)),
-            Comma => {}
+            _ => Err(self.error(1, "expected `[` or a number")),
         }
     }
-    assert_eq!(lists.len(), 1);
-    let mut list = lists.pop().unwrap();
-    assert_eq!(list.len(), 1);
-    match list.pop().unwrap() {
-        Value::Int(_) => panic!("invalid input"),
-        Value::List(data) => data,
+}
+
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Value, ParseError> {
+        let mut parser = Parser {
+            text: s.as_bytes(),
+            point: 0,
+        };
+        let value = parser.parse_value()?;
+        if parser.point != s.len() {
+            return Err(parser.error(s.len(), "unexpected text after packet"));
+        }
+        Ok(value)
     }
 }
 
-type Input1 = Vec<(Vec<Value>, Vec<Value>)>;
+#[cfg(feature = "serde_json")]
+impl From<&Value> for serde_json::Value {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Int(n) => (*n).into(),
+            Value::List(v) => serde_json::Value::Array(v.iter().map(Into::into).collect()),
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl TryFrom<&serde_json::Value> for Value {
+    type Error = anyhow::Error;
+
+    fn try_from(json: &serde_json::Value) -> anyhow::Result<Value> {
+        match json {
+            serde_json::Value::Number(n) => n
+                .as_u64()
+                .map(Value::Int)
+                .ok_or_else(|| anyhow::anyhow!("not a packet integer: {n}")),
+            serde_json::Value::Array(v) => Ok(Value::List(
+                v.iter()
+                    .map(Value::try_from)
+                    .collect::<anyhow::Result<_>>()?,
+            )),
+            other => anyhow::bail!("expected a number or array, got {other}"),
+        }
+    }
+}
+
+/// Parse the packet on line `i` (counting from 0) of the input.
+fn parse_line(i: usize, line: &str) -> anyhow::Result<Value> {
+    line.parse()
+        .map_err(|err| anyhow::anyhow!("line {}, {err}", i + 1))
+}
+
+/// Every packet in the input, skipping blank lines.
+fn parse_packets(text: &str) -> anyhow::Result<Vec<Value>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| parse_line(i, line))
+        .collect()
+}
+
+type Input1 = Vec<(Value, Value)>;
 
+/// Pairs of packets, each pair in its own blank-line-separated section.
 #[aoc_generator(day13, part1, jorendorff)]
 fn parse_input_1(text: &str) -> anyhow::Result<Input1> {
-    let signal = parser!(tokens:{
-        '[' => OpenBkt,
-        ']' => CloseBkt,
-        n:u64 => Int(n),
-        ',' => Comma,
-    }+ => parse_list(&tokens));
-    let p = parser!(sections(line(signal) line(signal)));
-    Ok(p.parse(text)?)
+    let lines = text.lines().enumerate().collect::<Vec<_>>();
+    lines
+        .split(|(_, line)| line.is_empty())
+        .filter(|section| !section.is_empty())
+        .map(|section| match *section {
+            [(i, a), (j, b)] => Ok((parse_line(i, a)?, parse_line(j, b)?)),
+            _ => anyhow::bail!(
+                "line {}: expected a pair of packets, got {}",
+                section[0].0 + 1,
+                section.len()
+            ),
+        })
+        .collect()
 }
 
 #[aoc_generator(day13, part2, jorendorff)]
-fn parse_input_2(text: &str) -> anyhow::Result<Vec<Vec<Value>>> {
-    let signal = parser!(tokens:{
-        '[' => OpenBkt,
-        ']' => CloseBkt,
-        n:u64 => Int(n),
-        ',' => Comma,
-    }+ => parse_list(&tokens));
-    let p =
-        parser!(s:sections(lines(signal)) => s.into_iter().flatten().collect::<Vec<Vec<Value>>>());
-    Ok(p.parse(text)?)
+fn parse_input_2(text: &str) -> anyhow::Result<Vec<Value>> {
+    parse_packets(text)
 }
 
 #[aoc(day13, part1, jorendorff)]
@@ -109,15 +225,15 @@ fn part_1(input: &Input1) -> usize {
     input
         .iter()
         .enumerate()
-        .filter(|(_i, (a, b))| cmp_lists(a, b) != Ordering::Greater)
+        .filter(|(_i, (a, b))| a <= b)
         .map(|(i, _)| 1 + i)
         .sum()
 }
 
 #[aoc(day13, part2, jorendorff)]
-fn part_2(signals: &[Vec<Value>]) -> usize {
-    let first = vec![Value::List(vec![Value::Int(2)])];
-    let second = vec![Value::List(vec![Value::Int(6)])];
+fn part_2(signals: &[Value]) -> usize {
+    let first: Value = "[[2]]".parse().unwrap();
+    let second: Value = "[[6]]".parse().unwrap();
 
     let first_idx = signals.iter().filter(|s| *s < &first).count() + 1;
     let second_idx = signals.iter().filter(|s| *s < &second).count() + 2;
@@ -163,4 +279,69 @@ mod tests {
     fn test_part_2() {
         assert_eq!(part_2(&parse_input_2(EXAMPLE).unwrap()), 140);
     }
+
+    #[test]
+    fn test_sort() {
+        let mut packets = parse_packets(&format!("{EXAMPLE}[[2]]\n[[6]]\n")).unwrap();
+        packets.sort();
+        let sorted = packets.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            sorted,
+            [
+                "[]",
+                "[[]]",
+                "[[[]]]",
+                "[1,1,3,1,1]",
+                "[1,1,5,1,1]",
+                "[[1],[2,3,4]]",
+                "[1,[2,[3,[4,[5,6,0]]]],8,9]",
+                "[1,[2,[3,[4,[5,6,7]]]],8,9]",
+                "[[1],4]",
+                "[[2]]",
+                "[3]",
+                "[[4,4],4,4]",
+                "[[4,4],4,4,4]",
+                "[[6]]",
+                "[7,7,7]",
+                "[7,7,7,7]",
+                "[[8,7,6]]",
+                "[9]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| s.parse::<Value>().unwrap_err();
+        let at = |span, message| ParseError { span, message };
+        assert_eq!(err(""), at(0..0, "expected `[` or a number"));
+        assert_eq!(err("[1,2"), at(4..4, "expected `,` or `]`"));
+        assert_eq!(err("[1;2]"), at(2..3, "expected `,` or `]`"));
+        assert_eq!(err("[1,]"), at(3..4, "expected `[` or a number"));
+        assert_eq!(err("[[99999999999999999999]]"), at(2..22, "number too big"));
+        assert_eq!(err("[1] 2"), at(3..5, "unexpected text after packet"));
+
+        let msg = parse_packets("[1]\n\n[x]\n").unwrap_err().to_string();
+        assert_eq!(msg, "line 3, column 2: expected `[` or a number");
+
+        let msg = parse_input_1("[1]\n[2]\n[3]\n\n[4]\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(msg, "line 1: expected a pair of packets, got 3");
+        let msg = parse_input_1("[1]\n[2]\n\n[3]\n").unwrap_err().to_string();
+        assert_eq!(msg, "line 4: expected a pair of packets, got 1");
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_serde_json() {
+        let packet: Value = "[1,[2,[]],3]".parse().unwrap();
+        let json = serde_json::Value::from(&packet);
+        assert_eq!(json, serde_json::json!([1, [2, []], 3]));
+        let back = Value::try_from(&json).unwrap();
+        assert_eq!(back.to_string(), "[1,[2,[]],3]");
+
+        assert!(Value::try_from(&serde_json::json!([1, "two"])).is_err());
+        assert!(Value::try_from(&serde_json::json!([-1])).is_err());
+    }
 }
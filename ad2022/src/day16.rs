@@ -1,5 +1,6 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 
+use adlib::CompressedGraph;
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -45,90 +46,26 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     ))
 }
 
-fn search(
-    valves: &[Valve],
-    time_limit: usize,
-    time: usize,
-    loc: usize,
-    open: u128,
-    score: u64,
-) -> u64 {
-    if time >= time_limit {
-        return score;
-    }
-    let mut best = score;
-    let mut seen = 1u128 << loc;
-    let mut todo = VecDeque::from([(time, loc)]);
-    while let Some((t, p)) = todo.pop_front() {
-        if t >= time_limit {
-            break;
-        }
-        let flow = valves[p].flow_rate;
-        if flow > 0 && open & (1 << p) == 0 {
-            let stab = search(
-                valves,
-                time_limit,
-                t + 1,
-                p,
-                open | (1 << p),
-                score + (time_limit as u64 - (t as u64 + 1)) * flow,
-            );
-            best = best.max(stab);
-        }
-        for &n in &valves[p].neighbors {
-            if seen & (1 << n) == 0 {
-                todo.push_back((t + 1, n));
-                seen |= 1 << n;
-            }
-        }
-    }
-    best
+fn compress(input: &Input) -> CompressedGraph {
+    let (start, valves) = input;
+    let neighbors: Vec<Vec<usize>> = valves.iter().map(|v| v.neighbors.clone()).collect();
+    let flow_rates: Vec<u64> = valves.iter().map(|v| v.flow_rate).collect();
+    CompressedGraph::from_unweighted(&neighbors, &flow_rates, *start)
 }
 
 #[aoc(day16, part1, jorendorff)]
-fn part_1(input: &Input) -> u64 {
-    let (start, valves) = input;
-    search(valves, 30, 0, *start, 0, 0)
+fn part_1(input: &Input) -> anyhow::Result<u64> {
+    compress(input)
+        .best_with_agents(30, 1)
+        .ok_or_else(|| anyhow::anyhow!("too many valves with nonzero flow rate"))
 }
 
 #[aoc(day16, part2, jorendorff)]
-fn part_2(input: &Input) -> u64 {
-    let (start, valves) = input;
-    let start = *start;
-    let valves: &[Valve] = valves;
-
-    let vi: Vec<usize> = valves
-        .iter()
-        .enumerate()
-        .filter(|pair| pair.1.flow_rate != 0)
-        .map(|pair| pair.0)
-        .collect();
-
-    let n = vi.len();
-    let mut best = 0;
-    for i in 0..(1 << (n as u32)) {
-        // make a copy with only some enabled
-        let mut map1 = valves.to_vec();
-        for j in 0..n {
-            if i & (1 << j) == 0 {
-                map1[vi[j]].flow_rate = 0;
-            }
-        }
-        let my_best = search(&map1, 26, 0, start, 0, 0);
-
-        // the other ones enabled
-        let mut map2 = valves.to_vec();
-        for j in 0..n {
-            if i & (1 << j) != 0 {
-                map2[vi[j]].flow_rate = 0;
-            }
-        }
-        let elephant_best = search(&map2, 26, 0, start, 0, 0);
-
-        let stab = my_best + elephant_best;
-        best = best.max(stab);
-    }
-    best
+fn part_2(input: &Input) -> anyhow::Result<u64> {
+    // You and the elephant open disjoint sets of valves.
+    compress(input)
+        .best_with_agents(26, 2)
+        .ok_or_else(|| anyhow::anyhow!("too many valves with nonzero flow rate"))
 }
 
 #[cfg(test)]
@@ -150,11 +87,11 @@ Valve JJ has flow rate=21; tunnel leads to valve II
 
     #[test]
     fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 1651);
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), 1651);
     }
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 1707);
+        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()).unwrap(), 1707);
     }
 }
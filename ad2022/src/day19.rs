@@ -1,3 +1,4 @@
+use adlib::{BranchAndBound, Problem};
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -28,7 +29,7 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 struct State {
     time_left: u64,
     ore: u64,
@@ -41,72 +42,101 @@ struct State {
     geode_robots: u64,
 }
 
-// From state s, find max number of geodes we can finish in the time we have.
-// BUT: if the result would be <= `floor`, it's OK to return 0 instead.
-fn search(b: &Blueprint, mut s: State, floor: u64) -> u64 {
-    if s.time_left == 0 {
-        return s.geodes;
-    }
+struct Factory<'a> {
+    b: &'a Blueprint,
+}
+
+impl Problem for Factory<'_> {
+    type State = State;
 
-    // Prune if we can't possibly beat `floor`.
-    if s.geodes + s.time_left * s.geode_robots + s.time_left * (s.time_left - 1) / 2 <= floor {
-        return 0;
+    // Geodes we'll have at the end if we never build anything else.
+    fn value(&self, s: &State) -> u64 {
+        s.geodes + s.time_left * s.geode_robots
     }
 
-    // Only try ore if we (a) can afford it AND (b) don't already have as many
-    // ore robots as we could possibly ever need.
-    let try_ore = s.ore >= b.oo && s.ore_robots < b.co.max(b.bo).max(b.go);
-    let try_clay = s.ore >= b.co;
-    let try_obsidian = s.ore >= b.bo && s.clay >= b.bc;
-    let try_geode = s.ore >= b.go && s.obsidian >= b.gb;
-
-    s.time_left -= 1;
-    s.ore += s.ore_robots;
-    s.clay += s.clay_robots;
-    s.obsidian += s.obsidian_robots;
-    s.geodes += s.geode_robots;
-
-    // Try building a geode robot first, since that's virtually guaranteed to
-    // be the best choice; maxing out `best` early improves the `floor` for the
-    // other moves, a huge speedup.
-    let mut best = floor;
-    if try_geode {
-        let mut s = s.clone();
-        s.ore -= b.go;
-        s.obsidian -= b.gb;
-        s.geode_robots += 1;
-        best = best.max(search(b, s, best));
+    // As if we could build a geode robot every remaining minute.
+    fn upper_bound(&self, s: &State) -> u64 {
+        self.value(s) + s.time_left * s.time_left.saturating_sub(1) / 2
     }
-    if try_obsidian {
+
+    fn successors(&self, s: &State, out: &mut Vec<State>) {
+        let b = self.b;
+        if s.time_left == 0 {
+            return;
+        }
+
+        // Only try ore if we (a) can afford it AND (b) don't already have as many
+        // ore robots as we could possibly ever need.
+        let try_ore = s.ore >= b.oo && s.ore_robots < b.co.max(b.bo).max(b.go);
+        let try_clay = s.ore >= b.co;
+        let try_obsidian = s.ore >= b.bo && s.clay >= b.bc;
+        let try_geode = s.ore >= b.go && s.obsidian >= b.gb;
+
         let mut s = s.clone();
-        s.ore -= b.bo;
-        s.clay -= b.bc;
-        s.obsidian_robots += 1;
-        best = best.max(search(b, s, best));
+        s.time_left -= 1;
+        s.ore += s.ore_robots;
+        s.clay += s.clay_robots;
+        s.obsidian += s.obsidian_robots;
+        s.geodes += s.geode_robots;
+
+        // Try building a geode robot first, since that's virtually guaranteed to
+        // be the best choice; finding a good answer early lets the bound prune
+        // the other moves, a huge speedup.
+        if try_geode {
+            let mut s = s.clone();
+            s.ore -= b.go;
+            s.obsidian -= b.gb;
+            s.geode_robots += 1;
+            out.push(s);
+        }
+        if try_obsidian {
+            let mut s = s.clone();
+            s.ore -= b.bo;
+            s.clay -= b.bc;
+            s.obsidian_robots += 1;
+            out.push(s);
+        }
+        if try_clay {
+            let mut s = s.clone();
+            s.ore -= b.co;
+            s.clay_robots += 1;
+            out.push(s);
+        }
+        if try_ore {
+            let mut s = s.clone();
+            s.ore -= b.oo;
+            s.ore_robots += 1;
+            out.push(s);
+        }
+        out.push(s);
     }
-    if try_clay {
-        let mut s = s.clone();
-        s.ore -= b.co;
-        s.clay_robots += 1;
-        best = best.max(search(b, s, best));
+
+    // States with the same time and robots can be compared by resources on hand.
+    fn dominance_group(&self, s: &State) -> Option<u64> {
+        Some(
+            s.time_left << 48
+                | s.ore_robots << 36
+                | s.clay_robots << 24
+                | s.obsidian_robots << 12
+                | s.geode_robots,
+        )
     }
-    if try_ore {
-        let mut s = s.clone();
-        s.ore -= b.oo;
-        s.ore_robots += 1;
-        best = best.max(search(b, s, best));
+
+    fn dominates(&self, a: &State, b: &State) -> bool {
+        a.ore >= b.ore && a.clay >= b.clay && a.obsidian >= b.obsidian && a.geodes >= b.geodes
     }
-    best = best.max(search(b, s.clone(), best));
-    best
 }
 
-fn max_geodes(b: &Blueprint, time_left: u64) -> u64 {
-    let state = State {
+fn start_state(time_left: u64) -> State {
+    State {
         time_left,
         ore_robots: 1,
         ..State::default()
-    };
-    search(b, state, 0)
+    }
+}
+
+fn max_geodes(b: &Blueprint, time_left: u64) -> u64 {
+    BranchAndBound::new(Factory { b }).maximize(start_state(time_left)).0
 }
 
 #[aoc(day19, part1, jorendorff)]
@@ -147,4 +177,18 @@ Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsid
         assert_eq!(max_geodes(&blueprints[0], 32), 56);
         assert_eq!(max_geodes(&blueprints[1], 32), 62);
     }
+
+    #[test]
+    fn test_pruning_strategies() {
+        let blueprints = parse_input(EXAMPLE).unwrap();
+        let mut bnb = BranchAndBound::new(Factory { b: &blueprints[0] });
+        let (best, all_on) = bnb.maximize(start_state(24));
+        assert_eq!(best, 9);
+
+        bnb.use_dominance = false;
+        bnb.use_memo = false;
+        let (best, bound_only) = bnb.maximize(start_state(24));
+        assert_eq!(best, 9);
+        assert!(all_on.expanded <= bound_only.expanded);
+    }
 }
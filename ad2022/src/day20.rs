@@ -1,3 +1,4 @@
+use adlib::{Handle, OrderList};
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -10,102 +11,58 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
-fn mix(input: &[i64], pos_to_id: &mut [usize], id_to_pos: &mut [usize]) {
+/// Move each number, in original order, as many places as its value. `list`
+/// holds ids (indexes into `input`) and `handles[id]` is where each one is.
+fn mix(input: &[i64], list: &mut OrderList<usize>, handles: &[Handle]) {
     let n = input.len();
-    let mut saved_vv = (0..n).map(|p| input[pos_to_id[p]]).collect::<Vec<i64>>();
-    for id in 0..n {
-        for j in 0..n {
-            assert_eq!(pos_to_id[id_to_pos[j]], j);
-            assert_eq!(id_to_pos[pos_to_id[j]], j);
+    #[cfg(feature = "verify-mix")]
+    let mut naive = list.iter().copied().collect::<Vec<usize>>();
+
+    for (id, &h) in handles.iter().enumerate() {
+        let orig = list.index_of(h);
+        list.remove_at(orig);
+        // With the number taken out, there are n - 1 places to put it back,
+        // and moving it n - 1 places brings it back where it started.
+        let dest = (orig as i64 + input[id]).rem_euclid(n as i64 - 1) as usize;
+        list.reinsert(dest, h);
+
+        #[cfg(feature = "verify-mix")]
+        {
+            naive.remove(orig);
+            naive.insert(dest, id);
+            assert_eq!(list.iter().copied().collect::<Vec<usize>>(), naive);
         }
-
-        let orig = id_to_pos[id];
-        let dx = input[id];
-        let dest = if dx > 0 {
-            (orig + dx as usize - 1) % (n - 1) + 1
-        } else if dx < 0 {
-            let dx = -((-dx as usize % (n - 1)) as i64);
-            (orig + (n as i64 - 1 + dx) as usize) % (n - 1)
-        } else {
-            orig
-        };
-        if dest < orig {
-            for p in (dest..orig).rev() {
-                pos_to_id[p + 1] = pos_to_id[p];
-                id_to_pos[pos_to_id[p]] += 1;
-            }
-        } else {
-            for p in orig..dest {
-                pos_to_id[p] = pos_to_id[p + 1];
-                id_to_pos[pos_to_id[p]] -= 1;
-            }
-        }
-        pos_to_id[dest] = id;
-        id_to_pos[id] = dest;
-
-        let vv = (0..n).map(|p| input[pos_to_id[p]]).collect::<Vec<i64>>();
-        if dest < orig {
-            assert_eq!(
-                vv,
-                saved_vv[0..dest]
-                    .iter()
-                    .cloned()
-                    .chain([input[id]].into_iter())
-                    .chain(saved_vv[dest..orig].iter().cloned())
-                    .chain(saved_vv[orig + 1..].iter().cloned())
-                    .collect::<Vec<i64>>()
-            );
-        } else {
-            assert_eq!(
-                vv,
-                saved_vv[0..orig]
-                    .iter()
-                    .cloned()
-                    .chain(saved_vv[orig + 1..dest + 1].iter().cloned())
-                    .chain([input[id]].into_iter())
-                    .chain(saved_vv[dest + 1..].iter().cloned())
-                    .collect::<Vec<i64>>()
-            );
-        }
-        saved_vv = vv;
     }
 }
 
-#[aoc(day20, part1, jorendorff)]
-fn part_1(input: &Input) -> i64 {
-    let zero_id = input.iter().copied().take_while(|&x| x != 0).count();
+/// Mix `rounds` times, then add up the numbers 1000, 2000 and 3000 places
+/// after the 0.
+fn decrypt(input: &[i64], rounds: usize) -> i64 {
     let n = input.len();
+    let mut list = OrderList::new();
+    let handles = (0..n).map(|id| list.push(id)).collect::<Vec<Handle>>();
+    for _ in 0..rounds {
+        mix(input, &mut list, &handles);
+    }
 
-    let mut pos_to_id = (0..n).collect::<Vec<usize>>();
-    let mut id_to_pos = pos_to_id.clone();
-
-    mix(input, &mut pos_to_id, &mut id_to_pos);
+    let zero_id = input.iter().position(|&x| x == 0).unwrap();
+    let p0 = list.index_of(handles[zero_id]);
+    [1000, 2000, 3000]
+        .into_iter()
+        .map(|k| input[*list.value(list.get((p0 + k) % n))])
+        .sum()
+}
 
-    let p0 = id_to_pos[zero_id];
-    input[pos_to_id[(p0 + 1000) % n]]
-        + input[pos_to_id[(p0 + 2000) % n]]
-        + input[pos_to_id[(p0 + 3000) % n]]
+#[aoc(day20, part1, jorendorff)]
+fn part_1(input: &Input) -> i64 {
+    decrypt(input, 1)
 }
 
 #[aoc(day20, part2, jorendorff)]
 fn part_2(input: &Input) -> i64 {
-    let zero_id = input.iter().copied().take_while(|&x| x != 0).count();
-    let n = input.len();
-
     const KEY: i64 = 811589153;
     let input = input.iter().copied().map(|x| x * KEY).collect::<Vec<i64>>();
-
-    let mut pos_to_id = (0..n).collect::<Vec<usize>>();
-    let mut id_to_pos = pos_to_id.clone();
-
-    for _ in 0..10 {
-        mix(&input, &mut pos_to_id, &mut id_to_pos);
-    }
-
-    let p0 = id_to_pos[zero_id];
-    input[pos_to_id[(p0 + 1000) % n]]
-        + input[pos_to_id[(p0 + 2000) % n]]
-        + input[pos_to_id[(p0 + 3000) % n]]
+    decrypt(&input, 10)
 }
 
 #[cfg(test)]
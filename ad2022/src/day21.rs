@@ -1,7 +1,9 @@
 use std::collections::HashMap;
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
 
 use num_bigint::BigInt;
+use num_traits::{One, Signed, Zero};
 
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
@@ -22,42 +24,6 @@ enum Op {
 enum Rule {
     Num(i64),
     Job(String, Op, String),
-    Expr(Polynomial),
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct Term {
-    coeff: Ratio,
-    degree: u64,
-}
-
-#[derive(Clone, Debug, Default, PartialEq)]
-struct Polynomial {
-    // maps degree to coefficient
-    coeffs: HashMap<u64, Ratio>,
-}
-
-impl Polynomial {
-    fn x() -> Self {
-        Polynomial {
-            coeffs: [(1, Ratio::new(1.into(), 1.into()))].into_iter().collect(),
-        }
-    }
-
-    fn constant(i: i64) -> Self {
-        Polynomial {
-            coeffs: [(0, Ratio::new(i.into(), 1.into()))].into_iter().collect(),
-        }
-    }
-
-    fn zero() -> Self {
-        Self::default()
-    }
-
-    fn simplify(mut self) -> Self {
-        self.coeffs.retain(|_k, v| *v != Ratio::default());
-        self
-    }
 }
 
 #[aoc_generator(day21, part1, jorendorff)]
@@ -77,142 +43,502 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
-fn what(rules: &mut HashMap<String, Rule>, target: &str) -> i64 {
-    match &rules[target] {
-        Rule::Num(n) => *n,
-        Rule::Job(left, op, right) => {
-            let left = left.to_string();
-            let right = right.to_string();
-            let op = *op;
-            let ln = what(rules, &left);
-            let rn = what(rules, &right);
-            let out = match op {
-                Op::Add => ln + rn,
-                Op::Sub => ln - rn,
-                Op::Mul => ln * rn,
-                Op::Div => ln / rn,
-            };
-            rules.insert(target.to_string(), Rule::Num(out)); // cache
-            out
+/// A polynomial in x with rational coefficients, lowest degree first. There
+/// are never trailing zeros, so zero is the empty list.
+#[derive(Clone, Debug, PartialEq)]
+struct Poly(Vec<Ratio>);
+
+impl Poly {
+    fn constant(c: Ratio) -> Self {
+        Poly(vec![c]).trim()
+    }
+
+    fn x() -> Self {
+        Poly(vec![Ratio::zero(), Ratio::one()])
+    }
+
+    fn trim(mut self) -> Self {
+        while self.0.last().is_some_and(Zero::is_zero) {
+            self.0.pop();
+        }
+        self
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Degree, counting zero as degree 0.
+    fn degree(&self) -> usize {
+        self.0.len().saturating_sub(1)
+    }
+
+    fn lead(&self) -> Ratio {
+        self.0.last().cloned().unwrap_or_else(Ratio::zero)
+    }
+
+    fn scale(&self, c: &Ratio) -> Poly {
+        Poly(self.0.iter().map(|a| a * c).collect()).trim()
+    }
+
+    fn eval(&self, x: &Ratio) -> Ratio {
+        self.0
+            .iter()
+            .rev()
+            .fold(Ratio::zero(), |acc, c| acc * x + c)
+    }
+
+    fn derivative(&self) -> Poly {
+        Poly(
+            self.0
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(i, c)| c * Ratio::from(BigInt::from(i)))
+                .collect(),
+        )
+    }
+
+    /// Quotient and remainder. `d` must not be zero.
+    fn div_rem(&self, d: &Poly) -> (Poly, Poly) {
+        let mut rem = self.clone();
+        let mut quot = vec![Ratio::zero(); (self.0.len() + 1).saturating_sub(d.0.len())];
+        while !rem.is_zero() && rem.0.len() >= d.0.len() {
+            let shift = rem.0.len() - d.0.len();
+            let c = rem.lead() / d.lead();
+            for (i, dc) in d.0.iter().enumerate() {
+                rem.0[shift + i] -= &c * dc;
+            }
+            quot[shift] = c;
+            rem = rem.trim();
+        }
+        (Poly(quot).trim(), rem)
+    }
+
+    /// The monic greatest common divisor.
+    fn gcd(&self, other: &Poly) -> Poly {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while !b.is_zero() {
+            let r = a.div_rem(&b).1;
+            a = b;
+            b = r;
+        }
+        if a.is_zero() {
+            a
+        } else {
+            a.scale(&a.lead().recip())
         }
-        _ => panic!("oh no"),
     }
 }
 
-#[aoc(day21, part1, jorendorff)]
-fn part_1(input: &Input) -> i64 {
-    let mut h = input.iter().cloned().collect::<HashMap<String, Rule>>();
-    what(&mut h, "root")
+impl Add for &Poly {
+    type Output = Poly;
+    fn add(self, other: &Poly) -> Poly {
+        let n = self.0.len().max(other.0.len());
+        let get = |p: &Poly, i: usize| p.0.get(i).cloned().unwrap_or_else(Ratio::zero);
+        Poly((0..n).map(|i| get(self, i) + get(other, i)).collect()).trim()
+    }
 }
 
-impl Add for Polynomial {
-    type Output = Polynomial;
-    fn add(mut self, other: Polynomial) -> Polynomial {
-        for (deg, coeff) in other.coeffs {
-            *self.coeffs.entry(deg).or_default() += coeff;
+impl Sub for &Poly {
+    type Output = Poly;
+    fn sub(self, other: &Poly) -> Poly {
+        self + &other.scale(&-Ratio::one())
+    }
+}
+
+impl Mul for &Poly {
+    type Output = Poly;
+    fn mul(self, other: &Poly) -> Poly {
+        if self.is_zero() || other.is_zero() {
+            return Poly(vec![]);
+        }
+        let mut out = vec![Ratio::zero(); self.0.len() + other.0.len() - 1];
+        for (i, a) in self.0.iter().enumerate() {
+            for (j, b) in other.0.iter().enumerate() {
+                out[i + j] += a * b;
+            }
         }
-        self.simplify()
+        Poly(out).trim()
     }
 }
 
-impl Neg for Polynomial {
-    type Output = Polynomial;
-    fn neg(mut self) -> Polynomial {
-        for (_deg, coeff) in &mut self.coeffs {
-            *coeff *= Ratio::from(BigInt::from(-1));
+/// Terms from the highest degree down, like `3*x^2 - x + 1/2`.
+impl fmt::Display for Poly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
         }
-        self
+        let mut first = true;
+        for (deg, c) in self.0.iter().enumerate().rev() {
+            if c.is_zero() {
+                continue;
+            }
+            let sign = if c.is_negative() { "-" } else { "+" };
+            match (first, sign) {
+                (true, "-") => write!(f, "-")?,
+                (true, _) => {}
+                (false, _) => write!(f, " {sign} ")?,
+            }
+            first = false;
+            let c = c.abs();
+            let x = match deg {
+                0 => String::new(),
+                1 => "x".to_string(),
+                _ => format!("x^{deg}"),
+            };
+            match (deg, c.is_one()) {
+                (0, _) => write!(f, "{c}")?,
+                (_, true) => write!(f, "{x}")?,
+                _ => write!(f, "{c}*{x}")?,
+            }
+        }
+        Ok(())
     }
 }
 
-impl Sub for Polynomial {
-    type Output = Polynomial;
-    fn sub(mut self, other: Polynomial) -> Polynomial {
-        for (deg, coeff) in other.coeffs {
-            *self.coeffs.entry(deg).or_default() -= coeff;
+/// A rational function `num / den` in lowest terms, with `den` monic.
+#[derive(Clone, Debug, PartialEq)]
+struct RatFn {
+    num: Poly,
+    den: Poly,
+}
+
+impl RatFn {
+    fn new(num: Poly, den: Poly) -> anyhow::Result<Self> {
+        anyhow::ensure!(!den.is_zero(), "division by zero");
+        if num.is_zero() {
+            return Ok(RatFn::from(num));
+        }
+        let g = num.gcd(&den);
+        let (num, den) = (num.div_rem(&g).0, den.div_rem(&g).0);
+        let scale = den.lead().recip();
+        Ok(RatFn {
+            num: num.scale(&scale),
+            den: den.scale(&scale),
+        })
+    }
+
+    /// The value, if this doesn't depend on x.
+    fn as_constant(&self) -> Option<Ratio> {
+        (self.num.degree() == 0 && self.den.degree() == 0).then(|| self.num.lead())
+    }
+
+    fn apply(&self, op: Op, other: &RatFn) -> anyhow::Result<RatFn> {
+        let (a, b, c, d) = (&self.num, &self.den, &other.num, &other.den);
+        match op {
+            Op::Add => RatFn::new(&(a * d) + &(c * b), b * d),
+            Op::Sub => RatFn::new(&(a * d) - &(c * b), b * d),
+            Op::Mul => RatFn::new(a * c, b * d),
+            Op::Div => RatFn::new(a * d, b * c),
         }
-        self.simplify()
     }
 }
 
-impl Mul for Polynomial {
-    type Output = Polynomial;
-    fn mul(self, other: Polynomial) -> Polynomial {
-        let mut p = Polynomial::zero();
-        for (&deg0, coeff0) in self.coeffs.iter() {
-            for (&deg1, coeff1) in other.coeffs.iter() {
-                *p.coeffs.entry(deg0 + deg1).or_default() += coeff0 * coeff1;
-            }
+impl From<Poly> for RatFn {
+    fn from(num: Poly) -> Self {
+        RatFn {
+            num,
+            den: Poly::constant(Ratio::one()),
         }
-        p.simplify()
     }
 }
 
-impl Div for Polynomial {
-    type Output = Polynomial;
-    fn div(self, other: Polynomial) -> Polynomial {
-        assert_eq!(other.coeffs.len(), 1);
-        let (rdeg, rcoeff) = other.coeffs.into_iter().next().unwrap();
-        Polynomial {
-            coeffs: self
-                .coeffs
-                .into_iter()
-                .map(move |(ldeg, lcoeff)| (ldeg - rdeg, lcoeff / rcoeff.clone()))
-                .collect(),
+impl fmt::Display for RatFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den.degree() == 0 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "({}) / ({})", self.num, self.den)
         }
     }
 }
 
-fn what2(rules: &mut HashMap<String, Rule>, target: &str) -> Polynomial {
-    if target == "humn" {
-        return Polynomial::x();
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    Num(i64),
+    Job(usize, Op, usize),
+}
+
+/// The monkeys' rules as a graph. Each monkey is evaluated once, however many
+/// other monkeys listen to it.
+struct Dag {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    nodes: Vec<Node>,
+}
+
+impl Dag {
+    /// Check that every name is defined and that no monkey depends on itself.
+    fn new(rules: &[(String, Rule)]) -> anyhow::Result<Dag> {
+        let names = rules
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let index = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect::<HashMap<_, _>>();
+        anyhow::ensure!(index.len() == names.len(), "a monkey is defined twice");
+        let lookup = |name: &String| {
+            index
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("no rule for {name}"))
+        };
+        let nodes = rules
+            .iter()
+            .map(|(_, rule)| {
+                Ok(match rule {
+                    Rule::Num(n) => Node::Num(*n),
+                    Rule::Job(a, op, b) => Node::Job(lookup(a)?, *op, lookup(b)?),
+                })
+            })
+            .collect::<anyhow::Result<Vec<Node>>>()?;
+        let dag = Dag {
+            names,
+            index,
+            nodes,
+        };
+        dag.check_acyclic()?;
+        Ok(dag)
+    }
+
+    fn id(&self, name: &str) -> anyhow::Result<usize> {
+        self.index
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("no rule for {name}"))
+    }
+
+    fn check_acyclic(&self) -> anyhow::Result<()> {
+        // 0 = unvisited, 1 = on the current path, 2 = done
+        let mut color = vec![0u8; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            let mut path = vec![start];
+            while let Some(&node) = path.last() {
+                if color[node] == 0 {
+                    color[node] = 1;
+                }
+                let next = match self.nodes[node] {
+                    Node::Num(_) => None,
+                    Node::Job(a, _, b) => [a, b].into_iter().find(|&k| color[k] != 2),
+                };
+                match next {
+                    Some(k) if color[k] == 1 => {
+                        let i = path.iter().position(|&p| p == k).unwrap();
+                        let mut cycle = path[i..]
+                            .iter()
+                            .map(|&p| self.names[p].as_str())
+                            .collect::<Vec<_>>();
+                        cycle.push(&self.names[k]);
+                        anyhow::bail!("cycle in rules: {}", cycle.join(" -> "));
+                    }
+                    Some(k) => path.push(k),
+                    None => {
+                        color[node] = 2;
+                        path.pop();
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
-    match &rules[target] {
-        Rule::Num(n) => Polynomial::constant(*n),
-        Rule::Expr(x) => x.clone(),
-        Rule::Job(left, op, right) => {
-            let left = left.to_string();
-            let right = right.to_string();
-            let op = *op;
-            let ln = what2(rules, &left);
-            let rn = what2(rules, &right);
-            let out = match op {
-                Op::Add => ln + rn,
-                Op::Sub => ln - rn,
-                Op::Mul => ln * rn,
-                Op::Div => ln / rn,
+    /// Nodes reachable from `root`, each after everything it depends on.
+    fn postorder(&self, root: usize) -> Vec<usize> {
+        let mut done = vec![false; self.nodes.len()];
+        let mut order = vec![];
+        let mut stack = vec![(root, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if done[node] {
+                continue;
+            }
+            if expanded {
+                done[node] = true;
+                order.push(node);
+                continue;
+            }
+            stack.push((node, true));
+            if let Node::Job(a, _, b) = self.nodes[node] {
+                stack.push((b, false));
+                stack.push((a, false));
+            }
+        }
+        order
+    }
+
+    /// Evaluate bottom-up from `root`, computing each node once.
+    fn fold<T: Clone>(
+        &self,
+        root: usize,
+        mut leaf: impl FnMut(usize, i64) -> anyhow::Result<T>,
+        mut job: impl FnMut(&T, Op, &T) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Vec<Option<T>>> {
+        let mut memo: Vec<Option<T>> = vec![None; self.nodes.len()];
+        for node in self.postorder(root) {
+            let value = match self.nodes[node] {
+                Node::Num(n) => leaf(node, n)?,
+                Node::Job(a, op, b) => {
+                    let (Some(a), Some(b)) = (&memo[a], &memo[b]) else {
+                        unreachable!("postorder visits children first");
+                    };
+                    job(a, op, b).map_err(|err| anyhow::anyhow!("{}: {err}", self.names[node]))?
+                }
             };
-            rules.insert(target.to_string(), Rule::Expr(out.clone())); // cache
-            out
+            memo[node] = Some(value);
         }
+        Ok(memo)
     }
-}
 
-#[aoc(day21, part2, jorendorff)]
-fn part_2(input: &Input) -> Ratio {
-    let mut h = input.iter().cloned().collect::<HashMap<String, Rule>>();
-    if let Rule::Job(_left, op, _right) = h.get_mut("root").unwrap() {
-        *op = Op::Sub;
+    fn eval(&self, root: &str) -> anyhow::Result<i64> {
+        let root = self.id(root)?;
+        let memo = self.fold(
+            root,
+            |_, n| Ok(n),
+            |&a, op, &b| {
+                match op {
+                    Op::Add => a.checked_add(b),
+                    Op::Sub => a.checked_sub(b),
+                    Op::Mul => a.checked_mul(b),
+                    Op::Div => a.checked_div(b),
+                }
+                .ok_or_else(|| anyhow::anyhow!("overflow or division by zero"))
+            },
+        )?;
+        Ok(memo[root].unwrap())
     }
-    let diff: Polynomial = what2(&mut h, "root");
 
-    for deg in diff.coeffs.keys() {
-        assert!(*deg == 0 || *deg == 1);
+    /// The value of every node reachable from `root` as a function of the
+    /// monkey `unknown`.
+    fn symbolic(&self, root: usize, unknown: usize) -> anyhow::Result<Vec<Option<RatFn>>> {
+        self.fold(
+            root,
+            |node, n| {
+                Ok(RatFn::from(if node == unknown {
+                    Poly::x()
+                } else {
+                    Poly::constant(Ratio::from(BigInt::from(n)))
+                }))
+            },
+            |a, op, b| a.apply(op, b),
+        )
     }
 
-    let c0 = diff
-        .coeffs
-        .get(&0)
-        .cloned()
-        .unwrap_or_else(|| Ratio::new(0.into(), 1.into()));
-    let c1 = diff
-        .coeffs
-        .get(&1)
-        .cloned()
-        .unwrap_or_else(|| Ratio::new(0.into(), 1.into()));
+    /// Find the value of `unknown` that makes the two sides of `root` equal.
+    ///
+    /// When `unknown` is used only once, this works backwards from the root,
+    /// undoing one operation at a time. Otherwise it solves `lhs - rhs = 0`
+    /// as a rational function.
+    fn solve(&self, root: &str, unknown: &str) -> anyhow::Result<Ratio> {
+        let root = self.id(root)?;
+        let unknown = self.id(unknown)?;
+        let Node::Job(lhs, _, rhs) = self.nodes[root] else {
+            anyhow::bail!("{} isn't an equation", self.names[root]);
+        };
+        let values = self.symbolic(root, unknown)?;
+        let constant = |node: usize| values[node].as_ref().unwrap().as_constant();
+
+        if let Some(x) = self.isolate(lhs, rhs, unknown, &constant)? {
+            return Ok(x);
+        }
+
+        let f = values[lhs]
+            .as_ref()
+            .unwrap()
+            .apply(Op::Sub, values[rhs].as_ref().unwrap())?;
+        solve_rational(&f)
+    }
+
+    /// Undo operations one at a time, from the root down to `unknown`.
+    /// Returns `None` if both operands of some node depend on `unknown`.
+    fn isolate(
+        &self,
+        lhs: usize,
+        rhs: usize,
+        unknown: usize,
+        constant: &impl Fn(usize) -> Option<Ratio>,
+    ) -> anyhow::Result<Option<Ratio>> {
+        let (mut node, mut target) = match (constant(lhs), constant(rhs)) {
+            (None, Some(t)) => (lhs, t),
+            (Some(t), None) => (rhs, t),
+            _ => return Ok(None),
+        };
+        while node != unknown {
+            let Node::Job(a, op, b) = self.nodes[node] else {
+                unreachable!("non-constant leaf");
+            };
+            let div = |n: Ratio, d: Ratio| {
+                anyhow::ensure!(!d.is_zero(), "{} has no unique solution", self.names[node]);
+                Ok(n / d)
+            };
+            (node, target) = match (constant(a), constant(b)) {
+                // target = x op k
+                (None, Some(k)) => match op {
+                    Op::Add => (a, target - k),
+                    Op::Sub => (a, target + k),
+                    Op::Mul => (a, div(target, k)?),
+                    Op::Div => (a, target * k),
+                },
+                // target = k op x
+                (Some(k), None) => match op {
+                    Op::Add => (b, target - k),
+                    Op::Sub => (b, k - target),
+                    Op::Mul => (b, div(target, k)?),
+                    Op::Div => (b, div(k, target)?),
+                },
+                _ => return Ok(None),
+            };
+        }
+        Ok(Some(target))
+    }
+}
+
+/// A root of `f`, which must be exact: solved directly if `f`'s numerator is
+/// linear, otherwise found by Newton's method, rounding each step to an
+/// integer.
+fn solve_rational(f: &RatFn) -> anyhow::Result<Ratio> {
+    let is_root = |x: &Ratio| f.num.eval(x).is_zero() && !f.den.eval(x).is_zero();
+    match f.num.degree() {
+        0 if f.num.is_zero() => anyhow::bail!("every value is a solution"),
+        0 => anyhow::bail!("no solution"),
+        1 => {
+            let x = -&f.num.0[0] / &f.num.0[1];
+            anyhow::ensure!(is_root(&x), "no solution");
+            Ok(x)
+        }
+        _ => {
+            let slope = f.num.derivative();
+            let mut x = Ratio::zero();
+            for _ in 0..1000 {
+                if is_root(&x) {
+                    return Ok(x);
+                }
+                let d = slope.eval(&x);
+                let next = if d.is_zero() {
+                    &x + Ratio::one()
+                } else {
+                    (&x - f.num.eval(&x) / d).round()
+                };
+                if next == x {
+                    break;
+                }
+                x = next;
+            }
+            anyhow::bail!("no integer solution found")
+        }
+    }
+}
+
+#[aoc(day21, part1, jorendorff)]
+fn part_1(input: &Input) -> anyhow::Result<i64> {
+    Dag::new(input)?.eval("root")
+}
 
-    -c0 / c1
+#[aoc(day21, part2, jorendorff)]
+fn part_2(input: &Input) -> anyhow::Result<Ratio> {
+    Dag::new(input)?.solve("root", "humn")
 }
 
 #[cfg(test)]
@@ -239,14 +565,102 @@ hmdt: 32
 
     #[test]
     fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 152);
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), 152);
     }
 
     #[test]
     fn test_part_2() {
         assert_eq!(
-            part_2(&parse_input(EXAMPLE).unwrap()),
+            part_2(&parse_input(EXAMPLE).unwrap()).unwrap(),
             Ratio::from(BigInt::from(301))
         );
     }
+
+    fn rules(text: &str) -> Input {
+        parse_input(text).unwrap()
+    }
+
+    fn int(n: i64) -> Ratio {
+        Ratio::from(BigInt::from(n))
+    }
+
+    #[test]
+    fn test_shared_subexpressions() {
+        // Each monkey listens to the previous one twice. Without memoization
+        // this would take 2^60 steps.
+        let mut input = vec![("a".to_string(), Rule::Num(1))];
+        let mut prev = "a".to_string();
+        for i in 0..60 {
+            let name = format!("b{i}");
+            input.push((name.clone(), Rule::Job(prev.clone(), Op::Add, prev)));
+            prev = name;
+        }
+        assert_eq!(Dag::new(&input).unwrap().eval(&prev).unwrap(), 1 << 60);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let input = rules("root: abc + def\nabc: def * two\ndef: abc - two\ntwo: 2\n");
+        let msg = Dag::new(&input).err().unwrap().to_string();
+        assert_eq!(msg, "cycle in rules: abc -> def -> abc");
+
+        let input = rules("root: abc + abc\n");
+        let msg = Dag::new(&input).err().unwrap().to_string();
+        assert_eq!(msg, "no rule for abc");
+    }
+
+    #[test]
+    fn test_simplify() {
+        let input = rules(
+            "root: num / den\nnum: sq - one\nsq: humn * humn\nden: humn - one\none: 1\nhumn: 0\n",
+        );
+        let dag = Dag::new(&input).unwrap();
+        let (root, humn) = (dag.id("root").unwrap(), dag.id("humn").unwrap());
+        let values = dag.symbolic(root, humn).unwrap();
+        assert_eq!(values[root].as_ref().unwrap().to_string(), "x + 1");
+        assert_eq!(
+            values[dag.id("sq").unwrap()].as_ref().unwrap().to_string(),
+            "x^2"
+        );
+
+        let input = rules("root: two / den\nden: humn + humn\ntwo: 2\nhumn: 0\n");
+        let dag = Dag::new(&input).unwrap();
+        let (root, humn) = (dag.id("root").unwrap(), dag.id("humn").unwrap());
+        let values = dag.symbolic(root, humn).unwrap();
+        assert_eq!(values[root].as_ref().unwrap().to_string(), "(1) / (x)");
+    }
+
+    #[test]
+    fn test_unknown_in_denominator() {
+        // 12 / humn == 8, by isolation
+        let input = rules("root: lhs + rhs\nlhs: twelve / humn\ntwelve: 12\nrhs: 8\nhumn: 0\n");
+        assert_eq!(
+            part_2(&input).unwrap(),
+            Ratio::new(BigInt::from(3), BigInt::from(2))
+        );
+
+        // (humn + 1) / humn == 3 / 2, which needs the rational function
+        let input = rules(
+            "root: lhs + rhs\nlhs: top / humn\ntop: humn + one\none: 1\nrhs: three / two\nthree: 3\ntwo: 2\nhumn: 0\n",
+        );
+        assert_eq!(part_2(&input).unwrap(), int(2));
+
+        // humn / humn == 2 has no solution
+        let input = rules("root: lhs + rhs\nlhs: humn / humn\nrhs: 2\nhumn: 0\n");
+        assert!(part_2(&input).is_err());
+    }
+
+    #[test]
+    fn test_nonlinear() {
+        // humn * humn - 2 * humn == 35: x = 7 or x = -5
+        let input = rules(
+            "root: lhs + rhs\nlhs: sq - dbl\nsq: humn * humn\ndbl: humn + humn\nrhs: 35\nhumn: 0\n",
+        );
+        let x = part_2(&input).unwrap();
+        assert!(x == int(7) || x == int(-5), "{x}");
+
+        // humn * humn == 2 has no integer solution
+        let input = rules("root: lhs + rhs\nlhs: humn * humn\nrhs: 2\nhumn: 0\n");
+        assert!(part_2(&input).is_err());
+    }
 }
@@ -169,15 +169,19 @@ impl CubeMap {
         }
     }
 
-    fn apply_tape(&mut self, (p1, d1): (Panel, Dir), (p2, d2): (Panel, Dir)) {
-        assert_eq!(self.edges[p1][d1 as usize], None);
+    fn apply_tape(&mut self, (p1, d1): (Panel, Dir), (p2, d2): (Panel, Dir)) -> anyhow::Result<()> {
+        let back = d2.flip();
+        anyhow::ensure!(
+            self.edges[p1][d1 as usize].is_none() && self.edges[p2][back as usize].is_none(),
+            "faces overlap when folded"
+        );
         self.edges[p1][d1 as usize] = Some((p2, d2));
-        assert_eq!(self.edges[p2][d2.flip() as usize], None);
-        self.edges[p2][d2.flip() as usize] = Some((p1, d1.flip()));
+        self.edges[p2][back as usize] = Some((p1, d1.flip()));
         self.count += 1;
+        Ok(())
     }
 
-    fn finish(&mut self) {
+    fn finish(&mut self) -> anyhow::Result<()> {
         // Fold up the cube. This code is neat!
         while self.count < 12 {
             let count_before = self.count;
@@ -188,7 +192,7 @@ impl CubeMap {
                         if let Some((p, d)) = self.edges[origin][dir.turn_right() as usize]
                             .and_then(|(p, d)| self.edges[p][d.turn_left() as usize])
                         {
-                            self.apply_tape((origin, dir), (p, d.turn_right()));
+                            self.apply_tape((origin, dir), (p, d.turn_right()))?;
                             continue;
                         }
 
@@ -196,13 +200,35 @@ impl CubeMap {
                         if let Some((p, d)) = self.edges[origin][dir.turn_left() as usize]
                             .and_then(|(p, d)| self.edges[p][d.turn_right() as usize])
                         {
-                            self.apply_tape((origin, dir), (p, d.turn_left()));
+                            self.apply_tape((origin, dir), (p, d.turn_left()))?;
                         }
                     }
                 }
             }
-            assert!(self.count > count_before, "progress");
+            anyhow::ensure!(self.count > count_before, "net doesn't fold into a cube");
         }
+        self.check()
+    }
+
+    /// Every face must have four different neighbors, and the three faces
+    /// around each corner must agree on that corner.
+    fn check(&self) -> anyhow::Result<()> {
+        for p in 0..6 {
+            let mut neighbors = [Right, Down, Left, Up].map(|d| self.get((p, d)).0);
+            neighbors.sort();
+            anyhow::ensure!(
+                !neighbors.contains(&p) && neighbors.windows(2).all(|w| w[0] != w[1]),
+                "face {p} is glued to itself or to one face twice"
+            );
+            for d in [Right, Down, Left, Up] {
+                let (q, e) = self.get((p, d));
+                anyhow::ensure!(
+                    self.get((p, d.turn_right())).0 == self.get((q, e.turn_right())).0,
+                    "faces {p} and {q} disagree about a corner"
+                );
+            }
+        }
+        Ok(())
     }
 
     fn get(&self, (depart_panel, depart_dir): (Panel, Dir)) -> (Panel, Dir) {
@@ -210,63 +236,240 @@ impl CubeMap {
     }
 }
 
-#[aoc(day22, part2, jorendorff)]
-fn part_2(input: &Input) -> usize {
-    // Rank 239 on this star's leaderboard.
-    let (grid, prog) = input;
-    let mut grid = grid.clone();
-    let width = grid.iter().map(Vec::len).max().unwrap();
-    let height = grid.len();
+/// A map that folds up into a cube: which squares of the map belong to which
+/// face, and how the faces are glued together.
+struct Net {
+    /// Length of a cube edge, in squares.
+    size: usize,
+    /// Top left corner of each face, in units of `size`.
+    panels: Vec<(usize, usize)>,
+    /// `mini_map[y][x] == Some(i)` whenever `panels[i] == (x, y)`.
+    mini_map: Vec<Vec<Option<Panel>>>,
+    cube: CubeMap,
+}
 
-    for row in &mut grid {
-        row.resize(width, Blank);
-    }
+impl Net {
+    /// Check that the non-blank squares of `grid` form one of the 11 nets of
+    /// a cube, in any orientation and at any scale.
+    fn new(grid: &[Vec<Square>]) -> anyhow::Result<Net> {
+        let height = grid.len();
+        let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+        let at = |x: usize, y: usize| grid[y].get(x).copied().unwrap_or(Blank);
 
-    // Cube size.
-    let cs = {
         let area = grid
             .iter()
             .flat_map(|row| row.iter().filter(|x| **x != Blank))
             .count();
-        assert_eq!(area % 6, 0);
-        let s = ((area / 6) as f64).sqrt() as usize;
-        assert_eq!(6 * s * s, area);
-        s
-    };
-    assert_eq!(height % cs, 0);
-    let height_panels = height / cs;
-    assert_eq!(width % cs, 0);
-    let width_panels = width / cs;
-
-    // Compute data used in teleportation.
-    // `panels` lists (x, y) coordinates of all faces of the cube
-    // coordinates are in panels (1/cs scale), not grid-squares
-    let mut panels = vec![];
-    // mini_map[y][x] = i whenever panels[i] == (x, y)
-    let mut mini_map = vec![vec![None; width_panels]; height_panels];
-    // `cube` tracks relationships between panels along edges
-    let mut cube = CubeMap::new();
-    for px in 0..width_panels {
-        for py in 0..height_panels {
-            if grid[py * cs][px * cs] != Blank {
+        let size = ((area / 6) as f64).sqrt() as usize;
+        anyhow::ensure!(
+            size > 0 && 6 * size * size == area,
+            "map area {area} isn't the surface area of a cube"
+        );
+        anyhow::ensure!(
+            width.is_multiple_of(size) && height.is_multiple_of(size),
+            "map is {width}x{height}, not a multiple of the cube size {size}"
+        );
+
+        let mut panels = vec![];
+        let mut mini_map = vec![vec![None; width / size]; height / size];
+        let mut cube = CubeMap::new();
+        for px in 0..width / size {
+            for py in 0..height / size {
+                let filled = (0..size)
+                    .flat_map(|dy| (0..size).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| at(px * size + dx, py * size + dy) != Blank)
+                    .count();
+                if filled == 0 {
+                    continue;
+                }
+                anyhow::ensure!(
+                    filled == size * size,
+                    "face at ({}, {}) is only partly filled in",
+                    px * size,
+                    py * size
+                );
                 let curr = panels.len();
+                anyhow::ensure!(curr < 6, "too many faces");
                 if py > 0 {
                     if let Some(prev) = mini_map[py - 1][px] {
-                        cube.apply_tape((prev, Down), (curr, Down));
+                        cube.apply_tape((prev, Down), (curr, Down))?;
                     }
                 }
                 if px > 0 {
                     if let Some(prev) = mini_map[py][px - 1] {
-                        cube.apply_tape((prev, Right), (curr, Right));
+                        cube.apply_tape((prev, Right), (curr, Right))?;
                     }
                 }
                 mini_map[py][px] = Some(curr);
                 panels.push((px, py));
             }
         }
+        // Six faces joined by five edges are connected exactly when they
+        // form a tree.
+        anyhow::ensure!(cube.count == 5, "faces must be connected, without loops");
+        cube.finish()?;
+
+        Ok(Net {
+            size,
+            panels,
+            mini_map,
+            cube,
+        })
+    }
+
+    fn panel_at(&self, x: usize, y: usize) -> Panel {
+        self.mini_map[y / self.size][x / self.size].unwrap()
+    }
+
+    /// Take one step from `(x, y)` facing `h`, around the cube if that goes
+    /// off the edge of a face. Returns the new position and facing.
+    fn step(&self, x: usize, y: usize, h: Dir) -> (usize, usize, Dir) {
+        let cs = self.size;
+        let (fx, fy) = (x % cs, y % cs);
+        let max = cs - 1;
+        let leaving = match h {
+            Right => fx == max,
+            Down => fy == max,
+            Left => fx == 0,
+            Up => fy == 0,
+        };
+        if !leaving {
+            return (
+                (x as i64 + h.dx()) as usize,
+                (y as i64 + h.dy()) as usize,
+                h,
+            );
+        }
+
+        let (dest_panel, new_dir) = self.cube.get((self.panel_at(x, y), h));
+        // Distance along the edge, counting clockwise around the old face.
+        let slot = match h {
+            Right => fy,
+            Down => max - fx,
+            Left => max - fy,
+            Up => fx,
+        };
+        let (xx, yy) = match new_dir {
+            Right => (0, slot),
+            Down => (max - slot, 0),
+            Left => (max, max - slot),
+            Up => (slot, max),
+        };
+        let (px, py) = self.panels[dest_panel];
+        (xx + cs * px, yy + cs * py, new_dir)
     }
-    assert_eq!(panels.len(), 6);
-    cube.finish();
+
+    /// For each face and direction, a letter naming the cube edge there.
+    /// Edges glued together get the same letter.
+    fn edge_labels(&self) -> [[char; 4]; 6] {
+        let mut labels = [[' '; 4]; 6];
+        let mut next = b'a';
+        for p in 0..6 {
+            for d in [Up, Right, Down, Left] {
+                if labels[p][d as usize] == ' ' {
+                    let (q, e) = self.cube.get((p, d));
+                    labels[p][d as usize] = next as char;
+                    labels[q][e.flip() as usize] = next as char;
+                    next += 1;
+                }
+            }
+        }
+        labels
+    }
+
+    /// Draw the net with faces numbered and edges lettered, one box per
+    /// face regardless of `size`.
+    fn to_ascii(&self) -> String {
+        let labels = self.edge_labels();
+        let rows = self.mini_map.len();
+        let cols = self.mini_map[0].len();
+        let mut canvas = vec![vec![' '; 6 * cols + 1]; 4 * rows + 1];
+        for (p, &(px, py)) in self.panels.iter().enumerate() {
+            let (x0, y0) = (6 * px, 4 * py);
+            for i in 0..=6 {
+                canvas[y0][x0 + i] = '-';
+                canvas[y0 + 4][x0 + i] = '-';
+            }
+            for j in 0..=4 {
+                canvas[y0 + j][x0] = '|';
+                canvas[y0 + j][x0 + 6] = '|';
+            }
+            for (dx, dy) in [(0, 0), (6, 0), (0, 4), (6, 4)] {
+                canvas[y0 + dy][x0 + dx] = '+';
+            }
+            canvas[y0][x0 + 3] = labels[p][Up as usize];
+            canvas[y0 + 2][x0 + 6] = labels[p][Right as usize];
+            canvas[y0 + 4][x0 + 3] = labels[p][Down as usize];
+            canvas[y0 + 2][x0] = labels[p][Left as usize];
+            canvas[y0 + 2][x0 + 3] = char::from_digit(p as u32, 10).unwrap();
+        }
+        canvas
+            .into_iter()
+            .map(|row| row.into_iter().collect::<String>().trim_end().to_string() + "\n")
+            .collect()
+    }
+
+    /// Same picture as `to_ascii`, as an SVG document.
+    fn to_svg(&self) -> String {
+        const SCALE: usize = 60;
+        let labels = self.edge_labels();
+        let width = SCALE * self.mini_map[0].len();
+        let height = SCALE * self.mini_map.len();
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             font-family=\"monospace\" text-anchor=\"middle\" dominant-baseline=\"middle\">\n",
+            width + 2,
+            height + 2
+        );
+        for (p, &(px, py)) in self.panels.iter().enumerate() {
+            let (x0, y0) = (SCALE * px + 1, SCALE * py + 1);
+            let half = SCALE / 2;
+            out += &format!(
+                "  <rect x=\"{x0}\" y=\"{y0}\" width=\"{SCALE}\" height=\"{SCALE}\" \
+                 fill=\"#eee\" stroke=\"black\"/>\n"
+            );
+            out += &format!(
+                "  <text x=\"{}\" y=\"{}\" font-size=\"20\">{p}</text>\n",
+                x0 + half,
+                y0 + half
+            );
+            for (d, (dx, dy)) in [
+                (Up, (half, 8)),
+                (Right, (SCALE - 8, half)),
+                (Down, (half, SCALE - 8)),
+                (Left, (8, half)),
+            ] {
+                out += &format!(
+                    "  <text x=\"{}\" y=\"{}\" font-size=\"12\" fill=\"blue\">{}</text>\n",
+                    x0 + dx,
+                    y0 + dy,
+                    labels[p][d as usize]
+                );
+            }
+        }
+        out += "</svg>\n";
+        out
+    }
+}
+
+/// Draw the cube net of the map in `text` as ASCII art.
+pub fn net_ascii(text: &str) -> anyhow::Result<String> {
+    let (grid, _) = parse_input(text)?;
+    Ok(Net::new(&grid)?.to_ascii())
+}
+
+/// Draw the cube net of the map in `text` as an SVG document.
+pub fn net_svg(text: &str) -> anyhow::Result<String> {
+    let (grid, _) = parse_input(text)?;
+    Ok(Net::new(&grid)?.to_svg())
+}
+
+#[aoc(day22, part2, jorendorff)]
+fn part_2(input: &Input) -> anyhow::Result<usize> {
+    // Rank 239 on this star's leaderboard.
+    let (grid, prog) = input;
+    let net = Net::new(grid)?;
+    let at = |x: usize, y: usize| grid[y].get(x).copied().unwrap_or(Blank);
 
     let mut y = 0;
     let mut x = grid[0].iter().take_while(|c| **c != Open).count();
@@ -276,75 +479,24 @@ fn part_2(input: &Input) -> usize {
             TurnLeft => h = h.turn_left(),
             TurnRight => h = h.turn_right(),
             Go(n) => {
-                'zig: for _ in 0..*n {
-                    assert_eq!(grid[y][x], Open);
-
-                    // The casts to usize here wrap to a huge value if x+dx or
-                    // y+dy goes negative. We need to know we left the panel.
-                    let xx = (x as i64 + h.dx()) as usize;
-                    let yy = (y as i64 + h.dy()) as usize;
-                    let sq = if xx >= width || yy >= height {
-                        Blank
-                    } else {
-                        grid[yy][xx]
-                    };
-
-                    match sq {
-                        Wall => {
-                            // don't move
-                            break 'zig;
-                        }
-                        Open => {
-                            x = xx;
-                            y = yy;
-                        }
-                        Blank => {
-                            // We have wandered off the map! Perform stitching.
-                            assert_ne!(
-                                (x / cs, y / cs),
-                                (xx / cs, yy / cs),
-                                "we must have changed panels"
-                            );
-                            let old_panel = mini_map[y / cs][x / cs].unwrap();
-                            let (dest_panel, new_dir) = cube.get((old_panel, h));
-
-                            let slot = match h {
-                                Right => y % cs,
-                                Down => (cs - 1) - x % cs,
-                                Left => (cs - 1) - y % cs,
-                                Up => x % cs,
-                            };
-                            let max = cs - 1;
-                            let (mut xx, mut yy) = match new_dir {
-                                Right => (0, slot),
-                                Down => (max - slot, 0),
-                                Left => (max, max - slot),
-                                Up => (slot, max),
-                            };
-
-                            xx += cs * panels[dest_panel].0;
-                            yy += cs * panels[dest_panel].1;
-                            match grid[yy][xx] {
-                                Open => {
-                                    x = xx;
-                                    y = yy;
-                                    h = new_dir;
-                                }
-                                Wall => break 'zig,
-                                Blank => panic!("wrong turn in teleportation"),
-                            }
-                        }
+                for _ in 0..*n {
+                    let (xx, yy, hh) = net.step(x, y, h);
+                    match at(xx, yy) {
+                        Open => (x, y, h) = (xx, yy, hh),
+                        Wall => break,
+                        Blank => unreachable!("Net::step always lands on a face"),
                     }
                 }
             }
         }
     }
-    1000 * (y + 1) + 4 * (x + 1) + h.facing()
+    Ok(1000 * (y + 1) + 4 * (x + 1) + h.facing())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     const EXAMPLE: &str = "        ...#
         .#..
@@ -369,6 +521,202 @@ mod tests {
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 5031);
+        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()).unwrap(), 5031);
+    }
+
+    const NETS: [&str; 11] = [
+        "#...\n####\n#...\n",
+        "#...\n####\n.#..\n",
+        "#...\n####\n..#.\n",
+        "#...\n####\n...#\n",
+        ".#..\n####\n.#..\n",
+        ".#..\n####\n..#.\n",
+        "##..\n.###\n.#..\n",
+        "##..\n.###\n..#.\n",
+        "##..\n.###\n...#\n",
+        "##..\n.##.\n..##\n",
+        "###..\n..###\n",
+    ];
+
+    type Shape = Vec<(usize, usize)>;
+
+    fn shape(pic: &str) -> Shape {
+        let mut cells = vec![];
+        for (y, line) in pic.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == '#' {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// All 8 rotations and reflections of a shape, each moved to the origin
+    /// and sorted, so equal shapes compare equal.
+    fn orientations(cells: &Shape) -> Vec<Shape> {
+        (0..8)
+            .map(|k| {
+                let moved = cells
+                    .iter()
+                    .map(|&(x, y)| {
+                        let (x, y) = (x as i64, y as i64);
+                        let (x, y) = if k & 4 != 0 { (-x, y) } else { (x, y) };
+                        match k & 3 {
+                            0 => (x, y),
+                            1 => (-y, x),
+                            2 => (-x, -y),
+                            _ => (y, -x),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let x0 = moved.iter().map(|c| c.0).min().unwrap();
+                let y0 = moved.iter().map(|c| c.1).min().unwrap();
+                let mut out = moved
+                    .into_iter()
+                    .map(|(x, y)| ((x - x0) as usize, (y - y0) as usize))
+                    .collect::<Shape>();
+                out.sort();
+                out
+            })
+            .collect()
+    }
+
+    /// A map with a `size` by `size` face of open squares for each cell.
+    fn grid(cells: &Shape, size: usize) -> Vec<Vec<Square>> {
+        let height = cells.iter().map(|c| c.1 + 1).max().unwrap();
+        let mut grid = vec![vec![]; height * size];
+        for &(x, y) in cells {
+            for row in &mut grid[y * size..(y + 1) * size] {
+                if row.len() < (x + 1) * size {
+                    row.resize((x + 1) * size, Blank);
+                }
+                row[x * size..(x + 1) * size].fill(Open);
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_every_net() {
+        let size = 3;
+        for pic in NETS {
+            for cells in orientations(&shape(pic)) {
+                let grid = grid(&cells, size);
+                let net = Net::new(&grid).unwrap_or_else(|err| panic!("{cells:?}: {err}"));
+                for (y, row) in grid.iter().enumerate() {
+                    for (x, sq) in row.iter().enumerate() {
+                        if *sq == Blank {
+                            continue;
+                        }
+                        for h in [Right, Down, Left, Up] {
+                            // Stepping forward then back returns to the start.
+                            let (xx, yy, hh) = net.step(x, y, h);
+                            assert_ne!(grid[yy][xx], Blank);
+                            let (bx, by, bh) = net.step(xx, yy, hh.flip());
+                            assert_eq!((bx, by, bh.flip()), (x, y, h));
+
+                            // Going straight circles the cube.
+                            let mut p = (x, y, h);
+                            for _ in 0..4 * size {
+                                p = net.step(p.0, p.1, p.2);
+                            }
+                            assert_eq!(p, (x, y, h));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_nets_fold() {
+        // Grow all hexominoes one square at a time, and check that exactly
+        // the 11 cube nets fold up.
+        let mut shapes = vec![vec![(1, 1)]];
+        for _ in 1..6 {
+            let mut grown = HashSet::new();
+            for cells in &shapes {
+                for &(x, y) in cells {
+                    for (nx, ny) in [(x + 1, y), (x, y + 1), (x - 1, y), (x, y - 1)] {
+                        if !cells.contains(&(nx, ny)) {
+                            let mut bigger = cells.clone();
+                            bigger.push((nx, ny));
+                            // Shift away from 0 so there's room to grow.
+                            let mut canonical = orientations(&bigger)[0].clone();
+                            for c in &mut canonical {
+                                *c = (c.0 + 1, c.1 + 1);
+                            }
+                            grown.insert(canonical);
+                        }
+                    }
+                }
+            }
+            shapes = grown.into_iter().collect();
+        }
+        assert_eq!(shapes.len(), 216); // fixed hexominoes
+
+        let nets = NETS
+            .iter()
+            .flat_map(|pic| orientations(&shape(pic)))
+            .collect::<HashSet<_>>();
+        let mut free = HashSet::new();
+        for cells in &shapes {
+            let folds = Net::new(&grid(cells, 2)).is_ok();
+            let cells = orientations(cells)[0].clone();
+            assert_eq!(folds, nets.contains(&cells), "{cells:?}");
+            if folds {
+                free.insert(orientations(&cells).into_iter().min().unwrap());
+            }
+        }
+        assert_eq!(free.len(), 11);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = |pic: &str| Net::new(&grid(&shape(pic), 2)).err().unwrap().to_string();
+        assert_eq!(
+            err("#####\n"),
+            "map area 20 isn't the surface area of a cube"
+        );
+        assert_eq!(err("###\n###\n"), "faces must be connected, without loops");
+        assert_eq!(
+            err("###.##\n.....#\n"),
+            "faces must be connected, without loops"
+        );
+        assert_eq!(err("##.#\n.###\n"), "faces overlap when folded");
+        assert_eq!(err("######\n"), "net doesn't fold into a cube");
+
+        let mut grid = grid(&shape(NETS[0]), 2);
+        grid[0][1] = Blank;
+        grid[0].push(Open);
+        assert_eq!(
+            Net::new(&grid).err().unwrap().to_string(),
+            "face at (0, 0) is only partly filled in"
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(
+            net_ascii(EXAMPLE).unwrap(),
+            "            +--a--+
+            |     |
+            e  2  h
+            |     |
++--a--+--e--+--i--+
+|     |     |     |
+d  0  b  1  f  3  j
+|     |     |     |
++--c--+--g--+--k--+--j--+
+            |     |     |
+            g  4  l  5  h
+            |     |     |
+            +--c--+--d--+
+"
+        );
+        let svg = net_svg(EXAMPLE).unwrap();
+        assert_eq!(svg.matches("<rect").count(), 6);
+        assert_eq!(svg.matches("<text").count(), 30);
     }
 }
@@ -1,38 +1,26 @@
+use adlib::Numeral;
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
-type Input = Vec<Vec<usize>>;
+type Input = Vec<String>;
 
 #[aoc_generator(day25, part1, jorendorff)]
 fn parse_input(text: &str) -> anyhow::Result<Input> {
-    let p = parser!(lines(char_of("=-012")+));
+    let p = parser!(lines(string(char_of("=-012")+)));
     Ok(p.parse(text)?)
 }
 
-fn encode(v: i64) -> String {
-    let mut s = if v > 2 {
-        encode((v + 2) / 5)
-    } else {
-        String::new()
-    };
-    s.push(['=', '-', '0', '1', '2'][((v + 2) as usize) % 5]);
-    s
+fn snafu() -> Numeral {
+    Numeral::balanced("=-012").unwrap()
 }
 
 #[aoc(day25, part1, jorendorff)]
-fn part_1(input: &Input) -> String {
+fn part_1(input: &Input) -> anyhow::Result<String> {
     // Rank 298 on this star's leaderboard.
-    let total = input.iter()
-        .map(|digits| {
-            let mut v = 0i64;
-            for d in digits {
-                v *= 5;
-                v += *d as i64 - 2;
-            }
-            v
-        })
-        .sum::<i64>();
-    encode(total)
+    let snafu = snafu();
+    Ok(input
+        .iter()
+        .try_fold("0".to_string(), |total, n| snafu.add(&total, n))?)
 }
 
 #[cfg(test)]
@@ -57,9 +45,9 @@ mod tests {
 
     #[test]
     fn test_part_1() {
-        assert_eq!(&encode(2022), "1=11-2");
-        assert_eq!(&encode(12345), "1-0---0");
-        assert_eq!(&encode(314159265), "1121-1110-1=0");
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), "2=-1=0".to_string());
+        assert_eq!(snafu().format_i128(2022), "1=11-2");
+        assert_eq!(snafu().format_i128(12345), "1-0---0");
+        assert_eq!(snafu().format_i128(314159265), "1121-1110-1=0");
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), "2=-1=0");
     }
 }
@@ -0,0 +1,13 @@
+//! Solve a nonogram and print the picture.
+//!
+//! Usage: nonogram CLUE_FILE
+
+fn main() -> anyhow::Result<()> {
+    let Some(filename) = std::env::args().nth(1) else {
+        eprintln!("usage: nonogram CLUE_FILE");
+        std::process::exit(1);
+    };
+    let text = std::fs::read_to_string(filename)?;
+    print!("{}", ad2023::day12::solve_nonogram(&text)?);
+    Ok(())
+}
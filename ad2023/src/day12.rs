@@ -21,60 +21,269 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
+/// What one line's clue says about it: the number of arrangements that fit,
+/// and the cells that are the same in all of them. Other cells are `Unk`.
+#[derive(Debug, PartialEq)]
+struct LineSolution {
+    count: u64,
+    cells: Vec<Status>,
+}
+
+/// Whether a run of length `size` can start at `start`, followed by the end of
+/// the line or a gap.
 fn can_place(log: &[Status], start: usize, size: usize) -> bool {
-    log[start..start + size].iter().all(|x| *x != Op)
+    start + size <= log.len()
+        && log[start..start + size].iter().all(|x| *x != Op)
         && (start + size == log.len() || log[start + size] != Dmg)
 }
 
-fn place_next(log: &[Status], mut start: usize, size: usize) -> Vec<u64> {
-    let mut answers = vec![];
-    while start + size <= log.len() {
-        if can_place(log, start, size) {
-            answers.push(1);
-        } else {
-            answers.push(0);
+fn solve_line(log: &[Status], sizes: &[usize]) -> LineSolution {
+    let n = log.len();
+    let m = sizes.len();
+    // One step from state (i, k), meaning cells 0..i are decided and k runs
+    // are placed: either cell i is empty, or run k starts at i (and the gap
+    // after it is consumed too).
+    let empty = |i: usize| (log[i] != Dmg).then_some(i + 1);
+    let run = |i: usize, k: usize| {
+        (k < m && can_place(log, i, sizes[k])).then(|| (i + sizes[k] + 1).min(n))
+    };
+
+    // fwd[i][k] = ways to reach state (i, k) from (0, 0)
+    let mut fwd = vec![vec![0u64; m + 1]; n + 1];
+    fwd[0][0] = 1;
+    for i in 0..n {
+        for k in 0..=m {
+            let ways = fwd[i][k];
+            if ways == 0 {
+                continue;
+            }
+            if let Some(j) = empty(i) {
+                fwd[j][k] += ways;
+            }
+            if let Some(j) = run(i, k) {
+                fwd[j][k + 1] += ways;
+            }
         }
-        if log[start] == Dmg {
-            break;
+    }
+
+    // bwd[i][k] = ways to get from state (i, k) to (n, m)
+    let mut bwd = vec![vec![0u64; m + 1]; n + 1];
+    bwd[n][m] = 1;
+    for i in (0..n).rev() {
+        for k in 0..=m {
+            bwd[i][k] = empty(i).map_or(0, |j| bwd[j][k]) + run(i, k).map_or(0, |j| bwd[j][k + 1]);
         }
-        start += 1;
     }
-    answers
+
+    // For each cell, count the arrangements where it's filled. Runs cover a
+    // range of cells, so accumulate a difference array.
+    let count = fwd[n][m];
+    let mut diff = vec![0i128; n + 1];
+    for i in 0..n {
+        for k in 0..m {
+            if let Some(j) = run(i, k) {
+                let ways = (fwd[i][k] * bwd[j][k + 1]) as i128;
+                diff[i] += ways;
+                diff[i + sizes[k]] -= ways;
+            }
+        }
+    }
+    let mut filled = 0;
+    let cells = diff[..n]
+        .iter()
+        .map(|d| {
+            filled += d;
+            match filled {
+                _ if count == 0 => Unk,
+                0 => Op,
+                f if f == count as i128 => Dmg,
+                _ => Unk,
+            }
+        })
+        .collect();
+    LineSolution { count, cells }
 }
 
 fn solve(log: &[Status], sizes: &[usize]) -> u64 {
-    // Let f(n, i) = number of ways groups 0..n can be placed to put group n-1 starting at offset i.
-    // Then f(1, i) can be computed ad hoc
-    let mut f = place_next(log, 0, sizes[0]);
-    f.resize(log.len(), 0); // extend with zeros to full length
-
-    // and given f(n, i), each element of that row contributes to f(n+1, j)
-    let mut prev_len = sizes[0];
-    for &group_size in &sizes[1..] {
-        let mut g = vec![0; log.len()];
-        for (i, count) in f.into_iter().enumerate() {
-            if count != 0 {
-                for (j, jcount) in place_next(log, i + prev_len + 1, group_size)
-                    .into_iter()
-                    .enumerate()
-                {
-                    g[i + prev_len + 1 + j] += count * jcount;
+    solve_line(log, sizes).count
+}
+
+type Picture = Vec<Vec<Status>>;
+
+/// A nonogram puzzle: a clue for each row and each column.
+#[derive(Debug, PartialEq)]
+struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+impl Nonogram {
+    /// Parse a clue file. It has a `rows:` section and a `columns:` section,
+    /// each with one clue per line, numbers separated by spaces or commas.
+    /// `0` is the clue for a blank line. Blank lines and `#` comments are
+    /// ignored.
+    fn parse(text: &str) -> anyhow::Result<Nonogram> {
+        let mut rows = vec![];
+        let mut cols = vec![];
+        let mut section: Option<&mut Vec<Vec<usize>>> = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            match line {
+                "" => {}
+                "rows:" => section = Some(&mut rows),
+                "columns:" => section = Some(&mut cols),
+                _ => {
+                    let clue = line
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|word| !word.is_empty())
+                        .map(|word| word.parse::<usize>())
+                        .collect::<Result<Vec<usize>, _>>()
+                        .map_err(|err| anyhow::anyhow!("line {}: {err}", i + 1))?;
+                    let clue = clue.into_iter().filter(|&n| n != 0).collect();
+                    section
+                        .as_mut()
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("line {}: clue before `rows:` or `columns:`", i + 1)
+                        })?
+                        .push(clue);
+                }
+            }
+        }
+        let puzzle = Nonogram { rows, cols };
+        puzzle.check()?;
+        Ok(puzzle)
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.rows.is_empty() && !self.cols.is_empty(),
+            "puzzle needs at least one row and one column"
+        );
+        let (height, width) = (self.rows.len(), self.cols.len());
+        for (kind, clues, len) in [("row", &self.rows, width), ("column", &self.cols, height)] {
+            for (i, clue) in clues.iter().enumerate() {
+                let need = clue.iter().sum::<usize>() + clue.len().saturating_sub(1);
+                anyhow::ensure!(need <= len, "{kind} {i} clue doesn't fit in {len} cells");
+            }
+        }
+        let total = |clues: &[Vec<usize>]| clues.iter().flatten().sum::<usize>();
+        anyhow::ensure!(
+            total(&self.rows) == total(&self.cols),
+            "rows fill {} cells but columns fill {}",
+            total(&self.rows),
+            total(&self.cols)
+        );
+        Ok(())
+    }
+
+    fn blank(&self) -> Picture {
+        vec![vec![Unk; self.cols.len()]; self.rows.len()]
+    }
+
+    /// Fill in every cell that the clues force, one line at a time, until
+    /// nothing changes. Returns false if some line has no arrangements left.
+    fn propagate(&self, pic: &mut Picture) -> bool {
+        let mut dirty_rows = vec![true; self.rows.len()];
+        let mut dirty_cols = vec![true; self.cols.len()];
+        while dirty_rows.contains(&true) || dirty_cols.contains(&true) {
+            for y in 0..self.rows.len() {
+                if !std::mem::take(&mut dirty_rows[y]) {
+                    continue;
+                }
+                let line = solve_line(&pic[y], &self.rows[y]);
+                if line.count == 0 {
+                    return false;
+                }
+                for (x, cell) in line.cells.into_iter().enumerate() {
+                    if cell != Unk && pic[y][x] == Unk {
+                        pic[y][x] = cell;
+                        dirty_cols[x] = true;
+                    }
+                }
+            }
+            for x in 0..self.cols.len() {
+                if !std::mem::take(&mut dirty_cols[x]) {
+                    continue;
+                }
+                let column = pic.iter().map(|row| row[x]).collect::<Vec<_>>();
+                let line = solve_line(&column, &self.cols[x]);
+                if line.count == 0 {
+                    return false;
+                }
+                for (y, cell) in line.cells.into_iter().enumerate() {
+                    if cell != Unk && pic[y][x] == Unk {
+                        pic[y][x] = cell;
+                        dirty_rows[y] = true;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Up to `limit` solutions. Propagates as far as possible, then guesses
+    /// the first unknown cell both ways.
+    fn solutions(&self, limit: usize) -> Vec<Picture> {
+        let mut found = vec![];
+        let mut stack = vec![self.blank()];
+        while let Some(mut pic) = stack.pop() {
+            if !self.propagate(&mut pic) {
+                continue;
+            }
+            let unknown = pic
+                .iter()
+                .enumerate()
+                .find_map(|(y, row)| row.iter().position(|&c| c == Unk).map(|x| (x, y)));
+            match unknown {
+                None => {
+                    found.push(pic);
+                    if found.len() == limit {
+                        break;
+                    }
+                }
+                Some((x, y)) => {
+                    let mut guess = pic.clone();
+                    guess[y][x] = Op;
+                    stack.push(guess);
+                    pic[y][x] = Dmg;
+                    stack.push(pic);
                 }
             }
         }
-        prev_len = group_size;
-        f = g;
+        found
     }
 
-    // now kill answers that are impossible because there are more #'s at the end
-    for i in 0..f.len() {
-        if f[i] != 0 && log[i + prev_len..].iter().any(|x| *x == Dmg) {
-            f[i] = 0;
+    /// The solution, if there is exactly one.
+    fn solve(&self) -> anyhow::Result<Picture> {
+        let mut found = self.solutions(2);
+        match found.len() {
+            0 => anyhow::bail!("puzzle has no solution"),
+            1 => Ok(found.pop().unwrap()),
+            _ => anyhow::bail!("puzzle has more than one solution"),
         }
     }
+}
+
+/// Draw a picture using the same characters as the puzzle input.
+fn render(pic: &Picture) -> String {
+    pic.iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| match cell {
+                    Unk => '?',
+                    Op => '.',
+                    Dmg => '#',
+                })
+                .chain(Some('\n'))
+                .collect::<String>()
+        })
+        .collect()
+}
 
-    // and the answer is the sum of f(n, i) for n=len(sizes), i=0..len(log).
-    f.into_iter().sum::<u64>()
+/// Solve the nonogram in a clue file (see `Nonogram::parse`) and draw the
+/// picture.
+pub fn solve_nonogram(text: &str) -> anyhow::Result<String> {
+    Ok(render(&Nonogram::parse(text)?.solve()?))
 }
 
 #[aoc(day12, part1, jorendorff)]
@@ -150,4 +359,136 @@ mod tests {
     fn test_part_2() {
         assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 525152);
     }
+
+    #[track_caller]
+    fn forced(case: &str) -> String {
+        let (log, sizes) = parse_input(&(case.to_string() + "\n"))
+            .unwrap()
+            .pop()
+            .unwrap();
+        render(&vec![solve_line(&log, &sizes).cells])
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn test_forced_cells() {
+        assert_eq!(forced("?????????? 8"), "??######??");
+        assert_eq!(forced("?????????? 3,4"), "??#???##??");
+        assert_eq!(forced("?????????? 3,4,1"), "###.####.#");
+        assert_eq!(forced("?????????? 4,5"), "####.#####");
+        assert_eq!(forced("????#????? 1"), "....#.....");
+        assert_eq!(forced("???.### 1,1,3"), "#.#.###");
+        assert_eq!(forced(".??..??...?##. 1,1,3"), ".??..??...###.");
+
+        // no arrangements: nothing is forced
+        let line = solve_line(&[Dmg, Dmg], &[1]);
+        assert_eq!(
+            line,
+            LineSolution {
+                count: 0,
+                cells: vec![Unk, Unk]
+            }
+        );
+
+        // no runs: everything is empty
+        assert_eq!(solve_line(&[Unk, Unk], &[]).cells, vec![Op, Op]);
+    }
+
+    // A sailboat.
+    const BOAT: &str = "\
+rows:
+1
+2
+3   # the sail
+1
+6
+4
+
+columns:
+1
+1, 2
+2 2
+6
+2
+1
+";
+
+    #[test]
+    fn test_nonogram() {
+        let puzzle = Nonogram::parse(BOAT).unwrap();
+        assert_eq!(puzzle.rows[2], vec![3]);
+        assert_eq!(puzzle.cols[1], vec![1, 2]);
+
+        let mut pic = puzzle.blank();
+        assert!(puzzle.propagate(&mut pic));
+        let solution = puzzle.solve().unwrap();
+        assert_eq!(pic, solution);
+        assert_eq!(
+            solve_nonogram(BOAT).unwrap(),
+            "\
+...#..
+..##..
+.###..
+...#..
+######
+.####.
+"
+        );
+    }
+
+    #[test]
+    fn test_backtracking() {
+        // Propagation gets nowhere on a checkerboard's clues; both
+        // checkerboards fit.
+        let puzzle = Nonogram::parse("rows:\n1\n1\ncolumns:\n1\n1\n").unwrap();
+        let mut pic = puzzle.blank();
+        assert!(puzzle.propagate(&mut pic));
+        assert_eq!(render(&pic), "??\n??\n");
+
+        let solutions = puzzle.solutions(10);
+        assert_eq!(solutions.len(), 2);
+        assert_eq!(render(&solutions[0]), "#.\n.#\n");
+        assert_eq!(render(&solutions[1]), ".#\n#.\n");
+        assert_eq!(
+            puzzle.solve().unwrap_err().to_string(),
+            "puzzle has more than one solution"
+        );
+
+        // Propagation stalls on this one too, but only one guess works out.
+        let puzzle = Nonogram::parse("rows:\n3\n4\n3\n1\n0\ncolumns:\n1 1\n3\n3\n2\n1\n").unwrap();
+        let mut pic = puzzle.blank();
+        assert!(puzzle.propagate(&mut pic));
+        assert_eq!(render(&pic), "??#??\n?###?\n?##?.\n??..?\n.....\n");
+        assert_eq!(
+            render(&puzzle.solve().unwrap()),
+            "###..\n.####\n.###.\n#....\n.....\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |text: &str| Nonogram::parse(text).unwrap_err().to_string();
+        assert_eq!(err("1\n"), "line 1: clue before `rows:` or `columns:`");
+        assert_eq!(
+            err("rows:\n1\ncolumns:\nx\n"),
+            "line 4: invalid digit found in string"
+        );
+        assert_eq!(
+            err("rows:\n3\ncolumns:\n1\n1\n"),
+            "row 0 clue doesn't fit in 2 cells"
+        );
+        assert_eq!(
+            err("rows:\n1\ncolumns:\n1\n1\n"),
+            "rows fill 1 cells but columns fill 2"
+        );
+        assert_eq!(
+            Nonogram::parse("rows:\n2\n0\ncolumns:\n0\n2\n")
+                .unwrap()
+                .solve()
+                .unwrap_err()
+                .to_string(),
+            "puzzle has no solution"
+        );
+    }
 }
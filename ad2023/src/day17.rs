@@ -1,95 +1,39 @@
-use pathfinding::directed::dijkstra;
+use adlib::*;
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
-type Input = Vec<Vec<usize>>;
+type Input = Grid<usize>;
 
 #[aoc_generator(day17, part1, jorendorff)]
 #[aoc_generator(day17, part2, jorendorff)]
 fn parse_input(text: &str) -> anyhow::Result<Input> {
     let p = parser!(lines(line(digit+)));
-    Ok(p.parse(text)?)
+    Ok(Grid {
+        data: p.parse(text)?,
+    })
 }
 
-const RIGHT: usize = 0;
-const UP: usize = 1;
-const LEFT: usize = 2;
-const DOWN: usize = 3;
-
-fn reverse(dir: usize) -> usize {
-    match dir {
-        RIGHT => LEFT,
-        UP => DOWN,
-        LEFT => RIGHT,
-        DOWN => UP,
-        _ => panic!(),
-    }
-}
-
-fn bump_row(row: usize, dir: usize) -> usize {
-    row.wrapping_add([0, usize::MAX, 0, 1][dir])
-}
-
-fn bump_col(col: usize, dir: usize) -> usize {
-    col.wrapping_add([1, 0, usize::MAX, 0][dir])
+fn solve(grid: &Input, rules: &MoveRules) -> usize {
+    let end = Point {
+        row: grid.num_rows() - 1,
+        col: grid.num_cols() - 1,
+    };
+    let start = Point { row: 0, col: 0 };
+    let (heat_loss, _path) =
+        min_cost_path(grid, rules, start, |&cost| Some(cost as u64), |p| p == end).unwrap();
+    heat_loss as usize
 }
 
 #[aoc(day17, part1, jorendorff)]
 fn part_1(input: &Input) -> usize {
     // #101 on the global leaderboard
-    let h = input.len();
-    let w = input[0].len();
-
-    dijkstra::dijkstra(
-        &(0, 0, 0, 0),  // row, col, dir, count
-        |&(r, c, dir, count)| {
-            [LEFT, RIGHT, UP, DOWN].into_iter().flat_map(move |dir1| {
-                if dir1 != reverse(dir) && (dir1 != dir || count < 3) {
-                    let r1 = bump_row(r, dir1);
-                    let c1 = bump_col(c, dir1);
-
-                    let count1 = if dir1 == dir { count + 1 } else { 1 };
-                    if r1 < h && c1 < w {
-                        let cost = input[r1][c1];
-                        return Some(((r1, c1, dir1, count1), cost));
-                    }
-                }
-                None
-            })
-        },
-        |&(r, c, _dir, _count)| r == h - 1 && c == w - 1,
-    )
-        .unwrap()
-        .1
+    solve(input, &MoveRules::crucible())
 }
 
 #[aoc(day17, part2, jorendorff)]
 fn part_2(input: &Input) -> usize {
     // #68 on the global leaderboard
-    let h = input.len();
-    let w = input[0].len();
-
-    dijkstra::dijkstra(
-        &(0, 0, 0, 0), // row, col, dir, count
-        |&(r, c, dir, count)| {
-            [LEFT, RIGHT, UP, DOWN].into_iter()
-                .filter_map(move |dir1| {
-                    if (dir1 == dir && count < 10) || (dir1 != dir && dir1 != reverse(dir) && count >= 4) {
-                        let r1 = bump_row(r, dir1);
-                        let c1 = bump_col(c, dir1);
-
-                        let count1 = if dir1 == dir { count + 1 } else { 1 };
-                        if r1 < h && c1 < w {
-                            return Some(((r1, c1, dir1, count1), input[r1][c1]));
-                        }
-                    }
-                    None
-                })
-        },
-        |&(r, c, _dir, count)| r == h - 1 && c == w - 1 && count >= 4,
-    )
-        .unwrap()
-        .1
+    solve(input, &MoveRules::ultra_crucible())
 }
 
 #[cfg(test)]
@@ -1,4 +1,5 @@
 use std::collections::*;
+use std::fmt::Write;
 
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
@@ -8,7 +9,6 @@ struct Machine {
     modules: BTreeMap<String, Module>,
     hi_count: u64,
     lo_count: u64,
-    mg_signaled: bool,
 }
 
 #[derive(Clone)]
@@ -39,7 +39,7 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
         "broadcaster -> " dest:repeat_sep(string(alpha+), ", ")
             => ("broadcaster".to_string(), Module { kind: Broadcaster, dest })
     })));
-    Ok(Machine { modules: p.parse(text)?, lo_count: 0, hi_count: 0, mg_signaled: false })
+    Ok(Machine { modules: p.parse(text)?, lo_count: 0, hi_count: 0 })
 }
 
 
@@ -75,27 +75,23 @@ impl Machine {
         v
     }
 
-    fn push_button(&mut self) {
-        self.mg_signaled = false;
+    /// Push the button once. `on_pulse(src, target, is_high)` is called for
+    /// every pulse, in the order they're delivered.
+    fn push_button(&mut self, mut on_pulse: impl FnMut(&str, &str, bool)) {
         let mut q: VecDeque<_> = [("button".to_string(), "broadcaster".to_string(), false)].into_iter().collect();
 
         while let Some((src, target, is_high)) = q.pop_front() {
-            if target == "mg" && is_high {
-                println!("{src} -{}-> {target}", if is_high { "high" } else { "low" });
-                self.mg_signaled = true;
-            }
+            on_pulse(&src, &target, is_high);
             if is_high {
                 self.hi_count += 1;
             } else {
                 self.lo_count += 1;
             }
             match self.modules.get_mut(&target) {
-                Some(Module { kind: FlipFlop { is_on }, dest }) => {
-                    if !is_high {
-                        *is_on = !*is_on;
-                        for d in dest {
-                            q.push_back((target.clone(), d.clone(), *is_on));
-                        }
+                Some(Module { kind: FlipFlop { is_on }, dest }) if !is_high => {
+                    *is_on = !*is_on;
+                    for d in dest {
+                        q.push_back((target.clone(), d.clone(), *is_on));
                     }
                 }
                 Some(Module { kind: Conjunction { memory }, dest }) => {
@@ -110,10 +106,195 @@ impl Machine {
                         q.push_back((target.clone(), d.clone(), is_high));
                     }
                 }
-                None => {}
+                // Flip-flops ignore high pulses; untyped modules ignore everything.
+                Some(Module { kind: FlipFlop { .. }, .. }) | None => {}
+            }
+        }
+    }
+
+    /// Names of the modules that send pulses to `name`.
+    fn senders(&self, name: &str) -> Vec<&str> {
+        self.modules
+            .iter()
+            .filter(|(_, m)| m.dest.iter().any(|d| d == name))
+            .map(|(src, _)| src.as_str())
+            .collect()
+    }
+
+    fn is_flip_flop(&self, name: &str) -> bool {
+        matches!(self.modules.get(name), Some(Module { kind: FlipFlop { .. }, .. }))
+    }
+
+    fn is_conjunction(&self, name: &str) -> bool {
+        matches!(self.modules.get(name), Some(Module { kind: Conjunction { .. }, .. }))
+    }
+
+    /// A machine containing just the given modules, with the broadcaster
+    /// wired only to `start`.
+    fn submachine(&self, start: &str, members: &BTreeSet<String>) -> Machine {
+        let mut modules: BTreeMap<String, Module> = members
+            .iter()
+            .map(|name| (name.clone(), self.modules[name].clone()))
+            .collect();
+        modules.insert("broadcaster".to_string(), Module { kind: Broadcaster, dest: vec![start.to_string()] });
+        let mut machine = Machine { modules, hi_count: 0, lo_count: 0 };
+        machine.init();
+        machine
+    }
+
+    /// Graphviz source for the module graph. Each counter is drawn as a
+    /// cluster.
+    fn to_dot(&self, counters: &[Counter]) -> String {
+        let mut out = "digraph modules {\n".to_string();
+        for (i, counter) in counters.iter().enumerate() {
+            writeln!(out, "    subgraph cluster_{i} {{").unwrap();
+            writeln!(out, "        label=\"{}\";", counter.start).unwrap();
+            for name in &counter.modules {
+                writeln!(out, "        \"{name}\";").unwrap();
             }
+            writeln!(out, "    }}").unwrap();
         }
+        for (name, module) in &self.modules {
+            let shape = match module.kind {
+                FlipFlop { .. } => "box",
+                Conjunction { .. } => "invhouse",
+                Broadcaster => "doublecircle",
+            };
+            writeln!(out, "    \"{name}\" [shape={shape}];").unwrap();
+            for d in &module.dest {
+                writeln!(out, "    \"{name}\" -> \"{d}\";").unwrap();
+            }
+        }
+        out += "}\n";
+        out
+    }
+}
+
+/// One independent piece of the machine, started by the broadcaster and
+/// sending pulses to the final conjunction.
+struct Counter {
+    /// The module the broadcaster sends to.
+    start: String,
+    modules: BTreeSet<String>,
+    /// The module that sends to the final conjunction.
+    output: String,
+}
+
+/// Find the conjunction that drives `sink` and split everything upstream
+/// of it into counters that don't interact.
+fn split_counters(machine: &Machine, sink: &str) -> anyhow::Result<(String, Vec<Counter>)> {
+    let [last] = machine.senders(sink)[..] else {
+        anyhow::bail!("expected exactly one module sending to {sink}");
+    };
+    anyhow::ensure!(machine.is_conjunction(last), "{last} is not a conjunction");
+
+    let mut counters = vec![];
+    let mut claimed = BTreeSet::new();
+    for start in &machine.modules["broadcaster"].dest {
+        let mut modules = BTreeSet::new();
+        let mut todo = vec![start.clone()];
+        while let Some(name) = todo.pop() {
+            if name != last && machine.modules.contains_key(&name) && modules.insert(name.clone()) {
+                todo.extend(machine.modules[&name].dest.iter().cloned());
+            }
+        }
+
+        for name in &modules {
+            anyhow::ensure!(claimed.insert(name.clone()), "counters overlap at {name}");
+            for src in machine.senders(name) {
+                anyhow::ensure!(
+                    src == "broadcaster" || modules.contains(src),
+                    "{name} gets pulses from outside its counter"
+                );
+            }
+        }
+        let outputs = modules
+            .iter()
+            .filter(|name| machine.modules[*name].dest.iter().any(|d| d == last))
+            .collect::<Vec<_>>();
+        let [output] = outputs[..] else {
+            anyhow::bail!("counter starting at {start} doesn't have exactly one output");
+        };
+        counters.push(Counter { start: start.clone(), output: output.clone(), modules });
     }
+
+    anyhow::ensure!(
+        machine.senders(last).len() == counters.len(),
+        "{last} gets pulses from outside the counters"
+    );
+    Ok((last.to_string(), counters))
+}
+
+/// Read the counter's flip-flops as a binary number. Each flip-flop is one
+/// bit, lowest first, chained to the next. The bits wired into the
+/// counter's conjunction spell the period: when they are all on, the
+/// conjunction fires and resets every flip-flop to zero.
+fn binary_period(machine: &Machine, counter: &Counter) -> Option<u64> {
+    let mut chain = vec![counter.start.as_str()];
+    loop {
+        let dest = &machine.modules.get(*chain.last()?)?.dest;
+        let next = dest.iter().filter(|d| machine.is_flip_flop(d)).collect::<Vec<_>>();
+        match next[..] {
+            [] => break,
+            [d] if !chain.contains(&d.as_str()) => chain.push(d),
+            _ => return None,
+        }
+    }
+    if chain.len() >= 64 || counter.modules.iter().filter(|m| machine.is_flip_flop(m)).count() != chain.len() {
+        return None;
+    }
+
+    let dest = |name: &str| &machine.modules[name].dest;
+    let conjunctions = chain
+        .iter()
+        .flat_map(|ff| dest(ff))
+        .filter(|d| machine.is_conjunction(d))
+        .collect::<BTreeSet<_>>();
+    let [conj] = conjunctions.into_iter().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+
+    let mut period = 0;
+    let mut resets = BTreeSet::from([chain[0]]);
+    for (i, ff) in chain.iter().enumerate() {
+        if dest(ff).contains(conj) {
+            period |= 1 << i;
+        } else {
+            resets.insert(*ff);
+        }
+    }
+    let actual = dest(conj)
+        .iter()
+        .filter(|d| machine.is_flip_flop(d))
+        .map(|d| d.as_str())
+        .collect::<BTreeSet<_>>();
+    (actual == resets).then_some(period)
+}
+
+/// Most button presses to simulate when looking for a counter's period.
+const MAX_PRESSES: u64 = 1 << 16;
+
+/// Press the button until the counter sends a high pulse to `last`, then
+/// check that the counter is periodic: one more press should put it in the
+/// same state as the first press did. (Not the initial state, because
+/// conjunctions remember pulses from the first press.)
+fn simulated_period(machine: &Machine, counter: &Counter, last: &str) -> anyhow::Result<u64> {
+    let mut sub = machine.submachine(&counter.start, &counter.modules);
+    let mut after_first = None;
+    for presses in 1..=MAX_PRESSES {
+        let mut fired = false;
+        sub.push_button(|src, target, is_high| {
+            fired |= is_high && src == counter.output && target == last;
+        });
+        let state = after_first.get_or_insert_with(|| sub.dump_state());
+        if fired {
+            let state = state.clone();
+            sub.push_button(|_, _, _| {});
+            anyhow::ensure!(sub.dump_state() == state, "counter {} isn't periodic", counter.start);
+            return Ok(presses);
+        }
+    }
+    anyhow::bail!("counter {} didn't fire in {MAX_PRESSES} presses", counter.start);
 }
 
 #[aoc(day20, part1, jorendorff)]
@@ -121,13 +302,62 @@ fn part_1(input: &Input) -> u64 {
     let mut machine = input.clone();
     machine.init();
     for _ in 0..1000 {
-        machine.push_button();
+        machine.push_button(|_, _, _| {});
     }
     machine.lo_count * machine.hi_count
 }
 
-const MACHINE_1: &str = "\
-broadcaster -> ht
+#[aoc(day20, part2, jorendorff)]
+fn part_2(input: &Input) -> anyhow::Result<u64> {
+    let (last, counters) = split_counters(input, "rx")?;
+    let mut answer = 1;
+    for counter in &counters {
+        let period = match binary_period(input, counter) {
+            Some(period) => period,
+            None => simulated_period(input, counter, &last)?,
+        };
+        answer = num::integer::lcm(answer, period);
+    }
+    Ok(answer)
+}
+
+/// Graphviz source for the machine described by `text`, with each counter
+/// that feeds `rx` drawn as a cluster.
+pub fn module_graph_dot(text: &str) -> anyhow::Result<String> {
+    let machine = parse_input(text)?;
+    let (_, counters) = split_counters(&machine, "rx")?;
+    Ok(machine.to_dot(&counters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+broadcaster -> a, b, c
+%a -> b
+%b -> c
+%c -> inv
+&inv -> a
+";
+
+    const EXAMPLE_2: &str = "\
+broadcaster -> a
+%a -> inv, con
+&inv -> b
+%b -> con
+&con -> output
+";
+
+    #[test]
+    fn test_part_1() {
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 32000000);
+        assert_eq!(part_1(&parse_input(EXAMPLE_2).unwrap()), 11687500);
+    }
+
+    /// Four counters feeding `mg`, like a real puzzle input.
+    const COUNTERS: &str = "\
+broadcaster -> ht, gb, vk, zz
 %ht -> vp, nt
 %vp -> qj, nt
 %qj -> hj
@@ -142,10 +372,6 @@ broadcaster -> ht
 %xd -> nt
 &nt -> ds, hj, ht, rh, qj
 &rh -> mg
-";
-
-const MACHINE_2: &str = "\
-broadcaster -> gb
 %gb -> fx, th
 %fx -> th, bn
 %bn -> fz
@@ -160,10 +386,6 @@ broadcaster -> gb
 %cd -> th
 &th -> bn, gb, tt, hf, bk
 &hf -> mg
-";
-
-const MACHINE_3: &str = "\
-broadcaster -> vk
 %vk -> mp, ff
 %mp -> bq, ff
 %bq -> pr
@@ -178,10 +400,6 @@ broadcaster -> vk
 %cv -> ff
 &ff -> vd, bq, pr, vk, ql, jm
 &jm -> mg
-";
-
-const MACHINE_4: &str = "\
-broadcaster -> zz
 %zz -> rz, zs
 %rz -> zc
 %zc -> dh
@@ -196,67 +414,40 @@ broadcaster -> zz
 %bm -> zs
 &zs -> mr, pj, zz, dh, jg, zc, rz
 &jg -> mg
+&mg -> rx
 ";
 
-fn analyze_machine(desc: &str) -> u64 {
-    println!("BEGIN");
-    let mut machine = parse_input(desc).unwrap();
-    machine.init();
+    #[test]
+    fn test_periods() {
+        let machine = parse_input(COUNTERS).unwrap();
+        let (last, counters) = split_counters(&machine, "rx").unwrap();
+        assert_eq!(last, "mg");
+        let outputs = counters.iter().map(|c| c.output.as_str()).collect::<Vec<_>>();
+        assert_eq!(outputs, ["rh", "hf", "jm", "jg"]);
 
-    let mut seen = HashMap::new();
-    seen.insert(machine.dump_state(), 0);
-    for i in 1.. {
-        machine.push_button();
-        if machine.mg_signaled {
-            println!("After {i} presses, mg signaled high");
-        }
-        let s = machine.dump_state();
-        if let Some(v) = seen.get(&s) {
-            println!("After {} presses, at same state as after {v}", i);
-            println!();
-            return i - 1;
+        let periods = counters
+            .iter()
+            .map(|c| binary_period(&machine, c).unwrap())
+            .collect::<Vec<u64>>();
+        assert_eq!(periods, [4019, 3947, 4003, 3793]);
+        for (counter, period) in counters.iter().zip(periods) {
+            assert_eq!(simulated_period(&machine, counter, &last).unwrap(), period);
         }
-        seen.insert(s, i);
+        assert_eq!(part_2(&machine).unwrap(), 4019 * 3947 * 4003 * 3793);
     }
-    unreachable!();
-}
-
-#[aoc(day20, part2, jorendorff)]
-fn part_2(_input: &Input) -> u64 {
-    let a = analyze_machine(MACHINE_1);
-    let b = analyze_machine(MACHINE_2);
-    let c = analyze_machine(MACHINE_3);
-    let d = analyze_machine(MACHINE_4);
-    num::integer::lcm(num::integer::lcm(num::integer::lcm(a, b), c), d)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    const EXAMPLE: &str = "\
-broadcaster -> a, b, c
-%a -> b
-%b -> c
-%c -> inv
-&inv -> a
-";
-
-    const EXAMPLE_2: &str = "\
-broadcaster -> a
-%a -> inv, con
-&inv -> b
-%b -> con
-&con -> output
-";
 
     #[test]
-    fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 32000000);
-        assert_eq!(part_1(&parse_input(EXAMPLE_2).unwrap()), 11687500);
+    fn test_not_counters() {
+        let machine = parse_input("broadcaster -> a, b\n%a -> b\n%b -> con\n&con -> rx\n").unwrap();
+        assert!(split_counters(&machine, "rx").is_err());
     }
 
     #[test]
-    fn test_part_2() {
+    fn test_to_dot() {
+        let dot = module_graph_dot(COUNTERS).unwrap();
+        assert!(dot.starts_with("digraph modules {\n    subgraph cluster_0 {\n        label=\"ht\";\n"));
+        assert!(dot.contains("    \"broadcaster\" [shape=doublecircle];\n"));
+        assert!(dot.contains("    \"nt\" [shape=invhouse];\n    \"nt\" -> \"ds\";\n"));
+        assert!(dot.contains("    \"mg\" -> \"rx\";\n"));
     }
 }
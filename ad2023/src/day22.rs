@@ -1,6 +1,6 @@
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Copy)]
 struct Point(u64, u64, u64);
@@ -26,154 +26,212 @@ fn brick_name(id: usize) -> String {
     }
 }
 
-#[aoc(day22, part1, jorendorff)]
-fn part_1(input: &Input) -> usize {
-    let mut bricks = input
-        .iter()
-        .enumerate()
-        .map(|(i, &(p, q))| (i, p, q))
-        .collect::<Vec<_>>();
-    bricks.sort_by_key(|(_i, p, q)| p.2.min(q.2));
-    let mut map: HashMap<(u64, u64), (usize, u64)> = HashMap::new();
-
-    // (i, xs) means i is atop all of xs
-    let mut deps: HashMap<usize, HashSet<usize>> = HashMap::new();
-
-    for &(i, p, q) in bricks.iter() {
-        println!("Brick {} {p:?}~{q:?}", brick_name(i));
-
-        let Point(xlo, ylo, _) = p;
-        let Point(xhi, yhi, _) = q;
-        assert!(xlo <= xhi);
-        assert!(ylo <= yhi);
-        let floor: u64 = (xlo..=xhi)
-            .flat_map(|x| {
-                let my_map = &map;
-                (ylo..=yhi).map(move |y| -> u64 {
-                    my_map.get(&(x, y)).copied().unwrap_or((usize::MAX, 0)).1
-                })
-            })
-            .max()
-            .unwrap();
-
-        println!("Brick {} ends up at z={}", brick_name(i), floor + 1);
-
-        let top = floor + p.2.abs_diff(q.2) + 1;
-        for x in xlo..=xhi {
-            for y in ylo..=yhi {
-                if let Some(&(piece, ht)) = map.get(&(x, y)) {
-                    if ht == floor {
-                        println!(
-                            "Brick {} is resting on brick {}",
-                            brick_name(i),
-                            brick_name(piece)
-                        );
-                        deps.entry(i).or_default().insert(piece);
+/// A brick after it has fallen into place. `lo` is the corner with the
+/// smallest coordinates, `hi` the opposite corner.
+#[derive(Debug, Clone, Copy)]
+struct Brick {
+    lo: Point,
+    hi: Point,
+}
+
+impl Brick {
+    fn new(p: Point, q: Point) -> Self {
+        Brick {
+            lo: Point(p.0.min(q.0), p.1.min(q.1), p.2.min(q.2)),
+            hi: Point(p.0.max(q.0), p.1.max(q.1), p.2.max(q.2)),
+        }
+    }
+}
+
+/// Which horizontal axis a side view shows.
+#[derive(Debug, Clone, Copy)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+/// A pile of bricks that have all fallen as far as they can.
+struct Stack {
+    /// Indexed by brick id, the brick's position in the input.
+    bricks: Vec<Brick>,
+    /// Brick ids from the bottom up. Every brick comes after the bricks
+    /// holding it up.
+    order: Vec<usize>,
+    /// `below[i]` lists the bricks brick `i` rests on.
+    below: Vec<Vec<usize>>,
+    /// `above[i]` lists the bricks resting on brick `i`.
+    above: Vec<Vec<usize>>,
+}
+
+impl Stack {
+    fn settle(input: &[(Point, Point)]) -> Stack {
+        let mut bricks = input
+            .iter()
+            .map(|&(p, q)| Brick::new(p, q))
+            .collect::<Vec<_>>();
+        let mut order = (0..bricks.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| bricks[i].lo.2);
+
+        let mut below = vec![vec![]; bricks.len()];
+        let mut above = vec![vec![]; bricks.len()];
+        // For each column, the brick on top and the height of its top.
+        let mut map: HashMap<(u64, u64), (usize, u64)> = HashMap::new();
+        for &i in &order {
+            let Brick { lo, hi } = bricks[i];
+            let columns = (lo.0..=hi.0).flat_map(|x| (lo.1..=hi.1).map(move |y| (x, y)));
+            let floor = columns
+                .clone()
+                .map(|xy| map.get(&xy).map_or(0, |&(_, z)| z))
+                .max()
+                .unwrap();
+            let top = floor + hi.2 - lo.2 + 1;
+            for xy in columns {
+                if let Some(&(piece, z)) = map.get(&xy) {
+                    if z == floor && !below[i].contains(&piece) {
+                        below[i].push(piece);
+                        above[piece].push(i);
                     }
                 }
-                map.insert((x, y), (i, top));
+                map.insert(xy, (i, top));
             }
+            bricks[i] = Brick {
+                lo: Point(lo.0, lo.1, floor + 1),
+                hi: Point(hi.0, hi.1, top),
+            };
         }
-    }
 
-    let mut has_sole_dependees = vec![false; bricks.len()];
-    for (i, xs) in deps {
-        if xs.len() == 1 {
-            let supporter = xs.into_iter().next().unwrap();
-            has_sole_dependees[supporter] = true;
-            println!(
-                "Brick {} cannot be disintegrated; brick {} would fall",
-                brick_name(supporter),
-                brick_name(i)
-            );
+        Stack {
+            bricks,
+            order,
+            below,
+            above,
         }
     }
 
-    has_sole_dependees.into_iter().filter(|b| !*b).count()
-}
+    /// True if nothing would fall if brick `i` were removed: every brick
+    /// resting on it also rests on something else.
+    fn can_remove(&self, i: usize) -> bool {
+        self.above[i].iter().all(|&j| self.below[j].len() > 1)
+    }
 
-#[aoc(day22, part2, jorendorff)]
-fn part_2(input: &Input) -> usize {
-    let mut bricks = input
-        .iter()
-        .enumerate()
-        .map(|(i, &(p, q))| (i, p, q))
-        .collect::<Vec<_>>();
-    bricks.sort_by_key(|(_i, p, q)| p.2.min(q.2));
-    let mut map: HashMap<(u64, u64), (usize, u64)> = HashMap::new();
-
-    // (i, xs) means i is supported by the bricks in xs
-    let mut deps: HashMap<usize, HashSet<usize>> = HashMap::new();
-
-    // (i, xs) means i supports each of the bricks in xs (the inverse relation)
-    let mut supports: HashMap<usize, HashSet<usize>> = HashMap::new();
-
-    for &(i, p, q) in bricks.iter() {
-        println!("Brick {} {p:?}~{q:?}", brick_name(i));
-
-        let Point(xlo, ylo, _) = p;
-        let Point(xhi, yhi, _) = q;
-        assert!(xlo <= xhi);
-        assert!(ylo <= yhi);
-        let floor: u64 = (xlo..=xhi)
-            .flat_map(|x| {
-                let my_map = &map;
-                (ylo..=yhi).map(move |y| -> u64 {
-                    my_map.get(&(x, y)).copied().unwrap_or((usize::MAX, 0)).1
-                })
-            })
-            .max()
-            .unwrap();
-
-        println!("Brick {} ends up at z={}", brick_name(i), floor + 1);
-
-        let top = floor + p.2.abs_diff(q.2) + 1;
-        for x in xlo..=xhi {
-            for y in ylo..=yhi {
-                if let Some(&(piece, ht)) = map.get(&(x, y)) {
-                    if ht == floor {
-                        println!(
-                            "Brick {} is resting on brick {}",
-                            brick_name(i),
-                            brick_name(piece)
-                        );
-                        deps.entry(i).or_default().insert(piece);
-                        supports.entry(piece).or_default().insert(i);
-                    }
+    /// For each brick, how many other bricks would fall if it were removed.
+    ///
+    /// Brick `j` falls when brick `i` is removed exactly when every path
+    /// from the ground up to `j` passes through `i`, that is, when `i`
+    /// dominates `j`. So build the dominator tree, rooted at the ground, and
+    /// count descendants. Bricks are added bottom up, so each brick's
+    /// immediate dominator is the lowest common ancestor of the bricks it
+    /// rests on. Binary lifting makes that `O(n log n)` overall.
+    fn fall_counts(&self) -> Vec<usize> {
+        let n = self.bricks.len();
+        let ground = n;
+        let levels = (usize::BITS - n.leading_zeros()) as usize + 1;
+        // up[k][i] = the 2^k-th dominator of brick i
+        let mut up = vec![vec![ground; n + 1]; levels];
+        let mut depth = vec![0; n + 1];
+
+        let lca = |up: &[Vec<usize>], depth: &[usize], mut a: usize, mut b: usize| {
+            if depth[a] < depth[b] {
+                std::mem::swap(&mut a, &mut b);
+            }
+            let diff = depth[a] - depth[b];
+            for (k, row) in up.iter().enumerate() {
+                if diff >> k & 1 == 1 {
+                    a = row[a];
                 }
-                map.insert((x, y), (i, top));
+            }
+            if a == b {
+                return a;
+            }
+            for row in up.iter().rev() {
+                if row[a] != row[b] {
+                    a = row[a];
+                    b = row[b];
+                }
+            }
+            up[0][a]
+        };
+
+        for &i in &self.order {
+            let idom = self.below[i]
+                .iter()
+                .copied()
+                .reduce(|a, b| lca(&up, &depth, a, b))
+                .unwrap_or(ground);
+            depth[i] = depth[idom] + 1;
+            up[0][i] = idom;
+            for k in 1..levels {
+                up[k][i] = up[k - 1][up[k - 1][i]];
             }
         }
+
+        // Children come after their dominators in `order`, so one pass from
+        // the top down adds up subtree sizes.
+        let mut size = vec![1; n + 1];
+        for &i in self.order.iter().rev() {
+            size[up[0][i]] += size[i];
+        }
+        size.truncate(n);
+        size.into_iter().map(|s| s - 1).collect()
     }
 
-    bricks
-        .iter()
-        .map(|&(i, _p, _q)| how_many_would_fall(deps.clone(), supports.clone(), i))
-        .sum()
-}
+    /// Draw the stack the way the puzzle does, looking along the Y axis (to
+    /// see X and Z) or along the X axis. Where several bricks line up, the
+    /// cell is drawn as `?`.
+    fn side_view(&self, axis: Axis) -> String {
+        let (name, across): (&str, fn(Point) -> u64) = match axis {
+            Axis::X => ("x", |p| p.0),
+            Axis::Y => ("y", |p| p.1),
+        };
+        let width = self
+            .bricks
+            .iter()
+            .map(|b| across(b.hi) + 1)
+            .max()
+            .unwrap_or(0) as usize;
+        let height = self.bricks.iter().map(|b| b.hi.2).max().unwrap_or(0);
 
-fn how_many_would_fall(
-    mut deps: HashMap<usize, HashSet<usize>>,
-    mut supports: HashMap<usize, HashSet<usize>>,
-    i: usize,
-) -> usize {
-    let mut todo = VecDeque::new();
-    todo.push_back(i);
-
-    let mut fall_count = 0;
-
-    while let Some(n) = todo.pop_front() {
-        let supportees = supports.remove(&n).unwrap_or_default();
-        for k in supportees {
-            let k_supporters = deps.get_mut(&k).unwrap();
-            k_supporters.remove(&n);
-            if k_supporters.is_empty() {
-                todo.push_back(k);
-                fall_count += 1;
+        let mut out = " ".repeat(width / 2) + name + "\n";
+        out.extend((0..width).map(|h| char::from_digit(h as u32 % 10, 10).unwrap()));
+        out.push('\n');
+        for z in (1..=height).rev() {
+            for h in 0..width as u64 {
+                let mut here = self
+                    .bricks
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| (across(b.lo)..=across(b.hi)).contains(&h))
+                    .filter(|(_, b)| (b.lo.2..=b.hi.2).contains(&z))
+                    .map(|(i, _)| i);
+                out += &match (here.next(), here.next()) {
+                    (None, _) => ".".to_string(),
+                    (Some(i), None) => brick_name(i),
+                    _ => "?".to_string(),
+                };
             }
+            out += &format!(" {z}\n");
         }
+        out += &format!("{} 0\n", "-".repeat(width));
+        out
     }
-    fall_count
+}
+
+#[aoc(day22, part1, jorendorff)]
+fn part_1(input: &Input) -> usize {
+    let stack = Stack::settle(input);
+    (0..stack.bricks.len())
+        .filter(|&i| stack.can_remove(i))
+        .count()
+}
+
+/// Settle the bricks in `text` and draw the stack from the side.
+pub fn side_view(text: &str, axis: Axis) -> anyhow::Result<String> {
+    Ok(Stack::settle(&parse_input(text)?).side_view(axis))
+}
+
+#[aoc(day22, part2, jorendorff)]
+fn part_2(input: &Input) -> usize {
+    Stack::settle(input).fall_counts().into_iter().sum()
 }
 
 #[cfg(test)]
@@ -199,4 +257,94 @@ mod tests {
     fn test_part_2() {
         assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 7);
     }
+
+    #[test]
+    fn test_graph() {
+        let stack = Stack::settle(&parse_input(EXAMPLE).unwrap());
+        let names = |ids: &[usize]| ids.iter().map(|&i| brick_name(i)).collect::<String>();
+        let mut above = stack.above.iter().map(|v| names(v)).collect::<Vec<_>>();
+        let mut below = stack.below.iter().map(|v| names(v)).collect::<Vec<_>>();
+        for v in above.iter_mut().chain(&mut below) {
+            let mut chars = v.chars().collect::<Vec<_>>();
+            chars.sort();
+            *v = chars.into_iter().collect();
+        }
+        assert_eq!(above, ["BC", "DE", "DE", "F", "F", "G", ""]);
+        assert_eq!(below, ["", "A", "A", "BC", "BC", "DE", "F"]);
+        assert_eq!(stack.fall_counts(), [6, 0, 0, 0, 0, 1, 0]);
+        assert!(!stack.can_remove(5) && stack.can_remove(6));
+    }
+
+    /// Remove brick `i` and watch what happens, the slow way.
+    fn would_fall(stack: &Stack, i: usize) -> usize {
+        let mut fallen = vec![false; stack.bricks.len()];
+        fallen[i] = true;
+        for &j in &stack.order {
+            if !stack.below[j].is_empty() && stack.below[j].iter().all(|&k| fallen[k]) {
+                fallen[j] = true;
+            }
+        }
+        fallen.into_iter().filter(|&f| f).count() - 1
+    }
+
+    #[test]
+    fn test_fall_counts() {
+        // A tall, wobbly pile with plenty of bricks resting on two others.
+        let mut input = vec![];
+        let mut seed = 12345u64;
+        for z in 1..300 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let (x, y, len) = (seed >> 33 & 3, seed >> 40 & 3, seed >> 50 & 3);
+            let p = Point(x, y, z);
+            let q = match seed >> 60 & 3 {
+                0 => Point(x + len, y, z),
+                1 => Point(x, y + len, z),
+                2 => Point(x, y, z + len),
+                _ => Point(x, y, z),
+            };
+            // Endpoints in either order.
+            input.push(if z % 2 == 0 { (p, q) } else { (q, p) });
+        }
+        let stack = Stack::settle(&input);
+        let expected = (0..input.len())
+            .map(|i| would_fall(&stack, i))
+            .collect::<Vec<_>>();
+        assert!(expected.iter().any(|&n| n > 10));
+        assert_eq!(stack.fall_counts(), expected);
+        for (i, n) in expected.into_iter().enumerate() {
+            assert_eq!(stack.can_remove(i), n == 0);
+        }
+    }
+
+    #[test]
+    fn test_side_view() {
+        assert_eq!(
+            side_view(EXAMPLE, Axis::X).unwrap(),
+            " x
+012
+.G. 6
+.G. 5
+FFF 4
+D.E 3
+??? 2
+.A. 1
+--- 0
+"
+        );
+        assert_eq!(
+            side_view(EXAMPLE, Axis::Y).unwrap(),
+            " y
+012
+.G. 6
+.G. 5
+.F. 4
+??? 3
+B.C 2
+AAA 1
+--- 0
+"
+        );
+    }
 }
@@ -19,7 +19,7 @@ pub mod day16;
 pub mod day17;
 pub mod day18;
 pub mod day19;
-// pub mod day20;
+pub mod day20;
 pub mod day21;
 pub mod day22;
 pub mod day23;
@@ -1,7 +1,6 @@
 // Part 1 rank 724, part 2 rank 614.
 
-use std::collections::HashMap;
-
+use adlib::Transition;
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -16,18 +15,7 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
 
 fn blink(v: Vec<u64>) -> Vec<u64> {
     v.into_iter()
-        .flat_map(|n| {
-            if n == 0 {
-                return vec![1];
-            }
-            let s = n.to_string();
-            if s.len() % 2 == 0 {
-                let h = s.len() / 2;
-                vec![s[..h].parse().unwrap(), s[h..].parse().unwrap()]
-            } else {
-                vec![n * 2024]
-            }
-        })
+        .flat_map(|n| change(n).into_iter().map(|(k, _)| k))
         .collect()
 }
 
@@ -40,38 +28,30 @@ fn part_1(input: &Input) -> usize {
     v.len()
 }
 
-fn how_many_after(k: u64, blinks: usize, cache: &mut HashMap<(u64, usize), u64>) -> u64 {
-    if blinks == 0 {
-        1
-    } else if let Some(&v) = cache.get(&(k, blinks)) {
-        v
+fn change(n: u64) -> Vec<(u64, u64)> {
+    if n == 0 {
+        return vec![(1, 1)];
+    }
+    let s = n.to_string();
+    if s.len().is_multiple_of(2) {
+        let h = s.len() / 2;
+        vec![(s[..h].parse().unwrap(), 1), (s[h..].parse().unwrap(), 1)]
     } else {
-        let answer = if k == 0 {
-            how_many_after(1, blinks - 1, cache)
-        } else {
-            let s = k.to_string();
-            if s.len() % 2 == 0 {
-                let h = s.len() / 2;
-                how_many_after(s[..h].parse().unwrap(), blinks - 1, cache)
-                    + how_many_after(s[h..].parse().unwrap(), blinks - 1, cache)
-            } else {
-                how_many_after(k * 2024, blinks - 1, cache)
-            }
-        };
-
-        cache.insert((k, blinks), answer);
-        answer
+        vec![(n * 2024, 1)]
     }
 }
 
+// Only a few thousand different numbers ever show up, so track how many
+// stones have each one.
+fn count_after(stones: &[u64], blinks: u64) -> u64 {
+    let t = Transition::new(stones.iter().copied(), |&n| change(n));
+    let start = t.vector(stones.iter().map(|&n| (n, 1)));
+    t.advance(&start, blinks).into_iter().sum()
+}
+
 #[aoc(day11, part2, jorendorff)]
 fn part_2(input: &Input) -> u64 {
-    let mut cache = HashMap::new();
-    input
-        .iter()
-        .copied()
-        .map(|k| how_many_after(k, 75, &mut cache))
-        .sum()
+    count_after(input, 75)
 }
 
 #[cfg(test)]
@@ -89,10 +69,8 @@ mod tests {
 
     #[test]
     fn test_part_2() {
-        let mut cache = HashMap::new();
-        assert_eq!(
-            how_many_after(125, 25, &mut cache) + how_many_after(17, 25, &mut cache),
-            55312
-        );
+        let stones = parse_input(EXAMPLE).unwrap();
+        assert_eq!(count_after(&stones, 6), 22);
+        assert_eq!(count_after(&stones, 25), 55312);
     }
 }
@@ -1,196 +1,325 @@
 // Part 2 rank 316
 
-use std::collections::*;
+use std::fmt;
+use std::io::{self, Write};
 
+use adlib::{Dir, Grid, Point};
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
-type Input = (Vec<Vec<char>>, Vec<(i64, i64)>);
+type Input = (Vec<Vec<char>>, Vec<Dir>);
 
 #[aoc_generator(day15, part1, jorendorff)]
+#[aoc_generator(day15, part2, jorendorff)]
 fn parse_input(text: &str) -> anyhow::Result<Input> {
     let p = parser!(
         section(lines(any_char+))
             section(lines:lines({
-                '^' => (-1, 0),
-                '<' => (0, -1),
-                '>' => (0, 1),
-                'v' => (1, 0),
+                '^' => Dir::Up,
+                '<' => Dir::Left,
+                '>' => Dir::Right,
+                'v' => Dir::Down,
             }+) => lines.into_iter().flatten().collect())
     );
     Ok(p.parse(text)?)
 }
 
-#[aoc_generator(day15, part2, jorendorff)]
-fn parse_input_2(text: &str) -> anyhow::Result<Input> {
-    let p = parser!(
-        section(lines(
-            strs:{
-                '#' => "##",
-                'O' => "[]",
-                '.' => "..",
-                '@' => "@.",
-            }+ => strs.into_iter().flat_map(|s| s.chars()).collect::<Vec<char>>()
-        ))
-            section(lines:lines({
-                '^' => (-1, 0),
-                '<' => (0, -1),
-                '>' => (0, 1),
-                'v' => (1, 0),
-            }+) => lines.into_iter().flatten().collect())
-    );
-    Ok(p.parse(text)?)
+/// The part 2 map: everything except the robot is twice as wide.
+fn widen(map: &[Vec<char>]) -> Vec<Vec<char>> {
+    map.iter()
+        .map(|row| {
+            row.iter()
+                .flat_map(|c| match c {
+                    'O' => ['[', ']'],
+                    '@' => ['@', '.'],
+                    &c => [c, c],
+                })
+                .collect()
+        })
+        .collect()
 }
 
-#[aoc(day15, part1, jorendorff)]
-fn part_1(input: &Input) -> u64 {
-    let (mut grid, moves) = input.clone();
-
-    let mut r_bot = 0;
-    let mut c_bot = 0;
-    'search:
-    for (r, row) in grid.iter_mut().enumerate() {
-        for (c, cell) in row.iter_mut().enumerate() {
-            if *cell == '@' {
-                r_bot = r;
-                c_bot = c;
-                *cell = '.';
-                break 'search;
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Floor,
+    Wall,
+    Object(usize),
+}
+
+/// Something the robot can push: any shape, moving as a unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    /// Each square the object covers, and how to draw it.
+    pub cells: Vec<(Point, char)>,
+}
+
+impl Object {
+    /// The top left corner of the object's bounding box.
+    pub fn top_left(&self) -> Point {
+        Point {
+            row: self.cells.iter().map(|(p, _)| p.row).min().unwrap(),
+            col: self.cells.iter().map(|(p, _)| p.col).min().unwrap(),
         }
     }
-    
-    for (dr, dc) in moves {
-        let r_next = (r_bot as i64 + dr) as usize;
-        let c_next = (c_bot as i64 + dc) as usize;
-        let mut r_blank = r_next;
-        let mut c_blank = c_next;
-        while grid[r_blank][c_blank] == 'O' {
-            r_blank = (r_blank as i64 + dr) as usize;
-            c_blank = (c_blank as i64 + dc) as usize;
-        }
-        if grid[r_blank][c_blank] == '.' {
-            grid[r_blank][c_blank] = grid[r_next][c_next];
-            grid[r_next][c_next] = '.';
-            r_bot = r_next;
-            c_bot = c_next;
-        }
+}
 
-        grid[r_bot][c_bot] = '@';
-        println!("Move {:?}:", (dr, dc));
-        for row in &grid {
-            println!("{}", row.iter().copied().collect::<String>());
-        }
-        println!();
-        grid[r_bot][c_bot] = '.';
-    }
-    
-    grid.into_iter()
-        .enumerate()
-        .map(|(r, row)| -> u64{
-            row.into_iter()
-                .enumerate()
-                .map(|(c, ch)| {
-                    if ch == 'O' {
-                        100 * r as u64 + c as u64
-                    } else {
-                        0
-                    }
-                })
-                .sum()
-        })
-        .sum()
+/// The puzzle's score for one box: 100 times its distance from the top edge
+/// plus its distance from the left edge, measured to its bounding box.
+pub fn gps(object: &Object) -> u64 {
+    let p = object.top_left();
+    100 * p.row as u64 + p.col as u64
 }
 
-#[aoc(day15, part2, jorendorff)]
-fn part_2(input: &Input) -> u64 {
-    let (mut grid, moves) = input.clone();
-
-    let mut r_bot = 0;
-    let mut c_bot = 0;
-    'search:
-    for (r, row) in grid.iter_mut().enumerate() {
-        for (c, cell) in row.iter_mut().enumerate() {
-            if *cell == '@' {
-                r_bot = r;
-                c_bot = c;
-                break 'search;
-            }
-        }
+/// One call to `Warehouse::push`, remembered so it can be undone.
+#[derive(Debug)]
+pub struct Move {
+    dir: Dir,
+    /// Objects that moved. Empty if the robot was blocked or just walked.
+    objects: Vec<usize>,
+    robot_moved: bool,
+}
+
+impl Move {
+    pub fn dir(&self) -> Dir {
+        self.dir
     }
-    
-    for (dr, dc) in moves {
-        fn enqueue(
-            grid: &[Vec<char>],
-            junk: &mut HashSet<(char, usize, usize)>,
-            todo: &mut VecDeque<(usize, usize)>,
-            r: usize,
-            c: usize,
-        ) {
-            let triple = (grid[r][c], r, c);
-            if !junk.contains(&triple) {
-                junk.insert(triple);
-                todo.push_back((r, c));
-            }
-        }
 
-        let mut junk = HashSet::new();
-        let mut todo = VecDeque::new();
-        enqueue(&grid, &mut junk, &mut todo, r_bot, c_bot);
+    /// Indexes of the objects that moved, in the order `Warehouse::objects`
+    /// lists them.
+    pub fn objects(&self) -> &[usize] {
+        &self.objects
+    }
 
-        let mut blocked = false;
-        while let Some((r, c)) = todo.pop_front() {
-            match grid[r][c] {
-                '[' => enqueue(&grid, &mut junk, &mut todo, r, c + 1),
-                ']' => enqueue(&grid, &mut junk, &mut todo, r, c - 1),
-                _ => {}
-            }
-            let r1 = (r as i64 + dr) as usize;
-            let c1 = (c as i64 + dc) as usize;
-            match grid[r1][c1] {
-                '#' => {
-                    blocked = true;
-                    break;
+    /// False if something in the way was stuck, so nothing moved.
+    pub fn robot_moved(&self) -> bool {
+        self.robot_moved
+    }
+}
+
+/// A map with the robot and everything it can push.
+#[derive(Debug, Clone)]
+pub struct Warehouse {
+    grid: Grid<Tile>,
+    objects: Vec<Object>,
+    robot: Point,
+}
+
+impl Warehouse {
+    /// Read a map. `#` is wall, `.` floor, and `@` the robot. Each `O` is a
+    /// box, `[]` is a two-wide box, and any other capital letter is part of
+    /// an object made of all the squares connected to it with that letter.
+    /// Anything off the edge of the map is treated as wall.
+    pub fn new(map: &[Vec<char>]) -> anyhow::Result<Warehouse> {
+        anyhow::ensure!(!map.is_empty(), "empty map");
+        let width = map[0].len();
+        anyhow::ensure!(
+            map.iter().all(|row| row.len() == width),
+            "map rows must all be the same length"
+        );
+        let mut grid = Grid {
+            data: vec![vec![Tile::Floor; width]; map.len()],
+        };
+        let mut objects = vec![];
+        let mut robot = None;
+        let at = |p: Point| map[p.row][p.col];
+
+        for (r, row) in map.iter().enumerate() {
+            for (c, &ch) in row.iter().enumerate() {
+                let p = Point { row: r, col: c };
+                if grid[p] != Tile::Floor {
+                    continue;
                 }
-                '.' => {}
-                _ => enqueue(&grid, &mut junk, &mut todo, r1, c1),
+                let cells = match ch {
+                    '.' => continue,
+                    '#' => {
+                        grid[p] = Tile::Wall;
+                        continue;
+                    }
+                    '@' => {
+                        anyhow::ensure!(robot.is_none(), "more than one robot");
+                        robot = Some(p);
+                        continue;
+                    }
+                    'O' => vec![(p, 'O')],
+                    '[' => {
+                        anyhow::ensure!(
+                            row.get(c + 1) == Some(&']'),
+                            "row {r}, column {c}: `[` without `]`"
+                        );
+                        vec![(p, '['), (p + Dir::Right, ']')]
+                    }
+                    'A'..='Z' => {
+                        let mut cells = vec![(p, ch)];
+                        let mut i = 0;
+                        while i < cells.len() {
+                            let q = cells[i].0;
+                            for d in adlib::DIRS {
+                                let n = q + d;
+                                if grid.has(n) && at(n) == ch && !cells.contains(&(n, ch)) {
+                                    cells.push((n, ch));
+                                }
+                            }
+                            i += 1;
+                        }
+                        cells
+                    }
+                    _ => anyhow::bail!("row {r}, column {c}: unexpected character {ch:?}"),
+                };
+                for &(q, _) in &cells {
+                    grid[q] = Tile::Object(objects.len());
+                }
+                objects.push(Object { cells });
             }
         }
 
-        if !blocked {
-            for (_, r, c) in junk.iter().copied() {
-                grid[r][c] = '.';
+        let robot = robot.ok_or_else(|| anyhow::anyhow!("no robot"))?;
+        Ok(Warehouse {
+            grid,
+            objects,
+            robot,
+        })
+    }
+
+    fn tile(&self, p: Point) -> Tile {
+        self.grid.get(p).copied().unwrap_or(Tile::Wall)
+    }
+
+    /// Add `id` and everything it would shove to `pushed`. Returns false if
+    /// any of it would hit a wall.
+    fn collect(&self, id: usize, dir: Dir, pushed: &mut Vec<usize>) -> bool {
+        if pushed.contains(&id) {
+            return true;
+        }
+        pushed.push(id);
+        self.objects[id]
+            .cells
+            .iter()
+            .all(|&(p, _)| match self.tile(p + dir) {
+                Tile::Floor => true,
+                Tile::Wall => false,
+                Tile::Object(other) => self.collect(other, dir, pushed),
+            })
+    }
+
+    fn shift(&mut self, ids: &[usize], dir: Dir) {
+        for &id in ids {
+            for &(p, _) in &self.objects[id].cells {
+                self.grid[p] = Tile::Floor;
             }
-            for (ch, r, c) in junk.iter().copied() {
-                grid[(r as i64 + dr) as usize][(c as i64 + dc) as usize] = ch;
+        }
+        for &id in ids {
+            for (p, _) in &mut self.objects[id].cells {
+                *p += dir;
+                self.grid[*p] = Tile::Object(id);
             }
-            r_bot = (r_bot as i64 + dr) as usize;
-            c_bot = (c_bot as i64 + dc) as usize;
         }
+    }
 
+    /// Try to move the robot one step, pushing whatever is in the way. If
+    /// anything it would push is stuck, nothing moves.
+    pub fn push(&mut self, dir: Dir) -> Move {
+        let mut pushed = vec![];
+        let ok = match self.tile(self.robot + dir) {
+            Tile::Floor => true,
+            Tile::Wall => false,
+            Tile::Object(id) => self.collect(id, dir, &mut pushed),
+        };
+        if !ok {
+            pushed.clear();
+        }
+        self.shift(&pushed, dir);
+        if ok {
+            self.robot += dir;
+        }
+        Move {
+            dir,
+            objects: pushed,
+            robot_moved: ok,
+        }
+    }
 
-        println!("Move {:?}:", (dr, dc));
-        for row in &grid {
-            println!("{}", row.iter().copied().collect::<String>());
+    /// Put things back the way they were before `m`, which must be the
+    /// last move not yet undone.
+    pub fn undo(&mut self, m: &Move) {
+        let back = m.dir.reverse();
+        self.shift(&m.objects, back);
+        if m.robot_moved {
+            self.robot += back;
         }
-        println!();
-    }
-    
-    grid.into_iter()
-        .enumerate()
-        .map(|(r, row)| -> u64{
-            row.into_iter()
-                .enumerate()
-                .map(|(c, ch)| {
-                    if ch == '[' {
-                        100 * r as u64 + c as u64
-                    } else {
-                        0
-                    }
-                })
-                .sum()
-        })
-        .sum()
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// Add up `f` over every object. The puzzle's scorer is `gps`.
+    pub fn score(&self, f: impl Fn(&Object) -> u64) -> u64 {
+        self.objects.iter().map(f).sum()
+    }
+
+    /// Make all the moves, writing the map after each one the way the puzzle
+    /// does.
+    pub fn replay(&mut self, moves: &[Dir], out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Initial state:\n{self}")?;
+        for &dir in moves {
+            let glyph = match dir {
+                Dir::Up => '^',
+                Dir::Left => '<',
+                Dir::Right => '>',
+                Dir::Down => 'v',
+            };
+            self.push(dir);
+            writeln!(out, "Move {glyph}:\n{self}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Warehouse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = self
+            .grid
+            .data
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|t| match t {
+                        Tile::Floor => '.',
+                        Tile::Wall => '#',
+                        Tile::Object(_) => '?',
+                    })
+                    .collect::<Vec<char>>()
+            })
+            .collect::<Vec<_>>();
+        for obj in &self.objects {
+            for &(p, ch) in &obj.cells {
+                rows[p.row][p.col] = ch;
+            }
+        }
+        rows[self.robot.row][self.robot.col] = '@';
+        for row in rows {
+            writeln!(f, "{}", row.into_iter().collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+fn run(map: &[Vec<char>], moves: &[Dir]) -> anyhow::Result<u64> {
+    let mut warehouse = Warehouse::new(map)?;
+    for &dir in moves {
+        warehouse.push(dir);
+    }
+    Ok(warehouse.score(gps))
+}
+
+#[aoc(day15, part1, jorendorff)]
+fn part_1((map, moves): &Input) -> anyhow::Result<u64> {
+    run(map, moves)
+}
+
+#[aoc(day15, part2, jorendorff)]
+fn part_2((map, moves): &Input) -> anyhow::Result<u64> {
+    run(&widen(map), moves)
 }
 
 #[cfg(test)]
@@ -209,7 +338,7 @@ mod tests {
 
 <^^>>>vv<v>>v<<
 ";
-    
+
     const EXAMPLE: &str = "\
 ##########
 #..O..O.O#
@@ -234,16 +363,163 @@ vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v
 v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^
 ";
 
+    fn map(text: &str) -> Vec<Vec<char>> {
+        text.lines().map(|line| line.chars().collect()).collect()
+    }
 
-    
     #[test]
     fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE_SMALL).unwrap()), 2028);
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 10092);
+        assert_eq!(part_1(&parse_input(EXAMPLE_SMALL).unwrap()).unwrap(), 2028);
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), 10092);
     }
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input_2(EXAMPLE).unwrap()), 9021);
+        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()).unwrap(), 9021);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut w = Warehouse::new(&map("\
+########
+#......#
+#.AA...#
+#.A.[].#
+#..O...#
+#..@...#
+########"))
+        .unwrap();
+        assert_eq!(w.objects.len(), 3);
+
+        // The box moves alone, then pushes the L, which is free to move.
+        assert_eq!(w.push(Dir::Up).objects, [2]);
+        assert_eq!(w.push(Dir::Up).objects, [2, 0]);
+        assert_eq!(
+            w.to_string(),
+            "\
+########
+#.AA...#
+#.AO...#
+#..@[].#
+#......#
+#......#
+########
+"
+        );
+
+        // Now the L is against the wall, so nothing moves.
+        let m = w.push(Dir::Up);
+        assert!(m.objects.is_empty() && !m.robot_moved);
+    }
+
+    #[test]
+    fn test_all_or_nothing() {
+        // The wide box has room on the left, but the box it would push on
+        // the right is stuck, so nothing moves.
+        let text = "\
+######
+#.#..#
+#.O..#
+#[]..#
+#.@..#
+######
+";
+        let mut w = Warehouse::new(&map(text)).unwrap();
+        let m = w.push(Dir::Up);
+        assert!(m.objects.is_empty() && !m.robot_moved);
+        assert_eq!(w.to_string(), text);
+    }
+
+    #[test]
+    fn test_undo() {
+        let (map, moves) = parse_input(EXAMPLE).unwrap();
+        let mut w = Warehouse::new(&widen(&map)).unwrap();
+        let start = w.to_string();
+        let history = moves.iter().map(|&d| w.push(d)).collect::<Vec<_>>();
+        assert_eq!(w.score(gps), 9021);
+        for m in history.iter().rev() {
+            w.undo(m);
+        }
+        assert_eq!(w.to_string(), start);
+    }
+
+    #[test]
+    fn test_score() {
+        let w = Warehouse::new(&map("#####\n#.O@#\n#[].#\n#####")).unwrap();
+        assert_eq!(w.score(gps), 102 + 201);
+        // Count boxes by area instead.
+        assert_eq!(w.score(|obj| obj.cells.len() as u64), 3);
+    }
+
+    #[test]
+    fn test_replay() {
+        let (map, moves) = parse_input(EXAMPLE_SMALL).unwrap();
+        let mut w = Warehouse::new(&map).unwrap();
+        let mut out = vec![];
+        w.replay(&moves[..2], &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+Initial state:
+########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+Move <:
+########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+Move ^:
+########
+#.@O.O.#
+##..O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+"
+        );
+    }
+
+    #[test]
+    fn test_bad_maps() {
+        let err = |text: &str| Warehouse::new(&map(text)).unwrap_err().to_string();
+        assert_eq!(err("#.#"), "no robot");
+        assert_eq!(err("@.@"), "more than one robot");
+        assert_eq!(err("@.[."), "row 0, column 2: `[` without `]`");
+        assert_eq!(err("@.]."), "row 0, column 2: unexpected character ']'");
+    }
+
+    #[test]
+    fn test_no_border() {
+        // The edge of the map stops things like a wall.
+        let mut w = Warehouse::new(&[vec!['@', '.']]).unwrap();
+        assert!(!w.push(Dir::Left).robot_moved());
+        assert!(!w.push(Dir::Up).robot_moved());
+        assert!(w.push(Dir::Right).robot_moved());
+        assert!(!w.push(Dir::Right).robot_moved());
+
+        let mut w = Warehouse::new(&map("@O.\n.[]")).unwrap();
+        assert_eq!(w.push(Dir::Right).objects(), [0]);
+        let m = w.push(Dir::Right);
+        assert!(m.objects().is_empty() && !m.robot_moved());
+        let m = w.push(Dir::Down);
+        assert!(m.objects().is_empty() && !m.robot_moved());
+        assert_eq!(w.to_string(), ".@O\n.[]\n");
+        assert_eq!(w.objects()[0].top_left(), Point { row: 0, col: 2 });
+        assert_eq!(w.score(gps), 2 + 101);
     }
 }
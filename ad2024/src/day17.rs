@@ -1,5 +1,7 @@
 // Part 1 rank 623, part 2 rank 61
 
+use std::fmt::Write;
+
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -19,67 +21,362 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
-fn combo(op: u8, (a, b, c): (i64, i64, i64)) -> i64 {
+/// Give up on programs that run longer than this.
+const MAX_STEPS: usize = 1 << 16;
+
+const MNEMONICS: [&str; 8] = ["adv", "bxl", "bst", "jnz", "bxc", "out", "bdv", "cdv"];
+
+fn combo_name(op: u8) -> String {
     match op {
-        0..=3 => op.into(),
-        4 => a,
-        5 => b,
-        6 => c,
-        _ => panic!("reserved"),
+        0..=3 => op.to_string(),
+        4 => "A".to_string(),
+        5 => "B".to_string(),
+        6 => "C".to_string(),
+        _ => "?".to_string(),
     }
 }
 
-fn run((mut a, mut b, mut c): (i64, i64, i64), program: &[u8]) -> Vec<u8> {
-    let mut output = vec![];
-    let mut ip = 0;
-    while let Some(&opcode) = program.get(ip) {
-        ip += 1;
-        match opcode {
-            0 => { a >>= combo(program[ip], (a, b, c)); }
-            1 => { b ^= program[ip] as i64; }
-            2 => { b = combo(program[ip], (a, b, c)) % 8; }
-            3 => if a != 0 { ip = program[ip] as usize; continue; }
-            4 => { b ^= c; }
-            5 => { output.push(combo(program[ip], (a, b, c)) as u8 % 8); }
-            6 => { b = a >> combo(program[ip], (a, b, c)); }
-            7 => { c = a >> combo(program[ip], (a, b, c)); }
-            _ => panic!("invalid opcode"),
+/// One instruction as assembly plus a line of pseudocode, like
+/// `bst A     B = A % 8`.
+fn instruction_text(opcode: u8, operand: u8) -> String {
+    let x = combo_name(operand);
+    let (arg, effect) = match opcode {
+        0 => (x.clone(), format!("A = A >> {x}")),
+        1 => (operand.to_string(), format!("B = B ^ {operand}")),
+        2 => (x.clone(), format!("B = {x} % 8")),
+        3 => (operand.to_string(), format!("if A != 0 goto {operand}")),
+        4 => (String::new(), "B = B ^ C".to_string()),
+        5 => (x.clone(), format!("out {x} % 8")),
+        6 => (x.clone(), format!("B = A >> {x}")),
+        7 => (x.clone(), format!("C = A >> {x}")),
+        _ => return format!("??? {opcode}"),
+    };
+    format!("{} {arg:<3}   {effect}", MNEMONICS[opcode as usize])
+}
+
+/// Render the program one instruction per line, reading it in pairs from
+/// address 0.
+pub fn disassemble(program: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, pair) in program.chunks(2).enumerate() {
+        let text = match *pair {
+            [opcode, operand] => instruction_text(opcode, operand),
+            _ => format!("??? {} (no operand)", pair[0]),
+        };
+        writeln!(out, "{:3}: {text}", 2 * i).unwrap();
+    }
+    out
+}
+
+/// Register contents the machine can compute with: plain numbers, or
+/// partially known numbers when working backwards.
+trait Word: Copy {
+    fn literal(n: u8) -> Self;
+    fn shr(self, amount: Self) -> Self;
+    fn xor(self, other: Self) -> Self;
+    fn low3(self) -> Self;
+    /// `None` if it can't be told.
+    fn is_nonzero(self) -> Option<bool>;
+}
+
+impl Word for u64 {
+    fn literal(n: u8) -> Self {
+        n.into()
+    }
+
+    fn shr(self, amount: Self) -> Self {
+        if amount >= 64 {
+            0
+        } else {
+            self >> amount
         }
-        ip += 1;
     }
-    output
+
+    fn xor(self, other: Self) -> Self {
+        self ^ other
+    }
+
+    fn low3(self) -> Self {
+        self % 8
+    }
+
+    fn is_nonzero(self) -> Option<bool> {
+        Some(self != 0)
+    }
 }
 
-#[aoc(day17, part1, jorendorff)]
-fn part_1(input: &Input) -> String {
-    let ((a, b, c), program) = input.clone();
-    let out = run((a, b, c), &program);
-    let out = out.into_iter().map(|v| v.to_string()).collect::<Vec<String>>();
-    out.join(",")
+/// A 64-bit number where only some bits are known. Unknown bits of `value`
+/// are always 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bits {
+    known: u64,
+    value: u64,
+}
+
+impl Bits {
+    fn exact(value: u64) -> Self {
+        Bits {
+            known: u64::MAX,
+            value,
+        }
+    }
+
+    /// Everything both `self` and `other` agree on.
+    fn join(self, other: Bits) -> Bits {
+        let known = self.known & other.known & !(self.value ^ other.value);
+        Bits {
+            known,
+            value: self.value & known,
+        }
+    }
+
+    fn could_be(self, n: u64) -> bool {
+        (n ^ self.value) & self.known == 0
+    }
 }
 
-fn search(a: i64, b: i64, c: i64, program: &[u8], len: usize) -> Option<i64> {
-    if len == program.len() + 1 {
-        return Some(a);
+impl Word for Bits {
+    fn literal(n: u8) -> Self {
+        Bits::exact(n.into())
+    }
+
+    fn shr(self, amount: Self) -> Self {
+        if amount.known == u64::MAX {
+            return match amount.value {
+                s @ 0..=63 => Bits {
+                    known: (self.known >> s) | !(u64::MAX >> s),
+                    value: self.value >> s,
+                },
+                _ => Bits::exact(0),
+            };
+        }
+        // Try every shift the amount could be.
+        let mut result = (0..64)
+            .filter(|&s| amount.could_be(s))
+            .map(|s| self.shr(Bits::exact(s)))
+            .reduce(Bits::join);
+        if amount.value >> 6 != 0 || !amount.known >> 6 != 0 {
+            result = Some(result.map_or(Bits::exact(0), |r| r.join(Bits::exact(0))));
+        }
+        result.expect("some shift is possible")
     }
 
-    let mut answers = vec![];
-    for word in 0..8 {
-        if run(((a << 3) | word, b, c), program) == program[program.len() - len..] {
-            if let Some(a) = search((a << 3) | word, b, c, program, len + 1) {
-                answers.push(a);
+    fn xor(self, other: Self) -> Self {
+        let known = self.known & other.known;
+        Bits {
+            known,
+            value: (self.value ^ other.value) & known,
+        }
+    }
+
+    fn low3(self) -> Self {
+        Bits {
+            known: self.known | !7,
+            value: self.value & 7,
+        }
+    }
+
+    fn is_nonzero(self) -> Option<bool> {
+        if self.value != 0 {
+            Some(true)
+        } else if self.known == u64::MAX {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+enum Step<W> {
+    Ran,
+    Output(W),
+    Halted,
+    /// A jump depends on bits we don't know.
+    Unknown,
+    /// Invalid opcode or reserved combo operand.
+    Fault,
+}
+
+struct Machine<W> {
+    /// Registers A, B, C.
+    regs: [W; 3],
+    ip: usize,
+}
+
+impl<W: Word> Machine<W> {
+    fn step(&mut self, program: &[u8]) -> Step<W> {
+        let (Some(&opcode), Some(&operand)) = (program.get(self.ip), program.get(self.ip + 1))
+        else {
+            return Step::Halted;
+        };
+        let [a, b, c] = self.regs;
+        let combo = match operand {
+            0..=3 => Some(W::literal(operand)),
+            4..=6 => Some(self.regs[operand as usize - 4]),
+            _ => None,
+        };
+        let lit = W::literal(operand);
+        let mut step = Step::Ran;
+        match (opcode, combo) {
+            (1, _) => self.regs[1] = b.xor(lit),
+            (3, _) => match a.is_nonzero() {
+                Some(true) => {
+                    self.ip = operand as usize;
+                    return Step::Ran;
+                }
+                Some(false) => {}
+                None => return Step::Unknown,
+            },
+            (4, _) => self.regs[1] = b.xor(c),
+            (0, Some(x)) => self.regs[0] = a.shr(x),
+            (2, Some(x)) => self.regs[1] = x.low3(),
+            (5, Some(x)) => step = Step::Output(x.low3()),
+            (6, Some(x)) => self.regs[1] = a.shr(x),
+            (7, Some(x)) => self.regs[2] = a.shr(x),
+            _ => return Step::Fault,
+        }
+        self.ip += 2;
+        step
+    }
+}
+
+fn run([a, b, c]: [u64; 3], program: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut machine = Machine {
+        regs: [a, b, c],
+        ip: 0,
+    };
+    let mut output = vec![];
+    for _ in 0..MAX_STEPS {
+        match machine.step(program) {
+            Step::Ran => {}
+            Step::Output(v) => output.push(v as u8),
+            Step::Halted => return Ok(output),
+            Step::Unknown | Step::Fault => {
+                let ip = machine.ip;
+                anyhow::bail!(
+                    "program crashed at {ip}: {}",
+                    instruction_text(program[ip], program[ip + 1])
+                );
             }
         }
     }
-    answers.into_iter().min()
+    anyhow::bail!("program ran for more than {MAX_STEPS} steps");
+}
+
+/// Run the program, listing each instruction executed and the registers
+/// after it.
+pub fn trace([a, b, c]: [u64; 3], program: &[u8]) -> String {
+    let mut machine = Machine {
+        regs: [a, b, c],
+        ip: 0,
+    };
+    let mut out = String::new();
+    for _ in 0..MAX_STEPS {
+        let ip = machine.ip;
+        let step = machine.step(program);
+        if let Step::Halted = step {
+            writeln!(out, "{ip:3}: halt").unwrap();
+            break;
+        }
+        let text = instruction_text(program[ip], program[ip + 1]);
+        let [a, b, c] = machine.regs;
+        write!(out, "{ip:3}: {text:<28}A={a} B={b} C={c}").unwrap();
+        match step {
+            Step::Output(v) => writeln!(out, " -> {v}").unwrap(),
+            Step::Fault => {
+                writeln!(out, " fault").unwrap();
+                break;
+            }
+            _ => writeln!(out).unwrap(),
+        }
+    }
+    out
+}
+
+/// Run the program with partly known A. `Some(false)` means no A with
+/// those bits can output `target`; `Some(true)` means it definitely does.
+fn check(a: Bits, [b, c]: [u64; 2], program: &[u8], target: &[u8]) -> Option<bool> {
+    let mut machine = Machine {
+        regs: [a, Bits::exact(b), Bits::exact(c)],
+        ip: 0,
+    };
+    let mut count = 0;
+    for _ in 0..MAX_STEPS {
+        match machine.step(program) {
+            Step::Ran => {}
+            Step::Output(v) => {
+                match target.get(count) {
+                    Some(&t) if v.could_be(t.into()) => {}
+                    _ => return Some(false),
+                }
+                count += 1;
+            }
+            Step::Halted => return Some(count == target.len()),
+            Step::Unknown => return None,
+            Step::Fault => return Some(false),
+        }
+    }
+    None
+}
+
+/// Fix the bits of A below `bit`, high to low, zeros first, so the first
+/// answer found is the smallest. `check` prunes each partial assignment.
+fn extend(a: Bits, bit: u32, regs: [u64; 2], program: &[u8], target: &[u8]) -> Option<u64> {
+    match check(a, regs, program, target) {
+        Some(false) => return None,
+        Some(true) if a.known == u64::MAX => return Some(a.value),
+        _ if bit == 0 => return None,
+        _ => {}
+    }
+    let bit = bit - 1;
+    (0..2).find_map(|v| {
+        let a = Bits {
+            known: a.known | (1 << bit),
+            value: a.value | (v << bit),
+        };
+        extend(a, bit, regs, program, target)
+    })
+}
+
+/// Find the smallest A that makes the program output exactly `target`.
+///
+/// This treats A as a vector of unknown bits and runs the program on what's
+/// known, so it works for any program, not just ones that shift A by 3 each
+/// time around a single loop. It's fast when outputs depend on few bits.
+fn solve([b, c]: [u64; 2], program: &[u8], target: &[u8]) -> Option<u64> {
+    if check(Bits::exact(0), [b, c], program, target) == Some(true) {
+        return Some(0);
+    }
+    (1..=64).find_map(|width| {
+        // The top bit of a `width`-bit number is 1 and everything above is 0.
+        let top = 1 << (width - 1);
+        let a = Bits {
+            known: !(top - 1),
+            value: top,
+        };
+        extend(a, width - 1, [b, c], program, target)
+    })
+}
+
+#[aoc(day17, part1, jorendorff)]
+fn part_1(input: &Input) -> anyhow::Result<String> {
+    let ((a, b, c), program) = input.clone();
+    let out = run([a as u64, b as u64, c as u64], &program)?;
+    let out = out.into_iter().map(|v| v.to_string()).collect::<Vec<String>>();
+    Ok(out.join(","))
 }
 
 #[aoc(day17, part2, jorendorff)]
-fn part_2(input: &Input) -> i64 {
+fn part_2(input: &Input) -> anyhow::Result<i64> {
     let ((_a, b, c), program) = input.clone();
-    let a = search(0, b, c, &program, 1).unwrap();
-    assert_eq!(run((a, b, c), &program), program);
-    a
+    let (b, c) = (b as u64, c as u64);
+    let a = solve([b, c], &program, &program)
+        .ok_or_else(|| anyhow::anyhow!("no value of A makes the program output itself"))?;
+    anyhow::ensure!(
+        run([a, b, c], &program)? == program,
+        "solver found a wrong A: {a}"
+    );
+    Ok(a as i64)
 }
 
 #[cfg(test)]
@@ -96,7 +393,10 @@ Program: 0,1,5,4,3,0
 
     #[test]
     fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), "4,6,3,5,6,3,5,2,1,0");
+        assert_eq!(
+            part_1(&parse_input(EXAMPLE).unwrap()).unwrap(),
+            "4,6,3,5,6,3,5,2,1,0"
+        );
     }
 
     const EXAMPLE2: &str = "\
@@ -109,6 +409,67 @@ Program: 0,3,5,4,3,0
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input(EXAMPLE2).unwrap()), 117440);
+        assert_eq!(part_2(&parse_input(EXAMPLE2).unwrap()).unwrap(), 117440);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(
+            disassemble(&[2, 4, 1, 5, 7, 5, 4, 3, 5, 6, 3, 0, 9]),
+            "  0: bst A     B = A % 8
+  2: bxl 5     B = B ^ 5
+  4: cdv B     C = A >> B
+  6: bxc       B = B ^ C
+  8: out C     out C % 8
+ 10: jnz 0     if A != 0 goto 0
+ 12: ??? 9 (no operand)
+"
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let text = trace([729, 0, 0], &[0, 1, 5, 4, 3, 0]);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 31);
+        assert_eq!(lines[0], "  0: adv 1     A = A >> 1        A=364 B=0 C=0");
+        assert_eq!(
+            lines[1],
+            "  2: out A     out A % 8         A=364 B=0 C=0 -> 4"
+        );
+        assert_eq!(lines[29], "  4: jnz 0     if A != 0 goto 0  A=0 B=0 C=0");
+        assert_eq!(lines[30], "  6: halt");
+    }
+
+    #[test]
+    fn test_solve() {
+        // Shifts A by 2, not 3, and each output depends on up to 10 bits.
+        let program = [2, 4, 1, 3, 7, 5, 0, 2, 4, 0, 5, 5, 3, 0];
+        for start in [27, 1000, 40000] {
+            let target = run([start, 0, 0], &program).unwrap();
+            let a = solve([0, 0], &program, &target).unwrap();
+            assert_eq!(run([a, 0, 0], &program).unwrap(), target);
+            assert!((0..a).all(|smaller| run([smaller, 0, 0], &program).unwrap() != target));
+        }
+        let target = run([987654321, 0, 0], &program).unwrap();
+        let a = solve([0, 0], &program, &target).unwrap();
+        assert_eq!(run([a, 0, 0], &program).unwrap(), target);
+
+        // Outputs that never happen.
+        assert_eq!(solve([0, 0], &program, &[7; 8]), None);
+        assert_eq!(solve([0, 0], &[5, 1], &[2]), None);
+        assert_eq!(solve([0, 0], &[0, 3, 5, 4, 3, 0], &[]), None);
+    }
+
+    #[test]
+    fn test_run_errors() {
+        assert_eq!(
+            run([0, 0, 0], &[2, 7]).unwrap_err().to_string(),
+            "program crashed at 0: bst ?     B = ? % 8"
+        );
+        assert_eq!(
+            run([1, 0, 0], &[3, 0]).unwrap_err().to_string(),
+            format!("program ran for more than {MAX_STEPS} steps")
+        );
     }
 }
@@ -2,6 +2,7 @@
 
 use std::collections::*;
 
+use adlib::{AhoCorasick, Trie};
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -9,6 +10,8 @@ type Input = (Vec<Vec<usize>>, Vec<Vec<usize>>);
 
 #[aoc_generator(day19, part1, jorendorff)]
 #[aoc_generator(day19, part2, jorendorff)]
+#[aoc_generator(day19, part1, hashset)]
+#[aoc_generator(day19, part2, hashset)]
 fn parse_input(text: &str) -> anyhow::Result<Input> {
     let p = parser!(
         section(line(repeat_sep(char_of("wubrg")+, ", ")))
@@ -17,8 +20,37 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
+fn towels(input: &Input) -> AhoCorasick {
+    let mut trie = Trie::new(5);
+    for towel in &input.0 {
+        trie.insert(towel);
+    }
+    trie.automaton()
+}
+
 #[aoc(day19, part1, jorendorff)]
 fn part_1(input: &Input) -> usize {
+    let towels = towels(input);
+    input
+        .1
+        .iter()
+        .filter(|pattern| towels.segment(pattern).is_possible())
+        .count()
+}
+
+#[aoc(day19, part2, jorendorff)]
+fn part_2(input: &Input) -> u64 {
+    let towels = towels(input);
+    input
+        .1
+        .iter()
+        .map(|pattern| towels.segment(pattern).count())
+        .sum()
+}
+
+/// The original hash-set versions, kept to compare with `cargo aoc bench`.
+#[aoc(day19, part1, hashset)]
+fn part_1_hashset(input: &Input) -> usize {
     let mut towels: Vec<HashSet<Vec<usize>>> = vec![];
     for towel in &input.0 {
         while towels.len() < towel.len() + 1 {
@@ -46,8 +78,8 @@ fn part_1(input: &Input) -> usize {
     count
 }
 
-#[aoc(day19, part2, jorendorff)]
-fn part_2(input: &Input) -> usize {
+#[aoc(day19, part2, hashset)]
+fn part_2_hashset(input: &Input) -> usize {
     let mut towels: Vec<HashSet<Vec<usize>>> = vec![];
     for towel in &input.0 {
         while towels.len() < towel.len() + 1 {
@@ -67,7 +99,7 @@ fn part_2(input: &Input) -> usize {
                 }
             }
         }
-        count += reachable[pattern.len()] ;
+        count += reachable[pattern.len()];
     }
     count
 }
@@ -98,4 +130,26 @@ bbrgwb
     fn test_part_2() {
         assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 16);
     }
+
+    #[test]
+    fn test_hashset() {
+        let input = parse_input(EXAMPLE).unwrap();
+        assert_eq!(part_1_hashset(&input), 6);
+        assert_eq!(part_2_hashset(&input), 16);
+    }
+
+    #[test]
+    fn test_fewest() {
+        let input = parse_input(EXAMPLE).unwrap();
+        let towels = towels(&input);
+        let fewest: Vec<usize> = input
+            .1
+            .iter()
+            .map(|pattern| towels.segment(pattern).fewest().map_or(0, |ids| ids.len()))
+            .collect();
+        assert_eq!(fewest, vec![3, 4, 2, 4, 0, 4, 3, 0]);
+
+        let s = towels.segment(&input.1[3]);
+        assert_eq!(s.all(10).len(), 6);
+    }
 }
@@ -1,4 +1,6 @@
-use pathfinding::directed::dijkstra;
+use std::collections::HashMap;
+
+use pathfinding::directed::dijkstra::{build_path, dijkstra_all};
 
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
@@ -12,173 +14,265 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     Ok(p.parse(text)?)
 }
 
-enum Cmd {
-    Up,
-    Down,
-    Left,
-    Right,
-    Activate,
-}
+const NUMERIC: &str = "\
+789
+456
+123
+ 0A";
+
+const DIRECTIONAL: &str = " ^A\n<v>";
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct State {
-    goal: (i8, i8),
-    robot1: (i8, i8),
-    robot2: (i8, i8),
-    robot3: (i8, i8),
-    success: bool,
+/// A keypad, drawn as rows of keys with spaces for gaps. Every keypad has an
+/// `A` key, where its robot's arm starts.
+#[derive(Debug, Clone)]
+struct Keypad {
+    rows: Vec<Vec<char>>,
+    keys: HashMap<char, (usize, usize)>,
 }
 
-impl State {
-    fn new(robot1: (i8, i8), goal: (i8, i8)) -> State {
-        State {
-            goal,
-            robot1,
-            robot2: (0, 2),
-            robot3: (0, 2),
-            success: false,
+impl Keypad {
+    fn parse(layout: &str) -> anyhow::Result<Keypad> {
+        let rows: Vec<Vec<char>> = layout.lines().map(|line| line.chars().collect()).collect();
+        let mut keys = HashMap::new();
+        for (r, row) in rows.iter().enumerate() {
+            for (c, &key) in row.iter().enumerate() {
+                if key != ' ' && keys.insert(key, (r, c)).is_some() {
+                    anyhow::bail!("key {key:?} appears twice");
+                }
+            }
         }
+        anyhow::ensure!(keys.contains_key(&'A'), "keypad has no A key");
+        Ok(Keypad { rows, keys })
+    }
+
+    fn is_directional(&self) -> bool {
+        "^v<>A".chars().all(|key| self.keys.contains_key(&key))
     }
 
-    fn punch(&mut self, cmd: Cmd) {
-        match cmd {
-            Cmd::Up => self.robot3_move(-1, 0),
-            Cmd::Down => self.robot3_move(1, 0),
-            Cmd::Left => self.robot3_move(0, -1),
-            Cmd::Right => self.robot3_move(0, 1),
-            Cmd::Activate => self.robot3_punch(),
+    /// The key next to `key` in direction `dir`, if there is one.
+    fn neighbor(&self, key: char, dir: char) -> Option<char> {
+        let (r, c) = self.keys[&key];
+        let (r, c) = match dir {
+            '^' => (r.checked_sub(1)?, c),
+            'v' => (r + 1, c),
+            '<' => (r, c.checked_sub(1)?),
+            '>' => (r, c + 1),
+            _ => panic!("not a direction: {dir:?}"),
+        };
+        match self.rows.get(r)?.get(c) {
+            Some(&' ') | None => None,
+            Some(&key) => Some(key),
         }
     }
+}
+
+/// For each `(from, to)` key pair on one keypad, a total cost and the keys to
+/// press one keypad down.
+type Table = HashMap<(char, char), (u64, Vec<char>)>;
+
+/// A stack of keypads. A person presses keys on the first one; a robot arm
+/// over each of the others is driven by the keypad before it, so every
+/// keypad except the last must be directional.
+struct Chain {
+    pads: Vec<Keypad>,
+    /// `best[d][&(a, b)]` is the cheapest way, with the arm over `pads[d]` at
+    /// `a`, to press `b`: the total number of presses by the person, and the
+    /// keys to press on `pads[d - 1]` to do it. `best[0]` is empty, because
+    /// pressing any key on the first keypad costs exactly one press.
+    best: Vec<Table>,
+}
+
+impl Chain {
+    fn new(pads: Vec<Keypad>) -> anyhow::Result<Chain> {
+        anyhow::ensure!(!pads.is_empty(), "no keypads");
+        for (i, pad) in pads[..pads.len() - 1].iter().enumerate() {
+            anyhow::ensure!(
+                pad.is_directional(),
+                "keypad {i} drives another keypad, so it needs ^ v < > A keys"
+            );
+        }
 
-    fn robot3_move(&mut self, dr: i8, dc: i8) {
-        let (r, c) = self.robot3;
-        if 0 <= r + dr && r + dr <= 1 && 0 <= c + dc && c + dc <= 2 && (r + dr, c + dc) != (0, 0) {
-            self.robot3 = (r + dr, c + dc);
+        let mut chain = Chain {
+            best: vec![HashMap::new()],
+            pads,
+        };
+        for d in 1..chain.pads.len() {
+            let table = chain.solve_level(d)?;
+            chain.best.push(table);
         }
+        Ok(chain)
     }
 
-    fn robot3_punch(&mut self) {
-        println!("robot3_punch");
-        match self.robot3 {
-            (0, 1) => self.robot2_move(-1, 0),
-            (0, 2) => self.robot2_punch(),
-            (1, 0) => self.robot2_move(0, -1),
-            (1, 1) => self.robot2_move(1, 0),
-            (1, 2) => self.robot2_move(0, 1),
-            _ => unreachable!(),
+    fn cost(&self, d: usize, from: char, to: char) -> u64 {
+        if d == 0 {
+            1
+        } else {
+            self.best[d][&(from, to)].0
         }
     }
 
-    fn robot2_move(&mut self, dr: i8, dc: i8) {
-        let (r, c) = self.robot2;
-        if 0 <= r + dr && r + dr <= 1 && 0 <= c + dc && c + dc <= 2 && (r + dr, c + dc) != (0, 0) {
-            self.robot2 = (r + dr, c + dc);
+    /// Fill in `best[d]`. The state is where the arm over `pads[d]` is, plus
+    /// the last key pressed on `pads[d - 1]`, which is where that keypad's arm
+    /// is. Each step presses a direction key, moving our arm; the cost is what
+    /// that press costs one level down.
+    fn solve_level(&self, d: usize) -> anyhow::Result<Table> {
+        let pad = &self.pads[d];
+        let mut table = HashMap::new();
+        for &from in pad.keys.keys() {
+            let start = (from, 'A');
+            let parents = dijkstra_all(&start, |&(key, arm)| {
+                "^v<>"
+                    .chars()
+                    .filter_map(move |dir| {
+                        let next = pad.neighbor(key, dir)?;
+                        Some(((next, dir), self.cost(d - 1, arm, dir)))
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            for &to in pad.keys.keys() {
+                let mut options: Vec<((char, char), u64)> = parents
+                    .iter()
+                    .filter(|&(&(key, _), _)| key == to)
+                    .map(|(&state, &(_, cost))| (state, cost))
+                    .collect();
+                if to == from {
+                    options.push((start, 0));
+                }
+                let ((key, arm), cost) = options
+                    .into_iter()
+                    .min_by_key(|&((_, arm), cost)| cost + self.cost(d - 1, arm, 'A'))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("can't get from {from:?} to {to:?} on keypad {d}")
+                    })?;
+                let mut presses: Vec<char> = build_path(&(key, arm), &parents)
+                    .into_iter()
+                    .skip(1)
+                    .map(|(_, dir)| dir)
+                    .collect();
+                presses.push('A');
+                table.insert((from, to), (cost + self.cost(d - 1, arm, 'A'), presses));
+            }
         }
+        Ok(table)
     }
 
-    fn robot2_punch(&mut self) {
-        println!("robot2_punch");
-        match self.robot2 {
-            (0, 1) => self.robot1_move(-1, 0),
-            (0, 2) => self.robot1_punch(),
-            (1, 0) => self.robot1_move(0, -1),
-            (1, 1) => self.robot1_move(1, 0),
-            (1, 2) => self.robot1_move(0, 1),
-            _ => unreachable!(),
+    fn check_code(&self, code: &str) -> anyhow::Result<()> {
+        let last = &self.pads[self.pads.len() - 1];
+        match code.chars().find(|key| !last.keys.contains_key(key)) {
+            Some(key) => anyhow::bail!("no key {key:?} on the last keypad"),
+            None => Ok(()),
         }
     }
 
-    fn robot1_move(&mut self, dr: i8, dc: i8) {
-        let (r, c) = self.robot1;
-        println!("trying to move robot 1 from {r},{c} by {dr},{dc}");
-        if 0 <= r + dr && r + dr <= 3 && 0 <= c + dc && c + dc <= 2 && (r + dr, c + dc) != (3, 0) {
-            println!("success!");
-            self.robot1 = (r + dr, c + dc);
+    /// Fewest presses the person needs to type `code` on the last keypad.
+    fn min_presses(&self, code: &str) -> anyhow::Result<u64> {
+        self.check_code(code)?;
+        let d = self.pads.len() - 1;
+        let mut arm = 'A';
+        let mut total = 0;
+        for key in code.chars() {
+            total += self.cost(d, arm, key);
+            arm = key;
         }
+        Ok(total)
     }
 
-    fn robot1_punch(&mut self) {
-        if self.robot1 == self.goal {
-            self.success = true;
+    /// One shortest sequence of presses that types `code`. This is as long as
+    /// `min_presses` says, so only use it with a few keypads.
+    fn presses(&self, code: &str) -> anyhow::Result<String> {
+        fn expand(chain: &Chain, d: usize, from: char, to: char, out: &mut String) {
+            if d == 0 {
+                out.push(to);
+            } else {
+                let mut arm = 'A';
+                for &key in &chain.best[d][&(from, to)].1 {
+                    expand(chain, d - 1, arm, key, out);
+                    arm = key;
+                }
+            }
         }
+
+        self.check_code(code)?;
+        let mut out = String::new();
+        let mut arm = 'A';
+        for key in code.chars() {
+            expand(self, self.pads.len() - 1, arm, key, &mut out);
+            arm = key;
+        }
+        Ok(out)
     }
-}
 
-// up down left right A
-
-// to move to and push the following button:
-// up down left right A
-// takes this many mashes:
-// 2 3 4 2 1
-
-// pushing any button again is just 1
-
-// to get the second robot to move to and push:
-//   up down left right A
-// takes this many mashes:
-//   8 9 10 6 1
-
-// to get the third robot to move to and push:
-//   0 1 2 3 4 5 6 7 8 9
-// takes:
-//
-
-fn coords(ch: char) -> (i8, i8) {
-    match ch {
-        '0' => (3, 1),
-        '1' => (2, 0),
-        '2' => (2, 1),
-        '3' => (2, 2),
-        '4' => (1, 0),
-        '5' => (1, 1),
-        '6' => (1, 2),
-        '7' => (0, 0),
-        '8' => (0, 1),
-        '9' => (0, 2),
-        'A' => (3, 2),
-        _ => panic!("unrecognized char in code: {ch:?}"),
+    /// Simulate the person pressing `presses`, and return what gets typed on
+    /// the last keypad.
+    fn run(&self, presses: &str) -> anyhow::Result<String> {
+        let mut arms = vec!['A'; self.pads.len()];
+        let mut typed = String::new();
+        for key in presses.chars() {
+            anyhow::ensure!(
+                self.pads[0].keys.contains_key(&key),
+                "no key {key:?} on the first keypad"
+            );
+            let mut d = 0;
+            let mut key = key;
+            loop {
+                if d + 1 == self.pads.len() {
+                    typed.push(key);
+                    break;
+                }
+                if key == 'A' {
+                    d += 1;
+                    key = arms[d];
+                } else {
+                    arms[d + 1] = self.pads[d + 1]
+                        .neighbor(arms[d + 1], key)
+                        .ok_or_else(|| anyhow::anyhow!("arm {} moved off its keypad", d + 1))?;
+                    break;
+                }
+            }
+        }
+        Ok(typed)
     }
 }
 
-fn min_len(code: &str) -> u64 {
-    let mut pos = coords('A');
-    let mut len = 0;
-    for ch in code.chars() {
-        let goal = coords(ch);
-        let state = State::new(pos, goal);
-        let (_path, cost) = dijkstra::dijkstra(
-            &state,
-            move |s| {
-                let s = *s;
-                println!("exploring from {s:?}");
-                [Cmd::Left, Cmd::Right, Cmd::Up, Cmd::Down, Cmd::Activate]
-                    .into_iter()
-                    .map(move |cmd| {
-                        let mut ss = s;
-                        ss.punch(cmd);
-                        (ss, 1)
-                    })
-            },
-            |s| s.success,
-        )
-        .unwrap();
-        len += cost;
-        pos = goal;
-    }
-    len
+/// The puzzle's chain: the person's keypad, `robots` directional keypads
+/// with robots at them, and the door's numeric keypad.
+fn door(robots: usize) -> Chain {
+    let dir = Keypad::parse(DIRECTIONAL).unwrap();
+    let mut pads = vec![dir; robots + 1];
+    pads.push(Keypad::parse(NUMERIC).unwrap());
+    Chain::new(pads).unwrap()
+}
+
+/// One shortest sequence of presses on the person's keypad that gets the
+/// door's keypad to type `code` through `robots` robots.
+pub fn door_presses(robots: usize, code: &str) -> anyhow::Result<String> {
+    door(robots).presses(code)
+}
+
+/// What the door's keypad types when the person presses `presses`, with
+/// `robots` robots in between. Use this to check a sequence.
+pub fn door_run(robots: usize, presses: &str) -> anyhow::Result<String> {
+    door(robots).run(presses)
+}
+
+fn complexity(chain: &Chain, codes: &[String]) -> anyhow::Result<u64> {
+    let mut total = 0;
+    for code in codes {
+        let n = code.trim_end_matches('A').parse::<u64>()?;
+        total += chain.min_presses(code)? * n;
+    }
+    Ok(total)
 }
 
 #[aoc(day21, part1, jorendorff)]
-fn part_1(input: &Input) -> i32 {
-    input
-        .iter()
-        .map(|code| min_len(code) as i32 * code[..code.len() - 1].parse::<i32>().unwrap())
-        .sum()
+fn part_1(input: &Input) -> anyhow::Result<u64> {
+    complexity(&door(2), input)
 }
 
 #[aoc(day21, part2, jorendorff)]
-fn part_2(input: &Input) -> usize {
-    0
+fn part_2(input: &Input) -> anyhow::Result<u64> {
+    complexity(&door(25), input)
 }
 
 #[cfg(test)]
@@ -195,16 +289,68 @@ mod tests {
 
     #[test]
     fn test_part_1() {
-        assert_eq!(min_len("379A"), 64);
-        assert_eq!(min_len("029A"), 68);
-        assert_eq!(min_len("980A"), 60);
-        assert_eq!(min_len("179A"), 68);
-        assert_eq!(min_len("456A"), 64);
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 126384);
+        let chain = door(2);
+        assert_eq!(chain.min_presses("379A").unwrap(), 64);
+        assert_eq!(chain.min_presses("029A").unwrap(), 68);
+        assert_eq!(chain.min_presses("980A").unwrap(), 60);
+        assert_eq!(chain.min_presses("179A").unwrap(), 68);
+        assert_eq!(chain.min_presses("456A").unwrap(), 64);
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), 126384);
     }
 
     #[test]
     fn test_part_2() {
-        assert_eq!(part_2(&parse_input(EXAMPLE).unwrap()), 0);
+        assert_eq!(
+            part_2(&parse_input(EXAMPLE).unwrap()).unwrap(),
+            154115708116294
+        );
+    }
+
+    #[test]
+    fn test_presses() {
+        let chain = door(2);
+        assert_eq!(
+            chain
+                .run("<v<A>>^AvA^A<vA<AA>>^AAvA<^A>AAvA^A<vA>^AA<A>A<v<A>A>^AAAvA<^A>A")
+                .unwrap(),
+            "379A"
+        );
+        for code in parse_input(EXAMPLE).unwrap() {
+            let presses = chain.presses(&code).unwrap();
+            assert_eq!(presses.len() as u64, chain.min_presses(&code).unwrap());
+            assert_eq!(chain.run(&presses).unwrap(), code);
+        }
+
+        assert_eq!(door_presses(0, "0").unwrap(), "<A");
+        assert!(door_run(0, "<<").is_err());
+        assert_eq!(door_run(1, "<A").unwrap(), "");
+        assert!(door_run(1, "v<<A<A").is_err());
+        let presses = door_presses(3, "029A").unwrap();
+        assert_eq!(door_run(3, &presses).unwrap(), "029A");
+    }
+
+    #[test]
+    fn test_detour() {
+        // Getting from 1 to 3 means going down and around the gap.
+        let pad = Keypad::parse("1 3\n2A4").unwrap();
+        let dir = Keypad::parse(DIRECTIONAL).unwrap();
+        let chain = Chain::new(vec![dir.clone(), dir, pad]).unwrap();
+        for code in ["13A", "31", "4213"] {
+            let presses = chain.presses(code).unwrap();
+            assert_eq!(presses.len() as u64, chain.min_presses(code).unwrap());
+            assert_eq!(chain.run(&presses).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Keypad::parse("12\n1A").is_err());
+        assert!(Keypad::parse("12\n34").is_err());
+        let numeric = Keypad::parse(NUMERIC).unwrap();
+        assert!(Chain::new(vec![numeric.clone(), numeric]).is_err());
+        let split = Keypad::parse("1 A").unwrap();
+        let dir = Keypad::parse(DIRECTIONAL).unwrap();
+        assert!(Chain::new(vec![dir, split]).is_err());
+        assert!(door(2).min_presses("12B").is_err());
     }
 }
@@ -1,6 +1,6 @@
-use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, self, Formatter};
 
+use adlib::Graph;
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
@@ -34,97 +34,19 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
 
 #[aoc(day23, part1, jorendorff)]
 fn part_1(input: &Input) -> usize {
-    let mut neighbors = HashMap::<Host, HashSet<Host>>::new();
-    for (a, b) in input {
-        neighbors.entry(*a).or_default().insert(*b);
-        neighbors.entry(*b).or_default().insert(*a);
-    }
-
-    let mut found = HashSet::<[Host; 3]>::new();
-    for (a, b) in input {
-        if a.starts_with_t() || b.starts_with_t() {
-            for c in &neighbors[a] {
-                if neighbors[b].contains(c) {
-                    let mut triple = [*a, *b, *c];
-                    triple.sort_unstable(); // canonical form, since HashSet<String> isn't hashable
-                    found.insert(triple);
-                }
-            }
-        }
-    }
-
-    found.len()
-}
-
-fn is_subsequence<T: PartialEq<U>, U>(needles: &[T], haystack: &[U]) -> bool {
-    let mut hay_iter = haystack.iter();
-    for needle in needles {
-        loop {
-            let Some(item) = hay_iter.next() else {
-                return false;
-            };
-            if needle == item {
-                break;
-            }
-        }
-    }
-    true
+    let (graph, hosts) = Graph::from_labeled_edges(input);
+    graph.count_k_cliques_containing(3, |i| hosts[i].starts_with_t())
 }
 
 #[aoc(day23, part2, jorendorff)]
 fn part_2(input: &Input) -> String {
-    let mut neighbors = HashMap::<Host, Vec<Host>>::new();
-    for (a, b) in input {
-        neighbors.entry(*a).or_default().push(*b);
-        neighbors.entry(*b).or_default().push(*a);
-    }
-
-    for list in neighbors.values_mut() {
-        list.sort();
-        list.dedup();
-    }
-
-    let mut best_set = Vec::<Host>::new();
-    // Loop invariant: best_set is one of the largest fully connected sets of hosts that contains
-    // any of the hosts that have been h0 so far.
-    for (h0, hs) in &neighbors {
-        if hs.len() > best_set.len() && !best_set.contains(h0) {
-            improve(&neighbors, &mut best_set, hs, 0, &mut vec![*h0]);
-        }
-    }
-
+    let (graph, hosts) = Graph::from_labeled_edges(input);
+    let mut best_set: Vec<Host> = graph.maximum_clique().into_iter().map(|i| hosts[i]).collect();
     best_set.sort_unstable();
     let best_set = best_set.into_iter().map(|h| h.to_string()).collect::<Vec<String>>();
     best_set.join(",")
 }
 
-/// If there is a set containing all of `*current_set`
-///
-/// This mutates current_set locally but on return it is unchanged.
-fn improve(
-    neighbors: &HashMap<Host, Vec<Host>>,
-    best_set: &mut Vec<Host>,
-    hs: &[Host],
-    start: usize,
-    current_set: &mut Vec<Host>,
-) {
-    for (i, hi) in hs.iter().enumerate().skip(start) {
-        let his = &neighbors[hi];
-        if his.len() > best_set.len() && {
-            let mut sorted = current_set.clone();
-            sorted.sort_unstable();
-            is_subsequence(&sorted, his)
-        } {
-            current_set.push(*hi);
-            improve(neighbors, best_set, hs, i + 1, current_set);
-            if current_set.len() > best_set.len() {
-                *best_set = current_set.clone();
-            }
-            current_set.pop();
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,18 +1,12 @@
 // Part 1 rank 545, Part 2 rank 202
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
+use adlib::{read_bus, Gate, Netlist, Op};
 use aoc_parse::{parser, prelude::*};
 use aoc_runner_derive::*;
 
-type Input = (HashMap<String, u8>, HashMap<String, (String, Op, String)>);
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum Op {
-    And,
-    Xor,
-    Or
-}
+type Input = (HashMap<String, bool>, Netlist);
 
 #[aoc_generator(day24, part1, jorendorff)]
 #[aoc_generator(day24, part2, jorendorff)]
@@ -25,84 +19,33 @@ fn parse_input(text: &str) -> anyhow::Result<Input> {
     });
     let p = parser!(
         section(hash_map(lines(
-            ident ": " u8
-        )))
-        section(hash_map(lines(
-            l:ident ' ' op:op ' ' r:ident " -> " out:ident => (out, (l, op, r))
+            w:ident ": " v:u8 => (w, v == 1)
         )))
+        section(lines(
+            l:ident ' ' op:op ' ' r:ident " -> " out:ident => Gate { op, inputs: [l, r], output: out }
+        ))
     );
-    Ok(p.parse(text)?)
+    let (inputs, gates) = p.parse(text)?;
+    Ok((inputs, Netlist::new(gates)?))
 }
 
 #[aoc(day24, part1, jorendorff)]
-fn part_1(input: &Input) -> u64 {
-    let (inputs, rules) = input;
-    let mut inputs = inputs.clone();
-    loop {
-        let mut any = false;
-        for (out, (lhs, op, rhs)) in rules {
-            if !inputs.contains_key(out) {
-                if let (Some(&lv), Some(&rv)) = (inputs.get(lhs), inputs.get(rhs)) {
-                    let out_v = match op {
-                        Op::And => lv & rv,
-                        Op::Xor => lv ^ rv,
-                        Op::Or => lv | rv,
-                    };
-                    inputs.insert(out.clone(), out_v);
-                    any = true;
-                }
-            }
-        }
-        if !any {
-            break;
-        }
-    }
-    let mut ans = 0;
-    for i in 0u32.. {
-        if let Some(&v) = inputs.get(&format!("z{i:02}")) {
-            ans |= (v as u64) << i;
-        } else {
-            break;
-        }
-    }
-    ans
+fn part_1(input: &Input) -> anyhow::Result<u64> {
+    let (inputs, netlist) = input;
+    Ok(read_bus(&netlist.eval(inputs)?, "z"))
 }
 
 #[aoc(day24, part2, jorendorff)]
-fn part_2(input: &Input) -> String {
-    let (_inputs, rules) = input;
-
-    let mut output = String::new();
-    let mut seen = HashSet::new();
-    for i in 0..=45 {
-        let mut round = vec![];
-        let mut todo = vec![format!("z{i:02}")];
-        seen.insert(todo[0].clone());
-        while let Some(name) = todo.pop() {
-            let Some((lhs, op, rhs)) = rules.get(&name) else {
-                continue;
-            };
-            if !seen.contains(lhs) {
-                seen.insert(lhs.clone());
-                todo.push(lhs.clone());
-            }
-            if !seen.contains(rhs) {
-                seen.insert(rhs.clone());
-                todo.push(rhs.clone());
-            }
-            let mut operands = [lhs.as_str(), rhs.as_str()];
-            operands.sort();
-            round.push(format!("{op:?} {} -> {name}\n", operands.join(" ")));
-        }
-
-        round.reverse();
-        let n = round.len();
-        round[..n - 1].sort_by_key(|s| s.split_whitespace().next().unwrap().to_string());
-        output += &round.join("");
-        output += "\n";
-    }
-
-    output
+fn part_2(input: &Input) -> anyhow::Result<String> {
+    let (_inputs, netlist) = input;
+    let mut netlist = netlist.clone();
+    let mut wires = netlist
+        .repair_adder("x", "y", "z")?
+        .into_iter()
+        .flat_map(|(a, b)| [a, b])
+        .collect::<Vec<String>>();
+    wires.sort();
+    Ok(wires.join(","))
 }
 
 #[cfg(test)]
@@ -170,12 +113,49 @@ y03 OR x01 -> nrd
 hwm AND bqk -> z03
 tgd XOR rvg -> z12
 tnw OR pbm -> gnj
+";
+
+    // A 4-bit ripple-carry adder with two pairs of gate outputs swapped:
+    // abq/hdr in bit 1 and tbd/z02 in bit 2.
+    const BROKEN_ADDER: &str = "\
+x00: 1
+x01: 0
+x02: 1
+x03: 1
+y00: 1
+y01: 1
+y02: 0
+y03: 1
+
+x00 XOR y00 -> z00
+x00 AND y00 -> kmc
+x01 XOR y01 -> hdr
+x01 AND y01 -> abq
+kmc XOR abq -> z01
+abq AND kmc -> fpt
+hdr OR fpt -> wqs
+y02 XOR x02 -> nvc
+x02 AND y02 -> rjk
+nvc XOR wqs -> tbd
+wqs AND nvc -> z02
+tbd OR rjk -> gmv
+x03 XOR y03 -> pls
+y03 AND x03 -> cqd
+gmv XOR pls -> z03
+pls AND gmv -> jhk
+cqd OR jhk -> z04
 ";
 
     #[test]
     fn test_part_1() {
-        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()), 4);
-        assert_eq!(part_1(&parse_input(EXAMPLE2).unwrap()), 2024);
-        
+        assert_eq!(part_1(&parse_input(EXAMPLE).unwrap()).unwrap(), 4);
+        assert_eq!(part_1(&parse_input(EXAMPLE2).unwrap()).unwrap(), 2024);
+    }
+
+    #[test]
+    fn test_part_2() {
+        let input = parse_input(BROKEN_ADDER).unwrap();
+        assert_eq!(part_2(&input).unwrap(), "abq,hdr,tbd,z02");
+        assert!(part_2(&parse_input(EXAMPLE2).unwrap()).is_err());
     }
 }
@@ -0,0 +1,196 @@
+//! Branch-and-bound search for puzzles that ask "what's the most you can get?"
+//!
+//! Implement [`Problem`] for the puzzle, then run [`BranchAndBound::maximize`].
+//! Each pruning technique can be switched off separately, and [`Stats`] counts
+//! what each one saved, so different bounds can be compared.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A maximization problem explored by depth-first search.
+pub trait Problem {
+    type State: Clone + Eq + Hash;
+
+    /// A score that is guaranteed to be achievable from `state` (for
+    /// example, the score if we stop making choices now).
+    fn value(&self, state: &Self::State) -> u64;
+
+    /// An optimistic estimate: nothing reachable from `state` scores more than this.
+    fn upper_bound(&self, state: &Self::State) -> u64;
+
+    /// States reachable in one move, best guesses first. Finding good
+    /// solutions early makes the bound prune more.
+    fn successors(&self, state: &Self::State, out: &mut Vec<Self::State>);
+
+    /// States that can be compared for dominance share a group. Returning
+    /// `None` (the default) turns dominance pruning off for this state.
+    fn dominance_group(&self, _state: &Self::State) -> Option<u64> {
+        None
+    }
+
+    /// True if `a` is at least as good as `b` in every way, so `b` need
+    /// not be explored if `a` already has been. Only called on states in
+    /// the same dominance group.
+    fn dominates(&self, _a: &Self::State, _b: &Self::State) -> bool {
+        false
+    }
+}
+
+/// Counters from one search.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Stats {
+    /// States whose successors were generated.
+    pub expanded: u64,
+    /// States skipped because their upper bound couldn't beat the best so far.
+    pub pruned_by_bound: u64,
+    /// States skipped because an already-explored state dominates them.
+    pub pruned_by_dominance: u64,
+    /// States skipped because they were already explored.
+    pub memo_hits: u64,
+}
+
+pub struct BranchAndBound<P: Problem> {
+    pub problem: P,
+    pub use_bound: bool,
+    pub use_dominance: bool,
+    pub use_memo: bool,
+}
+
+impl<P: Problem> BranchAndBound<P> {
+    /// A search with every kind of pruning turned on.
+    pub fn new(problem: P) -> Self {
+        BranchAndBound {
+            problem,
+            use_bound: true,
+            use_dominance: true,
+            use_memo: true,
+        }
+    }
+
+    /// Find the best value reachable from `start`.
+    pub fn maximize(&self, start: P::State) -> (u64, Stats) {
+        let mut search = Search {
+            bnb: self,
+            best: 0,
+            stats: Stats::default(),
+            seen: HashSet::new(),
+            groups: HashMap::new(),
+        };
+        search.visit(start);
+        (search.best, search.stats)
+    }
+}
+
+struct Search<'a, P: Problem> {
+    bnb: &'a BranchAndBound<P>,
+    best: u64,
+    stats: Stats,
+    seen: HashSet<P::State>,
+    groups: HashMap<u64, Vec<P::State>>,
+}
+
+impl<P: Problem> Search<'_, P> {
+    fn visit(&mut self, state: P::State) {
+        let problem = &self.bnb.problem;
+        self.best = self.best.max(problem.value(&state));
+
+        if self.bnb.use_bound && problem.upper_bound(&state) <= self.best {
+            self.stats.pruned_by_bound += 1;
+            return;
+        }
+        if self.bnb.use_memo {
+            if self.seen.contains(&state) {
+                self.stats.memo_hits += 1;
+                return;
+            }
+            self.seen.insert(state.clone());
+        }
+        if self.bnb.use_dominance {
+            if let Some(group) = problem.dominance_group(&state) {
+                let others = self.groups.entry(group).or_default();
+                if others.iter().any(|other| problem.dominates(other, &state)) {
+                    self.stats.pruned_by_dominance += 1;
+                    return;
+                }
+                others.retain(|other| !problem.dominates(&state, other));
+                others.push(state.clone());
+            }
+        }
+
+        self.stats.expanded += 1;
+        let mut next = vec![];
+        problem.successors(&state, &mut next);
+        for s in next {
+            self.visit(s);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0/1 knapsack: each state is (next item to consider, weight used, value so far).
+    struct Knapsack {
+        items: Vec<(u64, u64)>, // (weight, value)
+        capacity: u64,
+    }
+
+    impl Problem for Knapsack {
+        type State = (usize, u64, u64);
+
+        fn value(&self, s: &Self::State) -> u64 {
+            s.2
+        }
+
+        fn upper_bound(&self, s: &Self::State) -> u64 {
+            s.2 + self.items[s.0..].iter().map(|&(_, v)| v).sum::<u64>()
+        }
+
+        fn successors(&self, &(i, w, v): &Self::State, out: &mut Vec<Self::State>) {
+            if let Some(&(iw, iv)) = self.items.get(i) {
+                if w + iw <= self.capacity {
+                    out.push((i + 1, w + iw, v + iv));
+                }
+                out.push((i + 1, w, v));
+            }
+        }
+
+        fn dominance_group(&self, s: &Self::State) -> Option<u64> {
+            Some(s.0 as u64)
+        }
+
+        fn dominates(&self, a: &Self::State, b: &Self::State) -> bool {
+            a.1 <= b.1 && a.2 >= b.2
+        }
+    }
+
+    fn example() -> Knapsack {
+        Knapsack {
+            items: vec![(12, 4), (2, 2), (1, 1), (1, 2), (4, 10), (3, 3), (7, 8)],
+            capacity: 15,
+        }
+    }
+
+    #[test]
+    fn test_all_strategies_agree() {
+        let mut bnb = BranchAndBound::new(example());
+        let (best, all_on) = bnb.maximize((0, 0, 0));
+        assert_eq!(best, 23);
+
+        bnb.use_bound = false;
+        bnb.use_dominance = false;
+        bnb.use_memo = false;
+        let (best, all_off) = bnb.maximize((0, 0, 0));
+        assert_eq!(best, 23);
+        assert_eq!(all_off.pruned_by_bound, 0);
+        assert_eq!(all_off.pruned_by_dominance, 0);
+        assert_eq!(all_off.memo_hits, 0);
+        assert!(all_on.expanded < all_off.expanded);
+
+        bnb.use_dominance = true;
+        let (best, dominance_only) = bnb.maximize((0, 0, 0));
+        assert_eq!(best, 23);
+        assert!(dominance_only.pruned_by_dominance > 0);
+    }
+}
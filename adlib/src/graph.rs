@@ -0,0 +1,331 @@
+//! Undirected graphs and clique finding.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::BitSet;
+
+/// Above this many nodes, clique search uses sorted neighbor lists instead of bitsets.
+const BITSET_LIMIT: usize = 4096;
+
+/// A simple undirected graph on the nodes `0..num_nodes()`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Graph {
+    /// Neighbor lists, each sorted and free of duplicates.
+    neighbors: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    pub fn new(num_nodes: usize) -> Self {
+        Graph {
+            neighbors: vec![vec![]; num_nodes],
+        }
+    }
+
+    pub fn from_edges(num_nodes: usize, edges: impl IntoIterator<Item = (usize, usize)>) -> Self {
+        let mut g = Graph::new(num_nodes);
+        for (a, b) in edges {
+            g.add_edge(a, b);
+        }
+        g
+    }
+
+    /// Build a graph from edges between arbitrary labels. Returns the graph
+    /// and the label of each node, in order of first appearance.
+    pub fn from_labeled_edges<'a, L>(edges: impl IntoIterator<Item = &'a (L, L)>) -> (Self, Vec<L>)
+    where
+        L: Eq + Hash + Clone + 'a,
+    {
+        let mut ids = HashMap::<L, usize>::new();
+        let mut labels = vec![];
+        let mut g = Graph::new(0);
+        let mut id = |g: &mut Graph, label: &L| {
+            *ids.entry(label.clone()).or_insert_with(|| {
+                labels.push(label.clone());
+                g.neighbors.push(vec![]);
+                g.neighbors.len() - 1
+            })
+        };
+        for (a, b) in edges {
+            let a = id(&mut g, a);
+            let b = id(&mut g, b);
+            g.add_edge(a, b);
+        }
+        (g, labels)
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    /// Add an edge between `a` and `b`. Self-loops and duplicate edges are ignored.
+    pub fn add_edge(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        for (x, y) in [(a, b), (b, a)] {
+            let list = &mut self.neighbors[x];
+            if let Err(i) = list.binary_search(&y) {
+                list.insert(i, y);
+            }
+        }
+    }
+
+    pub fn has_edge(&self, a: usize, b: usize) -> bool {
+        self.neighbors[a].binary_search(&b).is_ok()
+    }
+
+    /// Neighbors of `v`, in increasing order.
+    pub fn neighbors(&self, v: usize) -> &[usize] {
+        &self.neighbors[v]
+    }
+
+    /// Call `f` once for each maximal clique, using Bron–Kerbosch with pivoting.
+    /// Each clique is passed to `f` in increasing order.
+    pub fn for_each_maximal_clique(&self, mut f: impl FnMut(&[usize])) {
+        self.search_cliques(&mut |clique| {
+            let mut clique = clique.to_vec();
+            clique.sort_unstable();
+            f(&clique);
+            0
+        });
+    }
+
+    pub fn maximal_cliques(&self) -> Vec<Vec<usize>> {
+        let mut cliques = vec![];
+        self.for_each_maximal_clique(|c| cliques.push(c.to_vec()));
+        cliques
+    }
+
+    /// One of the largest cliques in the graph, in increasing order.
+    pub fn maximum_clique(&self) -> Vec<usize> {
+        let mut best = vec![];
+        self.search_cliques(&mut |clique| {
+            if clique.len() > best.len() {
+                best = clique.to_vec();
+            }
+            best.len() + 1
+        });
+        best.sort_unstable();
+        best
+    }
+
+    /// Number of cliques of exactly `k` nodes that contain at least one node
+    /// for which `pred` returns true. Unlike the other methods here, this
+    /// counts all cliques, not just maximal ones.
+    pub fn count_k_cliques_containing(&self, k: usize, pred: impl Fn(usize) -> bool) -> usize {
+        let matching: Vec<bool> = (0..self.num_nodes()).map(pred).collect();
+        self.count_k_cliques_where(k, |_| true) - self.count_k_cliques_where(k, |v| !matching[v])
+    }
+
+    /// Number of `k`-cliques made up entirely of nodes for which `allowed` returns true.
+    fn count_k_cliques_where(&self, k: usize, allowed: impl Fn(usize) -> bool) -> usize {
+        fn count(g: &Graph, k: usize, candidates: &[usize]) -> usize {
+            if k == 1 {
+                return candidates.len();
+            }
+            candidates
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| {
+                    // Only extend with higher-numbered nodes, so each clique is counted once.
+                    let next = intersect(&candidates[i + 1..], g.neighbors(v));
+                    count(g, k - 1, &next)
+                })
+                .sum()
+        }
+
+        if k == 0 {
+            return 1;
+        }
+        let nodes: Vec<usize> = (0..self.num_nodes()).filter(|&v| allowed(v)).collect();
+        count(self, k, &nodes)
+    }
+
+    /// Run Bron–Kerbosch, calling `visit` on each maximal clique found (in no
+    /// particular node order). `visit` returns the smallest clique size still
+    /// of interest, so branches that can't reach that size are pruned.
+    fn search_cliques(&self, visit: &mut dyn FnMut(&[usize]) -> usize) {
+        let n = self.num_nodes();
+        let mut r = vec![];
+        let mut min_size = 0;
+        if n <= BITSET_LIMIT {
+            let adj: Vec<BitSet> = self
+                .neighbors
+                .iter()
+                .map(|list| {
+                    let mut set = BitSet::new(n);
+                    for &v in list {
+                        set.insert(v);
+                    }
+                    set
+                })
+                .collect();
+            bk_bits(&adj, &mut r, BitSet::full(n), BitSet::new(n), &mut min_size, visit);
+        } else {
+            let p = (0..n).collect();
+            bk_lists(self, &mut r, p, vec![], &mut min_size, visit);
+        }
+    }
+}
+
+/// Elements common to two sorted slices.
+fn intersect(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut out = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+fn intersect_len(a: &[usize], b: &[usize]) -> usize {
+    intersect(a, b).len()
+}
+
+fn bk_bits(
+    adj: &[BitSet],
+    r: &mut Vec<usize>,
+    mut p: BitSet,
+    mut x: BitSet,
+    min_size: &mut usize,
+    visit: &mut dyn FnMut(&[usize]) -> usize,
+) {
+    if p.is_empty() {
+        if x.is_empty() {
+            *min_size = visit(r);
+        }
+        return;
+    }
+    if r.len() + p.len() < *min_size {
+        return;
+    }
+
+    // Tomita pivot: the node of P ∪ X with the most neighbors in P.
+    let pivot = p
+        .iter()
+        .chain(x.iter())
+        .max_by_key(|&u| p.intersection_len(&adj[u]))
+        .unwrap();
+    let todo: Vec<usize> = p.iter().filter(|&v| !adj[pivot].contains(v)).collect();
+    for v in todo {
+        r.push(v);
+        bk_bits(adj, r, p.intersection(&adj[v]), x.intersection(&adj[v]), min_size, visit);
+        r.pop();
+        p.remove(v);
+        x.insert(v);
+    }
+}
+
+fn bk_lists(
+    g: &Graph,
+    r: &mut Vec<usize>,
+    mut p: Vec<usize>,
+    mut x: Vec<usize>,
+    min_size: &mut usize,
+    visit: &mut dyn FnMut(&[usize]) -> usize,
+) {
+    if p.is_empty() {
+        if x.is_empty() {
+            *min_size = visit(r);
+        }
+        return;
+    }
+    if r.len() + p.len() < *min_size {
+        return;
+    }
+
+    let pivot = p
+        .iter()
+        .chain(x.iter())
+        .copied()
+        .max_by_key(|&u| intersect_len(&p, g.neighbors(u)))
+        .unwrap();
+    let todo: Vec<usize> = p.iter().copied().filter(|&v| !g.has_edge(pivot, v)).collect();
+    for v in todo {
+        r.push(v);
+        let next_p = intersect(&p, g.neighbors(v));
+        let next_x = intersect(&x, g.neighbors(v));
+        bk_lists(g, r, next_p, next_x, min_size, visit);
+        r.pop();
+        p.retain(|&u| u != v);
+        let i = x.binary_search(&v).unwrap_err();
+        x.insert(i, v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles {0,1,2} and {2,3,4} sharing a node, plus a 4-clique {5,6,7,8}
+    /// and an isolated node 9.
+    fn sample() -> Graph {
+        Graph::from_edges(
+            10,
+            [
+                (0, 1), (1, 2), (0, 2),
+                (2, 3), (3, 4), (2, 4),
+                (5, 6), (5, 7), (5, 8), (6, 7), (6, 8), (7, 8),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_maximal_cliques() {
+        let mut cliques = sample().maximal_cliques();
+        cliques.sort();
+        assert_eq!(cliques, vec![vec![0, 1, 2], vec![2, 3, 4], vec![5, 6, 7, 8], vec![9]]);
+    }
+
+    #[test]
+    fn test_maximum_clique() {
+        assert_eq!(sample().maximum_clique(), vec![5, 6, 7, 8]);
+        assert_eq!(Graph::new(0).maximum_clique(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_count_k_cliques() {
+        let g = sample();
+        assert_eq!(g.count_k_cliques_containing(3, |_| true), 6);
+        assert_eq!(g.count_k_cliques_containing(3, |v| v == 2), 2);
+        assert_eq!(g.count_k_cliques_containing(3, |v| v == 5), 3);
+        assert_eq!(g.count_k_cliques_containing(4, |_| true), 1);
+        assert_eq!(g.count_k_cliques_containing(2, |v| v == 9), 0);
+    }
+
+    #[test]
+    fn test_lists_match_bits() {
+        // Exercise the sorted-list implementation directly on a small graph.
+        let g = sample();
+        let mut cliques = vec![];
+        let mut min_size = 0;
+        bk_lists(&g, &mut vec![], (0..10).collect(), vec![], &mut min_size, &mut |c| {
+            let mut c = c.to_vec();
+            c.sort_unstable();
+            cliques.push(c);
+            0
+        });
+        cliques.sort();
+        let mut expected = g.maximal_cliques();
+        expected.sort();
+        assert_eq!(cliques, expected);
+    }
+
+    #[test]
+    fn test_labeled() {
+        let edges = [("a", "b"), ("b", "c"), ("c", "a"), ("c", "d")];
+        let (g, labels) = Graph::from_labeled_edges(&edges);
+        assert_eq!(labels, vec!["a", "b", "c", "d"]);
+        let best: Vec<&str> = g.maximum_clique().into_iter().map(|i| labels[i]).collect();
+        assert_eq!(best, vec!["a", "b", "c"]);
+    }
+}
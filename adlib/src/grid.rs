@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Index, IndexMut};
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum Dir {
     Right,
     Up,
@@ -12,7 +12,7 @@ pub use Dir::*;
 
 pub const DIRS: [Dir; 4] = [Right, Up, Left, Down];
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct Point {
     pub row: usize,
     pub col: usize,
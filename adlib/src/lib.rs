@@ -1,4 +1,25 @@
+mod bitset;
+mod bnb;
+mod graph;
 mod grid;
+mod maze;
+mod momentum;
+mod netlist;
+mod numeral;
+mod order_list;
+mod recurrence;
+mod tour;
+mod trie;
 
+pub use bitset::*;
+pub use bnb::*;
+pub use graph::*;
 pub use grid::*;
-
+pub use maze::*;
+pub use momentum::*;
+pub use netlist::*;
+pub use numeral::*;
+pub use order_list::*;
+pub use recurrence::*;
+pub use tour::*;
+pub use trie::*;
@@ -0,0 +1,349 @@
+//! Longest simple paths through grid mazes.
+//!
+//! Most of a typical maze is long corridors with no choices in them. We
+//! contract each corridor to a single weighted edge between junctions, then
+//! search the (much smaller) junction graph.
+
+use std::collections::HashMap;
+
+use crate::{BitSet, Dir, Grid, Point, DIRS};
+
+/// One cell of a maze.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Tile {
+    Wall,
+    Open,
+    /// A one-way tile: after stepping onto it, the next step must go this direction.
+    Slope(Dir),
+}
+
+impl Tile {
+    /// Parse one of `#.^>v<`.
+    pub fn from_char(c: char) -> Option<Tile> {
+        Some(match c {
+            '#' => Tile::Wall,
+            '.' => Tile::Open,
+            '^' => Tile::Slope(Dir::Up),
+            '>' => Tile::Slope(Dir::Right),
+            'v' => Tile::Slope(Dir::Down),
+            '<' => Tile::Slope(Dir::Left),
+            _ => return None,
+        })
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::Wall => '#',
+            Tile::Open => '.',
+            Tile::Slope(Dir::Up) => '^',
+            Tile::Slope(Dir::Right) => '>',
+            Tile::Slope(Dir::Down) => 'v',
+            Tile::Slope(Dir::Left) => '<',
+        }
+    }
+}
+
+/// A corridor from one junction to another.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Edge {
+    pub to: usize,
+    /// Cells along the corridor, not including the starting junction but
+    /// including the destination. So `path.len()` is the number of steps.
+    pub path: Vec<Point>,
+}
+
+impl Edge {
+    pub fn len(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path.is_empty()
+    }
+}
+
+/// A maze contracted to a directed graph of junctions.
+#[derive(Debug, Clone)]
+pub struct JunctionGraph {
+    /// Location of each junction. The start is always junction 0 and the end
+    /// is junction 1.
+    pub junctions: Vec<Point>,
+    /// Outgoing edges of each junction. A corridor that can be walked both
+    /// ways appears once in each direction.
+    pub edges: Vec<Vec<Edge>>,
+}
+
+impl JunctionGraph {
+    /// Contract `grid` into a junction graph. Junctions are `start`, `end`, and
+    /// every open cell with more than two open neighbors. Corridors that dead-end
+    /// or that would require walking up a slope are dropped.
+    pub fn contract(grid: &Grid<Tile>, start: Point, end: Point) -> Self {
+        let is_open = |p: Point| grid.get(p).is_some_and(|&t| t != Tile::Wall);
+        let step = |p: Point, d: Dir| -> Option<Point> {
+            // Stepping off row or column 0 wraps to a huge index, which `grid.get` rejects.
+            let q = p + d;
+            if is_open(q) {
+                Some(q)
+            } else {
+                None
+            }
+        };
+        let can_leave = |p: Point, d: Dir| match grid[p] {
+            Tile::Slope(s) => s == d,
+            _ => true,
+        };
+
+        let mut junctions = vec![start, end];
+        for (p, &t) in grid.cells() {
+            if t != Tile::Wall
+                && p != start
+                && p != end
+                && DIRS.iter().filter(|&&d| step(p, d).is_some()).count() > 2
+            {
+                junctions.push(p);
+            }
+        }
+        let index: HashMap<Point, usize> =
+            junctions.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+
+        let edges = junctions
+            .iter()
+            .map(|&j| {
+                DIRS.iter()
+                    .filter_map(|&first| {
+                        if !can_leave(j, first) {
+                            return None;
+                        }
+                        let mut cur = step(j, first)?;
+                        let mut dir = first;
+                        let mut path = vec![cur];
+                        while !index.contains_key(&cur) {
+                            // In a corridor there is exactly one way forward (or none, at a dead end).
+                            let d = DIRS
+                                .into_iter()
+                                .find(|&d| d != dir.reverse() && step(cur, d).is_some())?;
+                            if !can_leave(cur, d) {
+                                return None;
+                            }
+                            cur = step(cur, d).unwrap();
+                            dir = d;
+                            path.push(cur);
+                        }
+                        Some(Edge {
+                            to: index[&cur],
+                            path,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        JunctionGraph { junctions, edges }
+    }
+
+    /// The longest path from the start junction to the end junction that
+    /// doesn't visit any junction twice. Returns the length and the
+    /// junctions along the way, or `None` if the end is unreachable.
+    pub fn longest_path(&self) -> Option<(usize, Vec<usize>)> {
+        let n = self.junctions.len();
+
+        // Upper bound: every remaining step of the path belongs to an edge
+        // ending at a distinct unvisited junction, so sum up the longest edge
+        // into each unvisited junction.
+        let mut max_in = vec![0; n];
+        for list in &self.edges {
+            for e in list {
+                max_in[e.to] = max_in[e.to].max(e.len());
+            }
+        }
+
+        struct Search<'a> {
+            g: &'a JunctionGraph,
+            max_in: Vec<usize>,
+            path: Vec<usize>,
+            visited: BitSet,
+            best: Option<(usize, Vec<usize>)>,
+        }
+
+        impl Search<'_> {
+            fn go(&mut self, at: usize, length: usize, bound: usize) {
+                if at == 1 {
+                    if self.best.as_ref().is_none_or(|(b, _)| length > *b) {
+                        self.best = Some((length, self.path.clone()));
+                    }
+                    return;
+                }
+                if let Some((b, _)) = self.best {
+                    if length + bound <= b {
+                        return;
+                    }
+                }
+                for e in &self.g.edges[at] {
+                    if !self.visited.contains(e.to) {
+                        self.path.push(e.to);
+                        self.visited.insert(e.to);
+                        let bound = bound - self.max_in[e.to];
+                        self.go(e.to, length + e.len(), bound);
+                        self.visited.remove(e.to);
+                        self.path.pop();
+                    }
+                }
+            }
+        }
+
+        let bound = max_in.iter().sum::<usize>() - max_in[0];
+        let mut search = Search {
+            g: self,
+            max_in,
+            path: vec![0],
+            visited: BitSet::new(n),
+            best: None,
+        };
+        search.visited.insert(0);
+        search.go(0, 0, bound);
+        search.best
+    }
+
+    /// Expand a path of junctions into every cell along the way, starting
+    /// with the start junction's cell.
+    pub fn cells_along(&self, junction_path: &[usize]) -> Vec<Point> {
+        let mut cells = vec![self.junctions[junction_path[0]]];
+        for pair in junction_path.windows(2) {
+            let edge = self.edges[pair[0]]
+                .iter()
+                .filter(|e| e.to == pair[1])
+                .max_by_key(|e| e.len())
+                .expect("junctions in path must be connected");
+            cells.extend_from_slice(&edge.path);
+        }
+        cells
+    }
+}
+
+/// Draw the maze with `O` on every cell of `path`.
+pub fn render_path(grid: &Grid<Tile>, path: &[Point]) -> String {
+    let mut chars: Vec<Vec<char>> = grid
+        .data
+        .iter()
+        .map(|row| row.iter().map(|t| t.to_char()).collect())
+        .collect();
+    for p in path {
+        chars[p.row][p.col] = 'O';
+    }
+    chars
+        .into_iter()
+        .map(|row| row.into_iter().collect::<String>() + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+#.#####################
+#.......#########...###
+#######.#########.#.###
+###.....#.>.>.###.#.###
+###v#####.#v#.###.#.###
+###.>...#.#.#.....#...#
+###v###.#.#.#########.#
+###...#.#.#.......#...#
+#####.#.#.#######.#.###
+#.....#.#.#.......#...#
+#.#####.#.#.#########v#
+#.#...#...#...###...>.#
+#.#.#v#######v###.###v#
+#...#.>.#...>.>.#.###.#
+#####v#.#.###v#.#.###.#
+#.....#...#...#.#.#...#
+#.#########.###.#.#.###
+#...###...#...#...#.###
+###.###.#.###v#####v###
+#...#...#.#.>.>.#.>.###
+#.###.###.#.###.#.#v###
+#.....###...###...#...#
+#####################.#
+";
+
+    fn parse(text: &str) -> Grid<Tile> {
+        Grid {
+            data: text
+                .lines()
+                .map(|line| line.chars().map(|c| Tile::from_char(c).unwrap()).collect())
+                .collect(),
+        }
+    }
+
+    fn endpoints(grid: &Grid<Tile>) -> (Point, Point) {
+        let last = grid.num_rows() - 1;
+        (
+            Point { row: 0, col: 1 },
+            Point {
+                row: last,
+                col: grid.num_cols() - 2,
+            },
+        )
+    }
+
+    #[test]
+    fn test_slopes() {
+        let grid = parse(EXAMPLE);
+        let (start, end) = endpoints(&grid);
+        let g = JunctionGraph::contract(&grid, start, end);
+        let (len, path) = g.longest_path().unwrap();
+        assert_eq!(len, 94);
+        let cells = g.cells_along(&path);
+        assert_eq!(cells.len(), 95);
+        assert_eq!(cells[0], start);
+        assert_eq!(cells[94], end);
+
+        let drawing = render_path(&grid, &cells);
+        assert_eq!(drawing.lines().next(), Some("#O#####################"));
+        assert_eq!(drawing.chars().filter(|&c| c == 'O').count(), 95);
+    }
+
+    #[test]
+    fn test_no_slopes() {
+        let mut grid = parse(EXAMPLE);
+        for row in &mut grid.data {
+            for t in row {
+                if let Tile::Slope(_) = t {
+                    *t = Tile::Open;
+                }
+            }
+        }
+        let (start, end) = endpoints(&grid);
+        let g = JunctionGraph::contract(&grid, start, end);
+        assert_eq!(g.longest_path().unwrap().0, 154);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let grid = parse("#.#\n###\n#.#\n");
+        let g = JunctionGraph::contract(&grid, Point { row: 0, col: 1 }, Point { row: 2, col: 1 });
+        assert_eq!(g.longest_path(), None);
+    }
+
+    #[test]
+    fn test_many_junctions() {
+        // A long corridor with a dead-end spur at every other cell, so
+        // there are more junctions than fit in a machine word or two.
+        let width = 303;
+        let row = |f: &dyn Fn(usize) -> bool| -> String {
+            (0..width).map(|c| if f(c) { '.' } else { '#' }).collect()
+        };
+        let text = [
+            row(&|c| c == 1),
+            row(&|c| (1..width - 1).contains(&c)),
+            row(&|c| (c % 2 == 0 && c < width - 3) || c == width - 2),
+            row(&|c| c == width - 2),
+        ]
+        .join("\n");
+        let grid = parse(&text);
+        let (start, end) = endpoints(&grid);
+        let g = JunctionGraph::contract(&grid, start, end);
+        assert!(g.junctions.len() > 128);
+        assert_eq!(g.longest_path().unwrap().0, width);
+    }
+}
@@ -0,0 +1,227 @@
+//! Shortest paths for movers that can't turn freely.
+//!
+//! Some puzzles have a cart or crucible that has to go straight for a while
+//! before it can turn, and can't go straight for too long. The search state is
+//! then not just a position but (position, direction, run length).
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{Dir, Grid, Point, DIRS};
+
+/// Where a mover is, which way it's headed, and how many steps it has taken
+/// in a straight line.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct MoveState {
+    pub pos: Point,
+    /// `None` only at the start, before the first step.
+    pub dir: Option<Dir>,
+    pub run: usize,
+}
+
+/// Rules for how a mover may steer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MoveRules {
+    /// Fewest straight steps before the mover may turn.
+    pub min_run: usize,
+    /// Most straight steps before the mover must turn.
+    pub max_run: usize,
+    pub allow_left: bool,
+    pub allow_right: bool,
+    pub allow_reverse: bool,
+    /// Fewest straight steps before the mover may stop at the goal.
+    pub min_run_to_stop: usize,
+}
+
+impl MoveRules {
+    /// The crucible from 2023 day 17 part 1: at most 3 steps straight, no reversing.
+    pub fn crucible() -> Self {
+        MoveRules {
+            min_run: 0,
+            max_run: 3,
+            allow_left: true,
+            allow_right: true,
+            allow_reverse: false,
+            min_run_to_stop: 0,
+        }
+    }
+
+    /// The ultra crucible from part 2: 4 to 10 steps before turning or stopping.
+    pub fn ultra_crucible() -> Self {
+        MoveRules {
+            min_run: 4,
+            max_run: 10,
+            min_run_to_stop: 4,
+            ..MoveRules::crucible()
+        }
+    }
+
+    /// Directions the mover may take next from `state`.
+    fn next_dirs(&self, state: &MoveState) -> impl Iterator<Item = Dir> + '_ {
+        let state = *state;
+        DIRS.into_iter().filter(move |&d| {
+            let Some(cur) = state.dir else {
+                return true;
+            };
+            if d == cur {
+                state.run < self.max_run
+            } else if state.run < self.min_run {
+                false
+            } else if d == cur.turn_left() {
+                self.allow_left
+            } else if d == cur.turn_right() {
+                self.allow_right
+            } else {
+                self.allow_reverse
+            }
+        })
+    }
+
+    fn can_stop(&self, state: &MoveState) -> bool {
+        state.run >= self.min_run_to_stop
+    }
+}
+
+/// Find a cheapest route from `start` to a cell where `goal` returns true,
+/// obeying `rules`. Entering a cell costs `cost(cell)`; `None` means the cell
+/// can't be entered. Returns the total cost and every state along the way.
+pub fn min_cost_path<T>(
+    grid: &Grid<T>,
+    rules: &MoveRules,
+    start: Point,
+    cost: impl Fn(&T) -> Option<u64>,
+    goal: impl Fn(Point) -> bool,
+) -> Option<(u64, Vec<MoveState>)> {
+    let start = MoveState {
+        pos: start,
+        dir: None,
+        run: 0,
+    };
+    let mut best = HashMap::from([(start, 0)]);
+    let mut came_from = HashMap::<MoveState, MoveState>::new();
+    let mut todo = BinaryHeap::from([Reverse((0, start))]);
+
+    while let Some(Reverse((total, state))) = todo.pop() {
+        if best[&state] < total {
+            continue;
+        }
+        if goal(state.pos) && rules.can_stop(&state) {
+            let mut path = vec![state];
+            let mut s = state;
+            while let Some(&prev) = came_from.get(&s) {
+                path.push(prev);
+                s = prev;
+            }
+            path.reverse();
+            return Some((total, path));
+        }
+        for d in rules.next_dirs(&state) {
+            let pos = state.pos + d;
+            let Some(step_cost) = grid.get(pos).and_then(&cost) else {
+                continue;
+            };
+            let next = MoveState {
+                pos,
+                dir: Some(d),
+                run: if state.dir == Some(d) {
+                    state.run + 1
+                } else {
+                    1
+                },
+            };
+            let next_total = total + step_cost;
+            if best.get(&next).is_none_or(|&b| next_total < b) {
+                best.insert(next, next_total);
+                came_from.insert(next, state);
+                todo.push(Reverse((next_total, next)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Grid<u64> {
+        Grid {
+            data: text
+                .lines()
+                .map(|line| line.bytes().map(|b| (b - b'0') as u64).collect())
+                .collect(),
+        }
+    }
+
+    fn solve(grid: &Grid<u64>, rules: &MoveRules) -> Option<(u64, Vec<MoveState>)> {
+        let end = Point {
+            row: grid.num_rows() - 1,
+            col: grid.num_cols() - 1,
+        };
+        min_cost_path(
+            grid,
+            rules,
+            Point { row: 0, col: 0 },
+            |&c| Some(c),
+            |p| p == end,
+        )
+    }
+
+    const EXAMPLE: &str = "\
+2413432311323
+3215453535623
+3255245654254
+3446585845452
+4546657867536
+1438598798454
+4457876987766
+3637877979653
+4654967986887
+4564679986453
+1224686865563
+2546548887735
+4322674655533
+";
+
+    #[test]
+    fn test_crucible() {
+        let grid = parse(EXAMPLE);
+        let (cost, path) = solve(&grid, &MoveRules::crucible()).unwrap();
+        assert_eq!(cost, 102);
+        assert_eq!(path[0].pos, Point { row: 0, col: 0 });
+        assert_eq!(path.iter().skip(1).map(|s| grid[s.pos]).sum::<u64>(), 102);
+        assert!(path.iter().all(|s| s.run <= 3));
+    }
+
+    #[test]
+    fn test_ultra_crucible() {
+        let grid = parse(EXAMPLE);
+        assert_eq!(solve(&grid, &MoveRules::ultra_crucible()).unwrap().0, 94);
+
+        let grid = parse("111111111111\n999999999991\n999999999991\n999999999991\n999999999991\n");
+        assert_eq!(solve(&grid, &MoveRules::ultra_crucible()).unwrap().0, 71);
+    }
+
+    #[test]
+    fn test_turn_rules() {
+        // With only right turns allowed, the route has to go right first, then down.
+        let grid = parse("11\n11\n");
+        let rules = MoveRules {
+            allow_left: false,
+            ..MoveRules::crucible()
+        };
+        let (cost, path) = solve(&grid, &rules).unwrap();
+        assert_eq!(cost, 2);
+        assert_eq!(path[1].dir, Some(Dir::Right));
+
+        // Walls make a cell impassable.
+        let grid = parse("19\n91\n");
+        let walls = |&c: &u64| if c == 9 { None } else { Some(c) };
+        let end = Point { row: 1, col: 1 };
+        let start = Point { row: 0, col: 0 };
+        assert_eq!(
+            min_cost_path(&grid, &MoveRules::crucible(), start, walls, |p| p == end),
+            None
+        );
+    }
+}
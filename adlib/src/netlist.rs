@@ -0,0 +1,644 @@
+//! Boolean circuits built from two-input gates, and a checker for
+//! ripple-carry adders.
+//!
+//! Wires are named by strings. A wire that no gate drives is an input of the
+//! circuit. Buses are numbered wires like `x00`, `x01`, ... with the least
+//! significant bit first.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Op {
+    And,
+    Or,
+    Xor,
+}
+
+impl Op {
+    pub fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            Op::And => a & b,
+            Op::Or => a | b,
+            Op::Xor => a ^ b,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Xor => "XOR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gate {
+    pub op: Op,
+    pub inputs: [String; 2],
+    pub output: String,
+}
+
+impl Gate {
+    pub fn new(a: &str, op: Op, b: &str, output: &str) -> Self {
+        Gate {
+            op,
+            inputs: [a.to_string(), b.to_string()],
+            output: output.to_string(),
+        }
+    }
+
+    fn reads(&self, wire: &str) -> bool {
+        self.inputs.iter().any(|w| w == wire)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetlistError {
+    /// More than one gate drives this wire.
+    MultipleDrivers(String),
+    /// This wire's value depends on itself.
+    Cycle(String),
+    /// No value was given for this input wire.
+    MissingInput(String),
+    /// The circuit doesn't look like an adder at this bit, and no swap of
+    /// gate outputs fixes it.
+    NotAnAdder(usize),
+    /// Tried to swap the output of a wire no gate drives.
+    NotDriven(String),
+    /// The adder has this many bits, too many to check with `u64` sums.
+    TooWide(usize),
+}
+
+impl Display for NetlistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NetlistError::MultipleDrivers(w) => {
+                write!(f, "wire {w} is driven by more than one gate")
+            }
+            NetlistError::Cycle(w) => write!(f, "wire {w} depends on itself"),
+            NetlistError::MissingInput(w) => write!(f, "no value for input wire {w}"),
+            NetlistError::NotAnAdder(i) => write!(f, "can't match bit {i} to a full adder"),
+            NetlistError::NotDriven(w) => write!(f, "wire {w} isn't the output of any gate"),
+            NetlistError::TooWide(n) => {
+                write!(
+                    f,
+                    "{n}-bit adder is too wide; at most 63 bits are supported"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetlistError {}
+
+/// The name of bit `i` of a bus.
+pub fn bus_wire(prefix: &str, i: usize) -> String {
+    format!("{prefix}{i:02}")
+}
+
+/// Read a bus out of a set of wire values. Stops at the first missing bit.
+pub fn read_bus(values: &HashMap<String, bool>, prefix: &str) -> u64 {
+    let mut n = 0;
+    for i in 0..64 {
+        match values.get(&bus_wire(prefix, i)) {
+            Some(&v) => n |= (v as u64) << i,
+            None => break,
+        }
+    }
+    n
+}
+
+/// Set the low `width` bits of a bus to `value`.
+pub fn write_bus(values: &mut HashMap<String, bool>, prefix: &str, width: usize, value: u64) {
+    for i in 0..width {
+        values.insert(bus_wire(prefix, i), (value >> i) & 1 == 1);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Netlist {
+    gates: Vec<Gate>,
+    /// Index of the gate driving each wire.
+    driver: HashMap<String, usize>,
+}
+
+/// Why one bit of an adder didn't match the template.
+enum Mismatch {
+    /// These two gate outputs look swapped.
+    Swap(String, String),
+    Stuck,
+}
+
+impl Netlist {
+    pub fn new(gates: Vec<Gate>) -> Result<Self, NetlistError> {
+        let mut driver = HashMap::new();
+        for (i, gate) in gates.iter().enumerate() {
+            if driver.insert(gate.output.clone(), i).is_some() {
+                return Err(NetlistError::MultipleDrivers(gate.output.clone()));
+            }
+        }
+        Ok(Netlist { gates, driver })
+    }
+
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+
+    /// The gate whose output is `wire`, if any.
+    pub fn driver(&self, wire: &str) -> Option<&Gate> {
+        self.driver.get(wire).map(|&i| &self.gates[i])
+    }
+
+    /// Wires that are read but not driven by any gate.
+    pub fn inputs(&self) -> BTreeSet<&str> {
+        self.gates
+            .iter()
+            .flat_map(|g| &g.inputs)
+            .filter(|w| !self.driver.contains_key(*w))
+            .map(|w| w.as_str())
+            .collect()
+    }
+
+    /// Number of bits in a bus: how many of `prefix00`, `prefix01`, ... exist.
+    pub fn bus_width(&self, prefix: &str) -> usize {
+        let inputs = self.inputs();
+        (0..)
+            .take_while(|&i| {
+                let w = bus_wire(prefix, i);
+                self.driver.contains_key(&w) || inputs.contains(w.as_str())
+            })
+            .count()
+    }
+
+    /// Gate indices in an order where every gate comes after the gates
+    /// driving its inputs.
+    pub fn topological_order(&self) -> Result<Vec<usize>, NetlistError> {
+        let mut pending = vec![0; self.gates.len()];
+        let mut readers = vec![vec![]; self.gates.len()];
+        for (i, gate) in self.gates.iter().enumerate() {
+            for w in &gate.inputs {
+                if let Some(&d) = self.driver.get(w) {
+                    pending[i] += 1;
+                    readers[d].push(i);
+                }
+            }
+        }
+        let mut order = (0..self.gates.len())
+            .filter(|&i| pending[i] == 0)
+            .collect::<Vec<usize>>();
+        let mut next = 0;
+        while let Some(&i) = order.get(next) {
+            next += 1;
+            for &r in &readers[i] {
+                pending[r] -= 1;
+                if pending[r] == 0 {
+                    order.push(r);
+                }
+            }
+        }
+        match (0..self.gates.len()).find(|&i| pending[i] != 0) {
+            Some(i) => Err(NetlistError::Cycle(self.gates[i].output.clone())),
+            None => Ok(order),
+        }
+    }
+
+    /// Compute every wire, given values for the inputs.
+    pub fn eval(
+        &self,
+        inputs: &HashMap<String, bool>,
+    ) -> Result<HashMap<String, bool>, NetlistError> {
+        let mut values = inputs.clone();
+        for i in self.topological_order()? {
+            let gate = &self.gates[i];
+            let [a, b] = gate
+                .inputs
+                .clone()
+                .map(|w| values.get(&w).copied().ok_or(NetlistError::MissingInput(w)));
+            let v = gate.op.apply(a?, b?);
+            values.insert(gate.output.clone(), v);
+        }
+        Ok(values)
+    }
+
+    /// Exchange the output wires of the gates driving `a` and `b`.
+    pub fn swap_outputs(&mut self, a: &str, b: &str) -> Result<(), NetlistError> {
+        let driver = |w: &str| {
+            self.driver
+                .get(w)
+                .copied()
+                .ok_or_else(|| NetlistError::NotDriven(w.to_string()))
+        };
+        let (ia, ib) = (driver(a)?, driver(b)?);
+        self.gates[ia].output = b.to_string();
+        self.gates[ib].output = a.to_string();
+        self.driver.insert(a.to_string(), ib);
+        self.driver.insert(b.to_string(), ia);
+        Ok(())
+    }
+
+    fn find(&self, op: Op, a: &str, b: &str) -> Option<&Gate> {
+        self.gates
+            .iter()
+            .find(|g| g.op == op && g.reads(a) && g.reads(b))
+    }
+
+    /// The other input of some `op` gate that reads `a`.
+    fn partner(&self, op: Op, a: &str) -> Option<&str> {
+        let g = self.gates.iter().find(|g| g.op == op && g.reads(a))?;
+        let [l, r] = &g.inputs;
+        Some(if l == a { r } else { l })
+    }
+
+    /// Match bit `i` against a full adder (a half adder for bit 0):
+    ///
+    /// ```text
+    /// s = x ^ y    z = s ^ carry_in
+    /// g = x & y    carry_out = g | (s & carry_in)
+    /// ```
+    ///
+    /// Returns the carry-out wire, or `None` if `carry_out` is false, in
+    /// which case the bit has no carry logic to check.
+    fn match_adder_bit(
+        &self,
+        [x, y, z]: [&str; 3],
+        i: usize,
+        carry_in: Option<&str>,
+        carry_out: bool,
+    ) -> Result<Option<String>, Mismatch> {
+        let (x, y, z) = (bus_wire(x, i), bus_wire(y, i), bus_wire(z, i));
+        let s = &self.find(Op::Xor, &x, &y).ok_or(Mismatch::Stuck)?.output;
+        let Some(c) = carry_in else {
+            if *s != z {
+                return Err(Mismatch::Swap(s.clone(), z));
+            }
+            if !carry_out {
+                return Ok(None);
+            }
+            let g = &self.find(Op::And, &x, &y).ok_or(Mismatch::Stuck)?.output;
+            return Ok(Some(g.clone()));
+        };
+
+        let Some(sum) = self.find(Op::Xor, s, c) else {
+            // One of the two inputs is wrong. Whichever one is still wired
+            // to the sum gate is right.
+            return Err(if let Some(o) = self.partner(Op::Xor, c) {
+                Mismatch::Swap(s.clone(), o.to_string())
+            } else if let Some(o) = self.partner(Op::Xor, s) {
+                Mismatch::Swap(c.to_string(), o.to_string())
+            } else {
+                Mismatch::Stuck
+            });
+        };
+        if sum.output != z {
+            return Err(Mismatch::Swap(sum.output.clone(), z));
+        }
+        if !carry_out {
+            return Ok(None);
+        }
+
+        let g = &self.find(Op::And, &x, &y).ok_or(Mismatch::Stuck)?.output;
+        let t = &self.find(Op::And, s, c).ok_or(Mismatch::Stuck)?.output;
+        match self.find(Op::Or, g, t) {
+            Some(carry) => Ok(Some(carry.output.clone())),
+            None => Err(if let Some(o) = self.partner(Op::Or, g) {
+                Mismatch::Swap(t.clone(), o.to_string())
+            } else if let Some(o) = self.partner(Op::Or, t) {
+                Mismatch::Swap(g.clone(), o.to_string())
+            } else {
+                Mismatch::Stuck
+            }),
+        }
+    }
+
+    /// Swap gate outputs until the circuit is a ripple-carry adder computing
+    /// `z = x + y`, where `x`, `y`, and `z` are bus prefixes. Returns the
+    /// swaps made, in order. The width comes from the `x` bus. If `z` is no
+    /// wider than `x`, the adder has no carry out of the top bit.
+    ///
+    /// Each bit is matched against the full-adder template in turn, and the
+    /// first wire that doesn't fit tells which outputs to swap. At the end,
+    /// the result is checked by simulation. On error the netlist is left
+    /// unchanged.
+    pub fn repair_adder(
+        &mut self,
+        x: &str,
+        y: &str,
+        z: &str,
+    ) -> Result<Vec<(String, String)>, NetlistError> {
+        let mut net = self.clone();
+        let swaps = net.repair_adder_in_place(x, y, z)?;
+        *self = net;
+        Ok(swaps)
+    }
+
+    fn repair_adder_in_place(
+        &mut self,
+        x: &str,
+        y: &str,
+        z: &str,
+    ) -> Result<Vec<(String, String)>, NetlistError> {
+        let width = self.bus_width(x);
+        if width >= 64 {
+            return Err(NetlistError::TooWide(width));
+        }
+        let top = bus_wire(z, width);
+        let carry_out = self.driver.contains_key(&top);
+        let mut swaps = vec![];
+        let mut carry = None;
+        for i in 0..width {
+            let need_carry = i + 1 < width || carry_out;
+            loop {
+                match self.match_adder_bit([x, y, z], i, carry.as_deref(), need_carry) {
+                    Ok(c) => {
+                        carry = c;
+                        break;
+                    }
+                    Err(Mismatch::Swap(a, b)) if swaps.len() < self.gates.len() => {
+                        self.swap_outputs(&a, &b)?;
+                        // The gate that drove the carry now drives the other wire.
+                        if carry.as_ref() == Some(&a) {
+                            carry = Some(b.clone());
+                        } else if carry.as_ref() == Some(&b) {
+                            carry = Some(a.clone());
+                        }
+                        swaps.push((a, b));
+                    }
+                    Err(_) => return Err(NetlistError::NotAnAdder(i)),
+                }
+            }
+        }
+        if let Some(c) = carry {
+            if c != top {
+                self.swap_outputs(&c, &top)?;
+                swaps.push((c, top));
+            }
+        }
+
+        if !self.adds(x, y, z, width)? {
+            return Err(NetlistError::NotAnAdder(width));
+        }
+        Ok(swaps)
+    }
+
+    /// Spot-check that the circuit adds, using each single bit, carries
+    /// rippling the full width, and some pseudorandom numbers.
+    fn adds(&self, x: &str, y: &str, z: &str, width: usize) -> Result<bool, NetlistError> {
+        let mask = (1u64 << width) - 1;
+        let mut cases = vec![(mask, 1), (mask, mask), (0, 0)];
+        for i in 0..width {
+            cases.extend([(1 << i, 0), (0, 1 << i), (1 << i, 1 << i)]);
+        }
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..32 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let a = seed & mask;
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            cases.push((a, seed & mask));
+        }
+
+        let carry_out = self.driver.contains_key(&bus_wire(z, width));
+        let mut values = HashMap::new();
+        for (a, b) in cases {
+            write_bus(&mut values, x, width, a);
+            write_bus(&mut values, y, width, b);
+            let sum = if carry_out { a + b } else { (a + b) & mask };
+            if read_bus(&self.eval(&values)?, z) != sum {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Graphviz source for the circuit. Gates driving any of the `faulty`
+    /// wires are drawn in red.
+    pub fn to_dot(&self, faulty: &HashSet<String>) -> String {
+        let mut out = "digraph netlist {\n    rankdir=LR;\n".to_string();
+        for w in self.inputs() {
+            writeln!(out, "    \"{w}\" [shape=box];").unwrap();
+        }
+        for gate in &self.gates {
+            let w = &gate.output;
+            let style = if faulty.contains(w) {
+                ", color=red, style=filled, fillcolor=mistyrose"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "    \"{w}\" [label=\"{w}\\n{}\"{style}];",
+                gate.op.name()
+            )
+            .unwrap();
+            for input in &gate.inputs {
+                writeln!(out, "    \"{input}\" -> \"{w}\";").unwrap();
+            }
+        }
+        out += "}\n";
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A correct ripple-carry adder with made-up names for internal wires.
+    fn adder(width: usize) -> Vec<Gate> {
+        let mut gates = vec![
+            Gate::new("x00", Op::Xor, "y00", "z00"),
+            Gate::new("y00", Op::And, "x00", "c00"),
+        ];
+        for i in 1..width {
+            let (x, y, z) = (bus_wire("x", i), bus_wire("y", i), bus_wire("z", i));
+            let s = format!("s{i:02}");
+            let g = format!("g{i:02}");
+            let t = format!("t{i:02}");
+            let c_in = format!("c{:02}", i - 1);
+            let c_out = if i == width - 1 {
+                bus_wire("z", width)
+            } else {
+                format!("c{i:02}")
+            };
+            gates.extend([
+                Gate::new(&x, Op::Xor, &y, &s),
+                Gate::new(&x, Op::And, &y, &g),
+                Gate::new(&c_in, Op::Xor, &s, &z),
+                Gate::new(&s, Op::And, &c_in, &t),
+                Gate::new(&g, Op::Or, &t, &c_out),
+            ]);
+        }
+        gates
+    }
+
+    #[test]
+    fn test_eval() {
+        let net = Netlist::new(adder(8)).unwrap();
+        assert_eq!(net.bus_width("x"), 8);
+        assert_eq!(net.bus_width("z"), 9);
+        let mut values = HashMap::new();
+        write_bus(&mut values, "x", 8, 200);
+        write_bus(&mut values, "y", 8, 99);
+        assert_eq!(read_bus(&net.eval(&values).unwrap(), "z"), 299);
+
+        values.remove("y03");
+        assert_eq!(
+            net.eval(&values),
+            Err(NetlistError::MissingInput("y03".to_string()))
+        );
+
+        let looped = Netlist::new(vec![
+            Gate::new("a", Op::And, "q", "p"),
+            Gate::new("p", Op::Or, "b", "q"),
+        ])
+        .unwrap();
+        assert!(matches!(
+            looped.topological_order(),
+            Err(NetlistError::Cycle(_))
+        ));
+
+        let doubled = vec![
+            Gate::new("a", Op::And, "b", "c"),
+            Gate::new("a", Op::Or, "b", "c"),
+        ];
+        assert_eq!(
+            Netlist::new(doubled).unwrap_err(),
+            NetlistError::MultipleDrivers("c".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repair_adder() {
+        let mut net = Netlist::new(adder(12)).unwrap();
+        assert_eq!(net.repair_adder("x", "y", "z").unwrap(), vec![]);
+
+        // Every kind of swap: sum with output, carry with output, inside
+        // one bit, and the final carry.
+        let broken = [
+            ("s03", "z03"),
+            ("c06", "z08"),
+            ("s09", "g09"),
+            ("z12", "z01"),
+        ];
+        let mut net = Netlist::new(adder(12)).unwrap();
+        for (a, b) in broken {
+            net.swap_outputs(a, b).unwrap();
+        }
+        let mut found = net
+            .clone()
+            .repair_adder("x", "y", "z")
+            .unwrap()
+            .into_iter()
+            .flat_map(|(a, b)| [a, b])
+            .collect::<Vec<String>>();
+        found.sort();
+        let mut expected = broken
+            .iter()
+            .flat_map(|&(a, b)| [a, b])
+            .collect::<Vec<&str>>();
+        expected.sort();
+        assert_eq!(found, expected);
+
+        let mut net = Netlist::new(vec![Gate::new("x00", Op::Or, "y00", "z00")]).unwrap();
+        assert_eq!(
+            net.repair_adder("x", "y", "z"),
+            Err(NetlistError::NotAnAdder(0))
+        );
+    }
+
+    #[test]
+    fn test_repair_without_carry_out() {
+        // Drop the gates that only compute the carry out of the top bit.
+        let gates = adder(6)
+            .into_iter()
+            .filter(|g| !["g05", "t05", "z06"].contains(&g.output.as_str()))
+            .collect();
+        let mut net = Netlist::new(gates).unwrap();
+        assert_eq!(net.bus_width("z"), 6);
+        assert_eq!(net.repair_adder("x", "y", "z").unwrap(), vec![]);
+
+        net.swap_outputs("z05", "s02").unwrap();
+        let swaps = net.repair_adder("x", "y", "z").unwrap();
+        assert_eq!(swaps.len(), 1);
+        let mut values = HashMap::new();
+        write_bus(&mut values, "x", 6, 63);
+        write_bus(&mut values, "y", 6, 2);
+        assert_eq!(read_bus(&net.eval(&values).unwrap(), "z"), 1);
+    }
+
+    #[test]
+    fn test_repair_errors() {
+        let mut net = Netlist::new(adder(3)).unwrap();
+        assert_eq!(
+            net.swap_outputs("z01", "x01"),
+            Err(NetlistError::NotDriven("x01".to_string()))
+        );
+
+        // The OR's other input is a primary input, so the suggested swap
+        // can't be made.
+        let mut net = Netlist::new(vec![
+            Gate::new("x00", Op::Xor, "y00", "z00"),
+            Gate::new("x00", Op::And, "y00", "c00"),
+            Gate::new("x01", Op::Xor, "y01", "s01"),
+            Gate::new("x01", Op::And, "y01", "g01"),
+            Gate::new("c00", Op::Xor, "s01", "z01"),
+            Gate::new("s01", Op::And, "c00", "t01"),
+            Gate::new("g01", Op::Or, "x00", "z02"),
+        ])
+        .unwrap();
+        assert_eq!(
+            net.repair_adder("x", "y", "z"),
+            Err(NetlistError::NotDriven("x00".to_string()))
+        );
+
+        // A fixable swap at bit 1, then a gate of the wrong kind at bit 2.
+        let mut gates = adder(4);
+        for g in &mut gates {
+            if g.output == "c02" {
+                g.op = Op::And;
+            }
+        }
+        let mut net = Netlist::new(gates).unwrap();
+        net.swap_outputs("z01", "s01").unwrap();
+        let before = net.gates.clone();
+        assert_eq!(
+            net.repair_adder("x", "y", "z"),
+            Err(NetlistError::NotAnAdder(2))
+        );
+        assert_eq!(net.gates, before);
+
+        let wide = (0..64)
+            .map(|i| {
+                Gate::new(
+                    &bus_wire("x", i),
+                    Op::Xor,
+                    &bus_wire("y", i),
+                    &bus_wire("z", i),
+                )
+            })
+            .collect();
+        assert_eq!(
+            Netlist::new(wide).unwrap().repair_adder("x", "y", "z"),
+            Err(NetlistError::TooWide(64))
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let net = Netlist::new(adder(2)).unwrap();
+        let dot = net.to_dot(&HashSet::from(["z01".to_string()]));
+        assert!(dot.starts_with("digraph netlist {\n"));
+        assert!(dot.contains("    \"x00\" [shape=box];\n"));
+        assert!(dot.contains(
+            "    \"z01\" [label=\"z01\\nXOR\", color=red, style=filled, fillcolor=mistyrose];\n"
+        ));
+        assert!(dot.contains("    \"c00\" [label=\"c00\\nAND\"];\n"));
+        assert!(dot.contains("    \"s01\" -> \"z01\";\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
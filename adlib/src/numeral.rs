@@ -0,0 +1,372 @@
+//! Positional numerals in any base, with any digit characters.
+//!
+//! Three kinds of system are supported, all written most significant digit
+//! first:
+//!
+//! - ordinary: digits worth `0..b`, like decimal or hex;
+//! - balanced: an odd number of digits centered on zero, like balanced
+//!   ternary (`-0+`) or SNAFU (`=-012`), so no sign is needed;
+//! - bijective: digits worth `1..=b`, like spreadsheet columns (`A`, ...,
+//!   `Z`, `AA`, ...), where zero is the empty string.
+//!
+//! Ordinary and bijective numerals use a leading `-` for negative values.
+//!
+//! Arithmetic works digit by digit, so values can be as large as you like.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Ordinary,
+    Balanced,
+    Bijective,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NumeralError {
+    /// The digit alphabet can't be used for this kind of system.
+    BadAlphabet(&'static str),
+    /// A character that isn't a digit, and its byte offset.
+    BadDigit(char, usize),
+    Empty,
+    Overflow,
+}
+
+impl fmt::Display for NumeralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumeralError::BadAlphabet(why) => write!(f, "bad digit alphabet: {why}"),
+            NumeralError::BadDigit(c, pos) => write!(f, "unexpected {c:?} at offset {pos}"),
+            NumeralError::Empty => write!(f, "empty numeral"),
+            NumeralError::Overflow => write!(f, "numeral too big"),
+        }
+    }
+}
+
+impl std::error::Error for NumeralError {}
+
+/// A number system: a base and the characters used for its digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Numeral {
+    digits: Vec<char>,
+    kind: Kind,
+}
+
+/// A value as digits, least significant first. For balanced systems the
+/// digits carry the sign and `negative` is always false.
+#[derive(Debug, PartialEq)]
+struct Value {
+    negative: bool,
+    digits: Vec<i64>,
+}
+
+impl Numeral {
+    fn new(digits: &str, kind: Kind) -> Result<Self, NumeralError> {
+        let digits = digits.chars().collect::<Vec<char>>();
+        if digits.len() < 2 {
+            return Err(NumeralError::BadAlphabet("too few digits"));
+        }
+        if (1..digits.len()).any(|i| digits[..i].contains(&digits[i])) {
+            return Err(NumeralError::BadAlphabet("repeated digit"));
+        }
+        if kind == Kind::Balanced && digits.len() % 2 == 0 {
+            return Err(NumeralError::BadAlphabet(
+                "balanced systems need an odd number of digits",
+            ));
+        }
+        if kind != Kind::Balanced && digits.contains(&'-') {
+            return Err(NumeralError::BadAlphabet("`-` is the minus sign"));
+        }
+        Ok(Numeral { digits, kind })
+    }
+
+    /// Digits worth 0, 1, 2, ... in order, like `"0123456789"`.
+    pub fn ordinary(digits: &str) -> Result<Self, NumeralError> {
+        Numeral::new(digits, Kind::Ordinary)
+    }
+
+    /// Digits from most negative to most positive, like `"=-012"`.
+    pub fn balanced(digits: &str) -> Result<Self, NumeralError> {
+        Numeral::new(digits, Kind::Balanced)
+    }
+
+    /// Digits worth 1, 2, 3, ... in order, like `"ABCDEFGHIJKLMNOPQRSTUVWXYZ"`.
+    pub fn bijective(digits: &str) -> Result<Self, NumeralError> {
+        Numeral::new(digits, Kind::Bijective)
+    }
+
+    pub fn base(&self) -> i64 {
+        self.digits.len() as i64
+    }
+
+    /// Value of the first digit in the alphabet.
+    fn low(&self) -> i64 {
+        match self.kind {
+            Kind::Ordinary => 0,
+            Kind::Balanced => -(self.base() - 1) / 2,
+            Kind::Bijective => 1,
+        }
+    }
+
+    fn read(&self, s: &str) -> Result<Value, NumeralError> {
+        let (negative, body, offset) = match s.strip_prefix('-') {
+            Some(rest) if self.kind != Kind::Balanced => (true, rest, 1),
+            _ => (false, s, 0),
+        };
+        if body.is_empty() && (negative || self.kind != Kind::Bijective) {
+            return Err(NumeralError::Empty);
+        }
+        let mut digits = body
+            .char_indices()
+            .map(|(i, c)| match self.digits.iter().position(|&d| d == c) {
+                Some(v) => Ok(v as i64 + self.low()),
+                None => Err(NumeralError::BadDigit(c, i + offset)),
+            })
+            .collect::<Result<Vec<i64>, _>>()?;
+        digits.reverse();
+        Ok(Value { negative, digits })
+    }
+
+    fn write(&self, value: &Value) -> String {
+        let mut s = String::new();
+        if value.negative {
+            s.push('-');
+        }
+        s.extend(
+            value
+                .digits
+                .iter()
+                .rev()
+                .map(|&d| self.digits[(d - self.low()) as usize]),
+        );
+        if s.is_empty() && self.kind != Kind::Bijective {
+            s.push(self.digits[-self.low() as usize]);
+        }
+        s
+    }
+
+    /// Propagate carries so every digit is in `low..low + base`. Returns the
+    /// carry left over at the top.
+    fn carry(&self, digits: &mut [i64], low: i64) -> i64 {
+        let b = self.base();
+        let mut carry = 0;
+        for d in digits.iter_mut() {
+            let sum = *d + carry;
+            *d = (sum - low).rem_euclid(b) + low;
+            carry = (sum - *d) / b;
+        }
+        carry
+    }
+
+    /// The canonical form of the number whose digits (least significant
+    /// first, any size, any sign) are `raw`.
+    fn normalize(&self, mut raw: Vec<i64>) -> Value {
+        let b = self.base();
+        let low = if self.kind == Kind::Balanced {
+            self.low()
+        } else {
+            0
+        };
+        let mut negative = false;
+        let mut carry = self.carry(&mut raw, low);
+        if self.kind != Kind::Balanced && carry < 0 {
+            // The number is negative. Work on its magnitude instead.
+            negative = true;
+            for d in &mut raw {
+                *d = -*d;
+            }
+            carry = -carry;
+            carry += self.carry(&mut raw, 0);
+        }
+        // In both cases the leftover carry shrinks toward zero.
+        while carry != 0 {
+            let d = (carry - low).rem_euclid(b) + low;
+            raw.push(d);
+            carry = (carry - d) / b;
+        }
+        while raw.last() == Some(&0) {
+            raw.pop();
+        }
+
+        if self.kind == Kind::Bijective {
+            // Replace each 0 with a b, borrowing 1 from the next digit up.
+            let mut borrow = 0;
+            for d in raw.iter_mut() {
+                *d -= borrow;
+                borrow = 0;
+                if *d <= 0 {
+                    *d += b;
+                    borrow = 1;
+                }
+            }
+            // The top digit was at least 1, so this can only zero it out.
+            if borrow == 1 {
+                raw.pop();
+            }
+        }
+        if raw.is_empty() {
+            negative = false;
+        }
+        Value {
+            negative,
+            digits: raw,
+        }
+    }
+
+    /// Digits, least significant first, with the sign folded in.
+    fn signed(&self, v: Value) -> Vec<i64> {
+        if v.negative {
+            v.digits.into_iter().map(|d| -d).collect()
+        } else {
+            v.digits
+        }
+    }
+
+    fn combine(&self, a: &str, b: &str, sign: i64) -> Result<String, NumeralError> {
+        let a = self.signed(self.read(a)?);
+        let b = self.signed(self.read(b)?);
+        let n = a.len().max(b.len());
+        let get = |v: &[i64], i: usize| v.get(i).copied().unwrap_or(0);
+        let sum = (0..n).map(|i| get(&a, i) + sign * get(&b, i)).collect();
+        Ok(self.write(&self.normalize(sum)))
+    }
+
+    /// Add two numerals.
+    pub fn add(&self, a: &str, b: &str) -> Result<String, NumeralError> {
+        self.combine(a, b, 1)
+    }
+
+    /// Subtract `b` from `a`.
+    pub fn sub(&self, a: &str, b: &str) -> Result<String, NumeralError> {
+        self.combine(a, b, -1)
+    }
+
+    /// Compare the values of two numerals.
+    pub fn cmp(&self, a: &str, b: &str) -> Result<Ordering, NumeralError> {
+        let a = self.signed(self.read(a)?);
+        let b = self.signed(self.read(b)?);
+        let n = a.len().max(b.len());
+        let get = |v: &[i64], i: usize| v.get(i).copied().unwrap_or(0);
+        let diff = self.normalize((0..n).map(|i| get(&a, i) - get(&b, i)).collect());
+        Ok(match diff.digits.last() {
+            None => Ordering::Equal,
+            Some(_) if diff.negative => Ordering::Less,
+            Some(&top) => top.cmp(&0),
+        })
+    }
+
+    /// Rewrite a numeral in its canonical form, without leading zeros.
+    pub fn canonical(&self, s: &str) -> Result<String, NumeralError> {
+        let v = self.read(s)?;
+        Ok(self.write(&self.normalize(self.signed(v))))
+    }
+
+    pub fn parse_i128(&self, s: &str) -> Result<i128, NumeralError> {
+        let v = self.read(s)?;
+        let sign = if v.negative { -1 } else { 1 };
+        v.digits.iter().rev().try_fold(0i128, |acc, &d| {
+            acc.checked_mul(self.base() as i128)
+                .and_then(|acc| acc.checked_add(sign * d as i128))
+                .ok_or(NumeralError::Overflow)
+        })
+    }
+
+    pub fn format_i128(&self, v: i128) -> String {
+        // Plain base-b digits of the magnitude, then let normalize sort it out.
+        let b = self.base() as u128;
+        let mut mag = v.unsigned_abs();
+        let mut digits = vec![];
+        while mag > 0 {
+            let d = (mag % b) as i64;
+            digits.push(if v < 0 { -d } else { d });
+            mag /= b;
+        }
+        self.write(&self.normalize(digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let systems = [
+            Numeral::ordinary("0123456789").unwrap(),
+            Numeral::ordinary("01").unwrap(),
+            Numeral::balanced("-0+").unwrap(),
+            Numeral::balanced("=-012").unwrap(),
+            Numeral::bijective("ABCDEFGHIJKLMNOPQRSTUVWXYZ").unwrap(),
+            Numeral::bijective("12").unwrap(),
+        ];
+        for sys in &systems {
+            let mut prev = sys.format_i128(-301);
+            for v in -300..=300 {
+                let s = sys.format_i128(v);
+                assert_eq!(sys.parse_i128(&s), Ok(v), "{s}");
+                assert_eq!(sys.canonical(&s).unwrap(), s);
+                assert_eq!(sys.sub(&s, &prev).unwrap(), sys.format_i128(1));
+                assert_eq!(sys.cmp(&prev, &s), Ok(Ordering::Less));
+                assert_eq!(sys.add(&s, &s).unwrap(), sys.format_i128(2 * v));
+                prev = s;
+            }
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let dec = Numeral::ordinary("0123456789").unwrap();
+        assert_eq!(dec.format_i128(-42), "-42");
+        assert_eq!(dec.canonical("007").unwrap(), "7");
+        assert_eq!(dec.canonical("-0").unwrap(), "0");
+
+        let snafu = Numeral::balanced("=-012").unwrap();
+        assert_eq!(snafu.format_i128(2022), "1=11-2");
+        assert_eq!(snafu.parse_i128("1121-1110-1=0"), Ok(314159265));
+
+        let cols = Numeral::bijective("ABCDEFGHIJKLMNOPQRSTUVWXYZ").unwrap();
+        assert_eq!(cols.format_i128(1), "A");
+        assert_eq!(cols.format_i128(26), "Z");
+        assert_eq!(cols.format_i128(27), "AA");
+        assert_eq!(cols.format_i128(16384), "XFD");
+        assert_eq!(cols.format_i128(0), "");
+        assert_eq!(cols.add("Z", "A").unwrap(), "AA");
+    }
+
+    #[test]
+    fn test_big() {
+        let dec = Numeral::ordinary("0123456789").unwrap();
+        let big = "9".repeat(60);
+        let one = "1".to_string() + &"0".repeat(60);
+        assert_eq!(dec.add(&big, "1").unwrap(), one);
+        assert_eq!(dec.sub("1", &one).unwrap(), format!("-{big}"));
+        assert_eq!(dec.cmp(&big, &one), Ok(Ordering::Less));
+        assert_eq!(dec.parse_i128(&big), Err(NumeralError::Overflow));
+
+        let snafu = Numeral::balanced("=-012").unwrap();
+        let huge = "2".repeat(80);
+        let twice = snafu.add(&huge, &huge).unwrap();
+        assert_eq!(snafu.sub(&twice, &huge).unwrap(), huge);
+        assert_eq!(snafu.cmp(&twice, &huge), Ok(Ordering::Greater));
+        assert_eq!(snafu.cmp(&snafu.sub("0", &huge).unwrap(), "="), Ok(Ordering::Less));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Numeral::balanced("0123").is_err());
+        assert!(Numeral::ordinary("0").is_err());
+        assert!(Numeral::bijective("1").is_err());
+        assert!(Numeral::ordinary("-0").is_err());
+        assert!(Numeral::ordinary("0120").is_err());
+
+        let dec = Numeral::ordinary("0123456789").unwrap();
+        assert_eq!(dec.parse_i128(""), Err(NumeralError::Empty));
+        assert_eq!(dec.parse_i128("-"), Err(NumeralError::Empty));
+        assert_eq!(dec.parse_i128("12x"), Err(NumeralError::BadDigit('x', 2)));
+        assert_eq!(dec.add("1", "-x"), Err(NumeralError::BadDigit('x', 1)));
+
+        let snafu = Numeral::balanced("=-012").unwrap();
+        assert_eq!(snafu.parse_i128("3"), Err(NumeralError::BadDigit('3', 0)));
+    }
+}
@@ -0,0 +1,300 @@
+//! A list with O(log n) insertion and removal at any index, and stable handles
+//! that can report their current index.
+//!
+//! It's an implicit treap: a binary tree kept in list order, where each node
+//! knows the size of its subtree, and random priorities keep the tree
+//! balanced. Nodes also point to their parents, so `index_of` can walk up from
+//! a handle to the root.
+
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    value: T,
+    priority: u64,
+    size: usize,
+    left: usize,
+    right: usize,
+    parent: usize,
+}
+
+/// A reference to one element of an `OrderList`. It stays valid as other
+/// elements move around, and even while its own element is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(usize);
+
+#[derive(Debug, Clone)]
+pub struct OrderList<T> {
+    nodes: Vec<Node<T>>,
+    root: usize,
+    seed: u64,
+}
+
+impl<T> Default for OrderList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> OrderList<T> {
+    pub fn new() -> Self {
+        OrderList {
+            nodes: vec![],
+            root: NIL,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    fn size(&self, t: usize) -> usize {
+        if t == NIL {
+            0
+        } else {
+            self.nodes[t].size
+        }
+    }
+
+    /// Recompute `t`'s size from its children, and make them point back to it.
+    fn update(&mut self, t: usize) {
+        let (l, r) = (self.nodes[t].left, self.nodes[t].right);
+        self.nodes[t].size = 1 + self.size(l) + self.size(r);
+        for c in [l, r] {
+            if c != NIL {
+                self.nodes[c].parent = t;
+            }
+        }
+    }
+
+    /// Split the tree `t` into its first `k` elements and the rest.
+    fn split(&mut self, t: usize, k: usize) -> (usize, usize) {
+        if t == NIL {
+            return (NIL, NIL);
+        }
+        let left = self.nodes[t].left;
+        let left_size = self.size(left);
+        if k <= left_size {
+            let (a, b) = self.split(left, k);
+            self.nodes[t].left = b;
+            self.update(t);
+            if a != NIL {
+                self.nodes[a].parent = NIL;
+            }
+            (a, t)
+        } else {
+            let right = self.nodes[t].right;
+            let (a, b) = self.split(right, k - left_size - 1);
+            self.nodes[t].right = a;
+            self.update(t);
+            if b != NIL {
+                self.nodes[b].parent = NIL;
+            }
+            (t, b)
+        }
+    }
+
+    /// Join two trees, all of `a` coming before all of `b`.
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        if self.nodes[a].priority > self.nodes[b].priority {
+            let right = self.nodes[a].right;
+            self.nodes[a].right = self.merge(right, b);
+            self.update(a);
+            a
+        } else {
+            let left = self.nodes[b].left;
+            self.nodes[b].left = self.merge(a, left);
+            self.update(b);
+            b
+        }
+    }
+
+    fn set_root(&mut self, t: usize) {
+        self.root = t;
+        if t != NIL {
+            self.nodes[t].parent = NIL;
+        }
+    }
+
+    /// Add `value` at the end.
+    pub fn push(&mut self, value: T) -> Handle {
+        self.insert(self.len(), value)
+    }
+
+    /// Add `value` so that it ends up at `index`.
+    pub fn insert(&mut self, index: usize, value: T) -> Handle {
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.nodes.push(Node {
+            value,
+            priority: self.seed,
+            size: 1,
+            left: NIL,
+            right: NIL,
+            parent: NIL,
+        });
+        let h = Handle(self.nodes.len() - 1);
+        self.reinsert(index, h);
+        h
+    }
+
+    /// Take the element at `index` out of the list. Its handle can be passed
+    /// to `reinsert` to put it back somewhere.
+    pub fn remove_at(&mut self, index: usize) -> Handle {
+        assert!(index < self.len(), "index {index} out of range");
+        let (a, b) = self.split(self.root, index);
+        let (m, c) = self.split(b, 1);
+        let root = self.merge(a, c);
+        self.set_root(root);
+        Handle(m)
+    }
+
+    /// Put a removed element back into the list at `index`.
+    pub fn reinsert(&mut self, index: usize, h: Handle) {
+        assert!(index <= self.len(), "index {index} out of range");
+        assert!(
+            self.nodes[h.0].size == 1 && self.nodes[h.0].parent == NIL && h.0 != self.root,
+            "element is already in the list"
+        );
+        let (a, b) = self.split(self.root, index);
+        let left = self.merge(a, h.0);
+        let root = self.merge(left, b);
+        self.set_root(root);
+    }
+
+    /// The handle of the element at `index`.
+    pub fn get(&self, index: usize) -> Handle {
+        assert!(index < self.len(), "index {index} out of range");
+        let mut t = self.root;
+        let mut k = index;
+        loop {
+            let left_size = self.size(self.nodes[t].left);
+            if k < left_size {
+                t = self.nodes[t].left;
+            } else if k == left_size {
+                return Handle(t);
+            } else {
+                k -= left_size + 1;
+                t = self.nodes[t].right;
+            }
+        }
+    }
+
+    /// Current index of the element `h`, which must be in the list.
+    pub fn index_of(&self, h: Handle) -> usize {
+        let mut t = h.0;
+        let mut index = self.size(self.nodes[t].left);
+        while self.nodes[t].parent != NIL {
+            let p = self.nodes[t].parent;
+            if self.nodes[p].right == t {
+                index += self.size(self.nodes[p].left) + 1;
+            }
+            t = p;
+        }
+        assert_eq!(t, self.root, "element is not in the list");
+        index
+    }
+
+    pub fn value(&self, h: Handle) -> &T {
+        &self.nodes[h.0].value
+    }
+
+    /// Values in list order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut stack = vec![];
+        let mut t = self.root;
+        std::iter::from_fn(move || {
+            while t != NIL {
+                stack.push(t);
+                t = self.nodes[t].left;
+            }
+            let n = stack.pop()?;
+            t = self.nodes[n].right;
+            Some(&self.nodes[n].value)
+        })
+    }
+}
+
+impl<T> FromIterator<T> for OrderList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = OrderList::new();
+        for value in iter {
+            list.push(value);
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_against_vec() {
+        let mut list = OrderList::new();
+        let mut model: Vec<(usize, Handle)> = vec![];
+        let mut removed = vec![];
+        let mut seed = 1u64;
+        let mut rand = move |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        for step in 0..5000 {
+            match rand(4) {
+                0 | 1 => {
+                    let i = rand(model.len() + 1);
+                    let h = list.insert(i, step);
+                    model.insert(i, (step, h));
+                }
+                2 if !model.is_empty() => {
+                    let i = rand(model.len());
+                    let h = list.remove_at(i);
+                    assert_eq!(model.remove(i).1, h);
+                    removed.push(h);
+                }
+                _ if !removed.is_empty() => {
+                    let h = removed.swap_remove(rand(removed.len()));
+                    let i = rand(model.len() + 1);
+                    list.reinsert(i, h);
+                    model.insert(i, (*list.value(h), h));
+                }
+                _ => {}
+            }
+            if step % 100 == 0 {
+                assert_eq!(
+                    list.iter().copied().collect::<Vec<_>>(),
+                    model.iter().map(|&(v, _)| v).collect::<Vec<_>>()
+                );
+                for (i, &(_, h)) in model.iter().enumerate() {
+                    assert_eq!(list.index_of(h), i);
+                    assert_eq!(list.get(i), h);
+                }
+            }
+        }
+        assert_eq!(list.len(), model.len());
+    }
+
+    #[test]
+    fn test_collect() {
+        let list = "hello".chars().collect::<OrderList<char>>();
+        assert_eq!(list.len(), 5);
+        assert_eq!(*list.value(list.get(1)), 'e');
+        assert_eq!(list.iter().collect::<String>(), "hello");
+        assert!(OrderList::<char>::new().is_empty());
+    }
+}
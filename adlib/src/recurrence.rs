@@ -0,0 +1,361 @@
+//! Linear recurrences over count vectors.
+//!
+//! Many puzzles track how many things are in each of a fixed set of states,
+//! where every thing in state `i` turns into some number of things in other
+//! states after one step. That's a matrix, and the state after `n` steps is
+//! that matrix to the `n`th power times the starting counts. Repeated squaring
+//! makes this logarithmic in `n`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Index, IndexMut};
+
+/// Numbers that matrices can hold.
+///
+/// The integer impls panic on overflow rather than wrap; use `Mod` when only
+/// the answer modulo something is wanted.
+pub trait Scalar: Copy + PartialEq + fmt::Debug {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn plus(self, other: Self) -> Self;
+    fn times(self, other: Self) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty),*) => {
+        $(
+            impl Scalar for $t {
+                fn zero() -> Self {
+                    0
+                }
+
+                fn one() -> Self {
+                    1
+                }
+
+                fn plus(self, other: Self) -> Self {
+                    self.checked_add(other).expect("overflow in linear recurrence")
+                }
+
+                fn times(self, other: Self) -> Self {
+                    self.checked_mul(other).expect("overflow in linear recurrence")
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(u64, u128);
+
+/// An integer modulo `M`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mod<const M: u64>(u64);
+
+impl<const M: u64> Mod<M> {
+    pub fn new(n: u64) -> Self {
+        Mod(n % M)
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl<const M: u64> fmt::Display for Mod<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<const M: u64> Scalar for Mod<M> {
+    fn zero() -> Self {
+        Mod(0)
+    }
+
+    fn one() -> Self {
+        Mod::new(1)
+    }
+
+    fn plus(self, other: Self) -> Self {
+        Mod(((self.0 as u128 + other.0 as u128) % M as u128) as u64)
+    }
+
+    fn times(self, other: Self) -> Self {
+        Mod((self.0 as u128 * other.0 as u128 % M as u128) as u64)
+    }
+}
+
+/// A square matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix<T> {
+    n: usize,
+    data: Vec<T>,
+}
+
+impl<T: Scalar> Matrix<T> {
+    pub fn zero(n: usize) -> Self {
+        Matrix {
+            n,
+            data: vec![T::zero(); n * n],
+        }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut m = Self::zero(n);
+        for i in 0..n {
+            m[(i, i)] = T::one();
+        }
+        m
+    }
+
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Self {
+        let n = rows.len();
+        assert!(
+            rows.iter().all(|row| row.len() == n),
+            "matrix must be square"
+        );
+        Matrix {
+            n,
+            data: rows.into_iter().flatten().collect(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        assert_eq!(self.n, other.n);
+        let n = self.n;
+        let mut out = Self::zero(n);
+        for i in 0..n {
+            for k in 0..n {
+                let a = self[(i, k)];
+                if a == T::zero() {
+                    continue;
+                }
+                for j in 0..n {
+                    let b = other[(k, j)];
+                    if b != T::zero() {
+                        out[(i, j)] = out[(i, j)].plus(a.times(b));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub fn pow(&self, mut k: u64) -> Self {
+        let mut result = Self::identity(self.n);
+        let mut base = self.clone();
+        while k > 0 {
+            if k & 1 == 1 {
+                result = result.mul(&base);
+            }
+            k >>= 1;
+            if k > 0 {
+                base = base.mul(&base);
+            }
+        }
+        result
+    }
+
+    /// Matrix times column vector.
+    pub fn apply(&self, v: &[T]) -> Vec<T> {
+        assert_eq!(v.len(), self.n);
+        (0..self.n)
+            .map(|i| (0..self.n).fold(T::zero(), |acc, j| acc.plus(self[(i, j)].times(v[j]))))
+            .collect()
+    }
+}
+
+impl<T> Index<(usize, usize)> for Matrix<T> {
+    type Output = T;
+
+    fn index(&self, (i, j): (usize, usize)) -> &T {
+        assert!(i < self.n && j < self.n);
+        &self.data[i * self.n + j]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Matrix<T> {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut T {
+        assert!(i < self.n && j < self.n);
+        &mut self.data[i * self.n + j]
+    }
+}
+
+/// The one-step rule of a count-vector recurrence, over every state reachable
+/// from some starting states.
+#[derive(Debug, Clone)]
+pub struct Transition<K, T> {
+    states: Vec<K>,
+    index: HashMap<K, usize>,
+    /// For each state, what one thing in that state becomes after a step.
+    edges: Vec<Vec<(usize, T)>>,
+}
+
+impl<K: Clone + Eq + Hash, T: Scalar> Transition<K, T> {
+    /// `step(k)` lists the states one thing in state `k` turns into after one
+    /// step, with how many of each. Listing a state twice adds them up.
+    pub fn new<I, F>(start: I, mut step: F) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(&K) -> Vec<(K, T)>,
+    {
+        let mut t = Transition {
+            states: vec![],
+            index: HashMap::new(),
+            edges: vec![],
+        };
+        for k in start {
+            t.intern(k);
+        }
+        let mut i = 0;
+        while i < t.states.len() {
+            let k = t.states[i].clone();
+            let out = step(&k)
+                .into_iter()
+                .map(|(k, w)| (t.intern(k), w))
+                .collect();
+            t.edges.push(out);
+            i += 1;
+        }
+        t
+    }
+
+    fn intern(&mut self, k: K) -> usize {
+        if let Some(&i) = self.index.get(&k) {
+            return i;
+        }
+        let i = self.states.len();
+        self.states.push(k.clone());
+        self.index.insert(k, i);
+        i
+    }
+
+    /// All states, in the order used by vectors and the matrix.
+    pub fn states(&self) -> &[K] {
+        &self.states
+    }
+
+    pub fn index_of(&self, k: &K) -> Option<usize> {
+        self.index.get(k).copied()
+    }
+
+    /// A count vector from `(state, count)` pairs. Panics if a state wasn't
+    /// reachable from the start states.
+    pub fn vector<I: IntoIterator<Item = (K, T)>>(&self, counts: I) -> Vec<T> {
+        let mut v = vec![T::zero(); self.states.len()];
+        for (k, n) in counts {
+            let i = self.index_of(&k).expect("state not in transition");
+            v[i] = v[i].plus(n);
+        }
+        v
+    }
+
+    pub fn matrix(&self) -> Matrix<T> {
+        let mut m = Matrix::<T>::zero(self.states.len());
+        for (i, out) in self.edges.iter().enumerate() {
+            for &(j, w) in out {
+                m[(j, i)] = m[(j, i)].plus(w);
+            }
+        }
+        m
+    }
+
+    /// Counts after a single step.
+    pub fn step(&self, v: &[T]) -> Vec<T> {
+        assert_eq!(v.len(), self.states.len());
+        let mut out = vec![T::zero(); v.len()];
+        for (i, &n) in v.iter().enumerate() {
+            if n != T::zero() {
+                for &(j, w) in &self.edges[i] {
+                    out[j] = out[j].plus(n.times(w));
+                }
+            }
+        }
+        out
+    }
+
+    /// Counts after `steps` steps.
+    ///
+    /// This squares the matrix unless stepping one at a time is cheaper, which
+    /// it is when there are lots of states and not many steps.
+    pub fn advance(&self, v: &[T], steps: u64) -> Vec<T> {
+        let n = self.states.len() as u128;
+        let edges = self.edges.iter().map(|out| out.len() as u128).sum::<u128>();
+        let squarings = 2 * (64 - steps.leading_zeros()) as u128;
+        if n * n * n * squarings < (n + edges) * steps as u128 {
+            self.matrix().pow(steps).apply(v)
+        } else {
+            let mut v = v.to_vec();
+            for _ in 0..steps {
+                v = self.step(&v);
+            }
+            v
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P: u64 = 1_000_000_007;
+
+    fn fib_matrix<T: Scalar>() -> Matrix<T> {
+        Matrix::from_rows(vec![vec![T::one(), T::one()], vec![T::one(), T::zero()]])
+    }
+
+    #[test]
+    fn test_fibonacci() {
+        let m = fib_matrix::<u128>();
+        assert_eq!(m.pow(0), Matrix::identity(2));
+        assert_eq!(m.pow(10)[(0, 1)], 55);
+        assert_eq!(m.pow(184)[(0, 1)], 127127879743834334146972278486287885163);
+
+        let m = fib_matrix::<Mod<P>>();
+        for n in 0..50 {
+            let exact = fib_matrix::<u128>().pow(n)[(0, 1)];
+            assert_eq!(m.pow(n)[(0, 1)], Mod::new((exact % P as u128) as u64));
+        }
+        assert_eq!(m.pow(1_000_000_000_000_000_000)[(0, 1)].value(), 209783453);
+    }
+
+    #[test]
+    fn test_transition() {
+        // A bacterium splits in two every step, but only once it's a step old.
+        let t = Transition::new(["new"], |&k| match k {
+            "new" => vec![("old", 1u64)],
+            _ => vec![("new", 1), ("old", 1)],
+        });
+        assert_eq!(t.states(), &["new", "old"]);
+        let v = t.vector([("new", 1)]);
+        assert_eq!(t.step(&v), vec![0, 1]);
+        assert_eq!(t.advance(&v, 10), vec![34, 55]);
+        assert_eq!(t.matrix().pow(10).apply(&v), vec![34, 55]);
+
+        // Stepping and squaring agree, whichever `advance` picks.
+        let t = Transition::new(0..20u64, |&k| {
+            vec![
+                ((k * 7 + 1) % 20, Mod::<P>::new(2)),
+                ((k + 3) % 20, Mod::new(k)),
+            ]
+        });
+        let v = t.vector((0..20).map(|k| (k, Mod::new(k * k))));
+        let mut slow = v.clone();
+        for steps in 0..40 {
+            assert_eq!(t.matrix().pow(steps).apply(&v), slow);
+            assert_eq!(t.advance(&v, steps), slow);
+            slow = t.step(&slow);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow")]
+    fn test_overflow() {
+        fib_matrix::<u64>().pow(100);
+    }
+}
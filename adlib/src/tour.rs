@@ -0,0 +1,255 @@
+//! Tours of a few valuable nodes in a larger graph, under a time budget.
+//!
+//! The idea is to throw away every node that isn't worth visiting, keeping
+//! only shortest-path distances between the ones that are. Then search over
+//! subsets of the remaining nodes with bitmasks.
+
+use std::collections::VecDeque;
+
+/// A graph reduced to its interesting nodes (those with nonzero value),
+/// plus a starting point.
+#[derive(Debug, Clone)]
+pub struct CompressedGraph {
+    /// Original id of each interesting node. Index into this list is the
+    /// compressed id, which is also the node's bit in subset masks.
+    pub nodes: Vec<usize>,
+    /// Value of each compressed node.
+    pub values: Vec<u64>,
+    /// `dist[i][j]` is the length of a shortest path between compressed nodes `i` and `j`.
+    pub dist: Vec<Vec<u64>>,
+    /// Length of a shortest path from the start node to each compressed node.
+    pub start_dist: Vec<u64>,
+}
+
+/// Most masks we are willing to allocate a table for.
+const MAX_NODES: usize = 24;
+
+impl CompressedGraph {
+    /// Compress an unweighted graph given as adjacency lists, using BFS from
+    /// each interesting node.
+    pub fn from_unweighted(neighbors: &[Vec<usize>], values: &[u64], start: usize) -> Self {
+        let bfs = |source: usize| {
+            let mut dist = vec![u64::MAX; neighbors.len()];
+            dist[source] = 0;
+            let mut todo = VecDeque::from([source]);
+            while let Some(p) = todo.pop_front() {
+                for &n in &neighbors[p] {
+                    if dist[n] == u64::MAX {
+                        dist[n] = dist[p] + 1;
+                        todo.push_back(n);
+                    }
+                }
+            }
+            dist
+        };
+
+        let nodes = interesting(values);
+        let full: Vec<Vec<u64>> = nodes.iter().map(|&v| bfs(v)).collect();
+        let start_full = bfs(start);
+        CompressedGraph {
+            values: nodes.iter().map(|&v| values[v]).collect(),
+            dist: full.iter().map(|row| nodes.iter().map(|&v| row[v]).collect()).collect(),
+            start_dist: nodes.iter().map(|&v| start_full[v]).collect(),
+            nodes,
+        }
+    }
+
+    /// Compress a weighted undirected graph given as a list of `(a, b, length)`
+    /// edges, using Floyd–Warshall.
+    pub fn from_weighted(edges: &[(usize, usize, u64)], values: &[u64], start: usize) -> Self {
+        let n = values.len();
+        let mut dist = vec![vec![u64::MAX; n]; n];
+        for (i, row) in dist.iter_mut().enumerate() {
+            row[i] = 0;
+        }
+        for &(a, b, len) in edges {
+            dist[a][b] = dist[a][b].min(len);
+            dist[b][a] = dist[b][a].min(len);
+        }
+        for k in 0..n {
+            for i in 0..n {
+                if dist[i][k] == u64::MAX {
+                    continue;
+                }
+                for j in 0..n {
+                    let via = dist[i][k].saturating_add(dist[k][j]);
+                    if via < dist[i][j] {
+                        dist[i][j] = via;
+                    }
+                }
+            }
+        }
+
+        let nodes = interesting(values);
+        CompressedGraph {
+            values: nodes.iter().map(|&v| values[v]).collect(),
+            dist: nodes.iter().map(|&a| nodes.iter().map(|&b| dist[a][b]).collect()).collect(),
+            start_dist: nodes.iter().map(|&v| dist[start][v]).collect(),
+            nodes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// For every subset of compressed nodes (as a bitmask), the best score a
+    /// single agent can get by activating only nodes in that subset.
+    ///
+    /// Walking one step takes 1 unit of time and so does activating a node.
+    /// A node whose activation finishes at time `t` scores `value * (time_budget - t)`.
+    /// The result is monotone: a superset never scores less than its subsets.
+    ///
+    /// Returns `None` if there are more than 24 compressed nodes, since the
+    /// table would be too big.
+    pub fn best_by_subset(&self, time_budget: u64) -> Option<Vec<u64>> {
+        let n = self.len();
+        if n > MAX_NODES {
+            return None;
+        }
+        let mut best = vec![0; 1 << n];
+
+        // Each entry on the stack is (current node, time used, mask, score).
+        let mut stack = vec![];
+        for i in 0..n {
+            let t = self.start_dist[i].saturating_add(1);
+            if t <= time_budget {
+                stack.push((i, t, 1u32 << i, self.values[i] * (time_budget - t)));
+            }
+        }
+        while let Some((at, time, mask, score)) = stack.pop() {
+            let slot = &mut best[mask as usize];
+            *slot = (*slot).max(score);
+            for next in 0..n {
+                if mask & (1 << next) != 0 {
+                    continue;
+                }
+                let t = time.saturating_add(self.dist[at][next]).saturating_add(1);
+                if t <= time_budget {
+                    let gain = self.values[next] * (time_budget - t);
+                    stack.push((next, t, mask | 1 << next, score + gain));
+                }
+            }
+        }
+
+        // Make the table monotone, so best[mask] covers every subset of mask.
+        for bit in 0..n {
+            for mask in 0..best.len() {
+                if mask & (1 << bit) != 0 {
+                    best[mask] = best[mask].max(best[mask ^ (1 << bit)]);
+                }
+            }
+        }
+        Some(best)
+    }
+
+    /// Best total score for `agents` agents working at once, each starting at
+    /// the start node with the same time budget. No node is activated twice,
+    /// so the agents split the nodes into disjoint subsets.
+    ///
+    /// Returns `None` if there are too many nodes; see `best_by_subset`.
+    pub fn best_with_agents(&self, time_budget: u64, agents: usize) -> Option<u64> {
+        let best = self.best_by_subset(time_budget)?;
+        let full = best.len() - 1;
+        Some(match agents {
+            0 => 0,
+            1 => best[full],
+            // Since `best` is monotone, it's enough to try complementary pairs.
+            2 => (0..=full).map(|mask| best[mask] + best[full ^ mask]).max().unwrap(),
+            _ => {
+                let mut combined = best.clone();
+                for _ in 1..agents {
+                    combined = (0..=full)
+                        .map(|mask| {
+                            // Try every split of `mask` into one agent's share and the rest.
+                            let mut result = combined[mask];
+                            let mut sub = mask;
+                            while sub != 0 {
+                                result = result.max(best[sub] + combined[mask ^ sub]);
+                                sub = (sub - 1) & mask;
+                            }
+                            result
+                        })
+                        .collect();
+                }
+                combined[full]
+            }
+        })
+    }
+}
+
+fn interesting(values: &[u64]) -> Vec<usize> {
+    (0..values.len()).filter(|&v| values[v] != 0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example from 2022 day 16: AA BB CC DD EE FF GG HH II JJ.
+    fn example() -> CompressedGraph {
+        let neighbors = vec![
+            vec![3, 8, 1],
+            vec![2, 0],
+            vec![3, 1],
+            vec![2, 0, 4],
+            vec![5, 3],
+            vec![4, 6],
+            vec![5, 7],
+            vec![6],
+            vec![0, 9],
+            vec![8],
+        ];
+        let values = [0, 13, 2, 20, 3, 0, 0, 22, 0, 21];
+        CompressedGraph::from_unweighted(&neighbors, &values, 0)
+    }
+
+    #[test]
+    fn test_compress() {
+        let g = example();
+        assert_eq!(g.nodes, vec![1, 2, 3, 4, 7, 9]);
+        assert_eq!(g.start_dist, vec![1, 2, 1, 2, 5, 2]);
+        assert_eq!(g.dist[4][5], 7); // HH to JJ
+    }
+
+    #[test]
+    fn test_agents() {
+        let g = example();
+        assert_eq!(g.best_with_agents(30, 1), Some(1651));
+        assert_eq!(g.best_with_agents(26, 2), Some(1707));
+        assert!(g.best_with_agents(26, 3).unwrap() >= 1707);
+        assert_eq!(g.best_with_agents(26, 0), Some(0));
+    }
+
+    #[test]
+    fn test_too_many_nodes() {
+        // A path of 25 nodes, every one worth visiting.
+        let mut neighbors = vec![vec![]; 25];
+        for i in 1..25 {
+            neighbors[i - 1].push(i);
+            neighbors[i].push(i - 1);
+        }
+        let g = CompressedGraph::from_unweighted(&neighbors, &[1; 25], 0);
+        assert_eq!(g.len(), 25);
+        assert_eq!(g.best_by_subset(30), None);
+        assert_eq!(g.best_with_agents(30, 2), None);
+    }
+
+    #[test]
+    fn test_weighted_matches_unweighted() {
+        let g = example();
+        let edges: Vec<(usize, usize, u64)> =
+            [(0, 3), (0, 8), (0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6), (6, 7), (8, 9)]
+                .into_iter()
+                .map(|(a, b)| (a, b, 1))
+                .collect();
+        let values = [0, 13, 2, 20, 3, 0, 0, 22, 0, 21];
+        let w = CompressedGraph::from_weighted(&edges, &values, 0);
+        assert_eq!(w.dist, g.dist);
+        assert_eq!(w.start_dist, g.start_dist);
+    }
+}
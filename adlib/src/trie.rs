@@ -0,0 +1,368 @@
+//! Tries and Aho–Corasick automata over small alphabets, and what they're
+//! usually for in puzzles: chopping a string into pieces from a fixed set.
+//!
+//! Symbols are `usize` values less than the alphabet size, so callers map
+//! their characters to `0..alphabet` first.
+
+const NIL: usize = usize::MAX;
+
+/// A set of words, stored as a tree of shared prefixes. Each word gets an id,
+/// its index in `words()`.
+#[derive(Debug, Clone)]
+pub struct Trie {
+    alphabet: usize,
+    /// `next[node * alphabet + symbol]`, or `NIL`. Node 0 is the root.
+    next: Vec<usize>,
+    word_at: Vec<Option<usize>>,
+    words: Vec<Vec<usize>>,
+}
+
+impl Trie {
+    pub fn new(alphabet: usize) -> Self {
+        assert!(alphabet > 0, "empty alphabet");
+        Trie {
+            alphabet,
+            next: vec![NIL; alphabet],
+            word_at: vec![None],
+            words: vec![],
+        }
+    }
+
+    pub fn alphabet(&self) -> usize {
+        self.alphabet
+    }
+
+    fn child(&self, node: usize, symbol: usize) -> usize {
+        assert!(symbol < self.alphabet, "symbol {symbol} not in alphabet");
+        self.next[node * self.alphabet + symbol]
+    }
+
+    /// Add a word and return its id. Adding a word twice returns the same id.
+    pub fn insert(&mut self, word: &[usize]) -> usize {
+        assert!(!word.is_empty(), "empty word");
+        let mut node = 0;
+        for &c in word {
+            let mut next = self.child(node, c);
+            if next == NIL {
+                next = self.word_at.len();
+                self.next[node * self.alphabet + c] = next;
+                self.next.extend(std::iter::repeat_n(NIL, self.alphabet));
+                self.word_at.push(None);
+            }
+            node = next;
+        }
+        *self.word_at[node].get_or_insert_with(|| {
+            self.words.push(word.to_vec());
+            self.words.len() - 1
+        })
+    }
+
+    pub fn words(&self) -> &[Vec<usize>] {
+        &self.words
+    }
+
+    pub fn find(&self, word: &[usize]) -> Option<usize> {
+        let mut node = 0;
+        for &c in word {
+            node = self.child(node, c);
+            if node == NIL {
+                return None;
+            }
+        }
+        self.word_at[node]
+    }
+
+    /// Ids of the words that are prefixes of `text`, shortest first.
+    pub fn prefixes_of<'a>(&'a self, text: &'a [usize]) -> impl Iterator<Item = usize> + 'a {
+        let mut node = 0;
+        text.iter()
+            .map_while(move |&c| {
+                node = self.child(node, c);
+                (node != NIL).then(|| self.word_at[node])
+            })
+            .flatten()
+    }
+
+    pub fn automaton(self) -> AhoCorasick {
+        AhoCorasick::new(self)
+    }
+}
+
+/// A trie plus, for every node and symbol, where to go next when scanning
+/// text, so that all occurrences of all words are found in one pass.
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    trie: Trie,
+    /// Complete transition table, same layout as `Trie::next`.
+    goto: Vec<usize>,
+    /// The longest proper suffix of each node that's a word, or `NIL`.
+    dict: Vec<usize>,
+}
+
+impl AhoCorasick {
+    pub fn new(trie: Trie) -> Self {
+        let a = trie.alphabet;
+        let n = trie.word_at.len();
+        let mut goto = vec![0; n * a];
+        let mut fail = vec![0; n];
+        let mut dict = vec![NIL; n];
+        let mut queue = std::collections::VecDeque::from([0]);
+        while let Some(u) = queue.pop_front() {
+            for c in 0..a {
+                let v = trie.next[u * a + c];
+                if v == NIL {
+                    goto[u * a + c] = if u == 0 { 0 } else { goto[fail[u] * a + c] };
+                } else {
+                    let f = if u == 0 { 0 } else { goto[fail[u] * a + c] };
+                    fail[v] = f;
+                    dict[v] = if trie.word_at[f].is_some() {
+                        f
+                    } else {
+                        dict[f]
+                    };
+                    goto[u * a + c] = v;
+                    queue.push_back(v);
+                }
+            }
+        }
+        AhoCorasick { trie, goto, dict }
+    }
+
+    pub fn trie(&self) -> &Trie {
+        &self.trie
+    }
+
+    /// Every occurrence of every word in `text`, as `(end, id)` pairs where
+    /// the word is `text[end - len..end]`. Ordered by `end`, and longest word
+    /// first for each `end`.
+    pub fn matches<'a>(&'a self, text: &'a [usize]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut node = 0;
+        let mut pos = 0;
+        let mut pending = NIL;
+        std::iter::from_fn(move || loop {
+            if pending != NIL {
+                let id = self.trie.word_at[pending].unwrap();
+                pending = self.dict[pending];
+                return Some((pos, id));
+            }
+            let &c = text.get(pos)?;
+            assert!(c < self.trie.alphabet, "symbol {c} not in alphabet");
+            node = self.goto[node * self.trie.alphabet + c];
+            pos += 1;
+            pending = if self.trie.word_at[node].is_some() {
+                node
+            } else {
+                self.dict[node]
+            };
+        })
+    }
+
+    /// All the ways to write `text` as a sequence of words.
+    pub fn segment(&self, text: &[usize]) -> Segmentation {
+        let mut pieces = vec![vec![]; text.len() + 1];
+        let mut ways = vec![0; text.len() + 1];
+        ways[0] = 1;
+        for (end, id) in self.matches(text) {
+            let start = end - self.trie.words[id].len();
+            if ways[start] != 0 {
+                pieces[end].push((start, id));
+                ways[end] += ways[start];
+            }
+        }
+        Segmentation { pieces, ways }
+    }
+}
+
+/// The result of `AhoCorasick::segment`: for each prefix of the text, the
+/// words that can end it.
+#[derive(Debug, Clone)]
+pub struct Segmentation {
+    /// `pieces[end]` lists `(start, id)` for each word `text[start..end]`
+    /// where `text[..start]` can itself be segmented.
+    pieces: Vec<Vec<(usize, usize)>>,
+    ways: Vec<u64>,
+}
+
+impl Segmentation {
+    pub fn is_possible(&self) -> bool {
+        self.count() != 0
+    }
+
+    pub fn count(&self) -> u64 {
+        self.ways[self.ways.len() - 1]
+    }
+
+    /// A segmentation using as few words as possible, as a list of word ids.
+    pub fn fewest(&self) -> Option<Vec<usize>> {
+        let n = self.ways.len() - 1;
+        let mut best: Vec<Option<(usize, usize)>> = vec![None; n + 1];
+        let mut len = vec![usize::MAX; n + 1];
+        len[0] = 0;
+        for end in 1..=n {
+            for &(start, id) in &self.pieces[end] {
+                if len[start] + 1 < len[end] {
+                    len[end] = len[start] + 1;
+                    best[end] = Some((start, id));
+                }
+            }
+        }
+        let mut words = vec![];
+        let mut end = n;
+        while end > 0 {
+            let (start, id) = best[end]?;
+            words.push(id);
+            end = start;
+        }
+        words.reverse();
+        Some(words)
+    }
+
+    /// Up to `limit` segmentations, each a list of word ids.
+    pub fn all(&self, limit: usize) -> Vec<Vec<usize>> {
+        fn walk(
+            s: &Segmentation,
+            end: usize,
+            suffix: &mut Vec<usize>,
+            out: &mut Vec<Vec<usize>>,
+            limit: usize,
+        ) {
+            if end == 0 {
+                out.push(suffix.iter().rev().copied().collect());
+                return;
+            }
+            for &(start, id) in &s.pieces[end] {
+                if out.len() == limit {
+                    return;
+                }
+                suffix.push(id);
+                walk(s, start, suffix, out, limit);
+                suffix.pop();
+            }
+        }
+
+        let mut out = vec![];
+        if limit > 0 {
+            walk(self, self.ways.len() - 1, &mut vec![], &mut out, limit);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols(s: &str) -> Vec<usize> {
+        s.bytes().map(|b| (b - b'a') as usize).collect()
+    }
+
+    fn automaton(words: &[&str]) -> AhoCorasick {
+        let mut trie = Trie::new(26);
+        for w in words {
+            trie.insert(&symbols(w));
+        }
+        trie.automaton()
+    }
+
+    fn spell(ac: &AhoCorasick, ids: &[usize]) -> Vec<String> {
+        ids.iter()
+            .map(|&id| {
+                ac.trie().words()[id]
+                    .iter()
+                    .map(|&c| (b'a' + c as u8) as char)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_trie() {
+        let mut trie = Trie::new(26);
+        assert_eq!(trie.insert(&symbols("he")), 0);
+        assert_eq!(trie.insert(&symbols("hers")), 1);
+        assert_eq!(trie.insert(&symbols("he")), 0);
+        assert_eq!(trie.find(&symbols("hers")), Some(1));
+        assert_eq!(trie.find(&symbols("her")), None);
+        let text = symbols("hersheys");
+        assert_eq!(trie.prefixes_of(&text).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(trie.prefixes_of(&text[1..]).count(), 0);
+    }
+
+    #[test]
+    fn test_matches() {
+        let ac = automaton(&["he", "she", "his", "hers"]);
+        let text = symbols("ushers");
+        let found: Vec<(usize, Vec<String>)> = ac
+            .matches(&text)
+            .map(|(end, id)| (end, spell(&ac, &[id])))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (4, vec!["she".to_string()]),
+                (4, vec!["he".to_string()]),
+                (6, vec!["hers".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segment() {
+        let ac = automaton(&["r", "wr", "b", "g", "bwu", "rb", "gb", "br"]);
+        let s = ac.segment(&symbols("gbbr"));
+        assert_eq!(s.count(), 4);
+        assert_eq!(spell(&ac, &s.fewest().unwrap()), vec!["gb", "br"]);
+        let mut all: Vec<Vec<String>> = s.all(10).iter().map(|ids| spell(&ac, ids)).collect();
+        all.sort();
+        assert_eq!(
+            all,
+            vec![
+                vec!["g", "b", "b", "r"],
+                vec!["g", "b", "br"],
+                vec!["gb", "b", "r"],
+                vec!["gb", "br"],
+            ]
+        );
+        assert_eq!(s.all(3).len(), 3);
+
+        let s = ac.segment(&symbols("ubwu"));
+        assert!(!s.is_possible());
+        assert_eq!(s.fewest(), None);
+        assert!(s.all(10).is_empty());
+
+        assert_eq!(ac.segment(&[]).all(10), vec![vec![]]);
+    }
+
+    #[test]
+    fn test_against_trie_walk() {
+        // Counting with the automaton agrees with walking the trie from every
+        // start position.
+        let mut seed = 7u64;
+        let mut rand = move |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        for _ in 0..50 {
+            let mut trie = Trie::new(3);
+            for _ in 0..rand(8) + 1 {
+                let word: Vec<usize> = (0..rand(4) + 1).map(|_| rand(3)).collect();
+                trie.insert(&word);
+            }
+            let text: Vec<usize> = (0..rand(30)).map(|_| rand(3)).collect();
+
+            let mut ways = vec![0u64; text.len() + 1];
+            ways[0] = 1;
+            for start in 0..text.len() {
+                for id in trie.prefixes_of(&text[start..]) {
+                    ways[start + trie.words()[id].len()] += ways[start];
+                }
+            }
+
+            let s = trie.automaton().segment(&text);
+            assert_eq!(s.count(), ways[text.len()]);
+            assert_eq!(s.all(usize::MAX).len() as u64, s.count());
+        }
+    }
+}